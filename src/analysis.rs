@@ -0,0 +1,322 @@
+// exhaustiveness and reachability of match arms, following Maranget's
+// "Warnings for pattern matching": an arm is reachable when it is useful
+// against the arms above it, and a match is exhaustive when `_` is not
+use crate::ast::{Expr, ExprKind, LType, Pattern, SEnum, UOp};
+
+// which arms can never run and the values no arm covers
+#[derive(Debug, Default, PartialEq)]
+pub struct MatchReport {
+    // indices of arms covered by the unguarded arms before them
+    pub unreachable: Vec<usize>,
+    // one pattern per top level constructor that is not covered
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    // index of the variant in its enum
+    Variant(usize),
+    Bool(bool),
+    // any other literal, by its text
+    Literal(String),
+}
+
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+// arms are the patterns with whether they have a guard, the patterns must
+// already type check against typ
+pub fn check_match<'a>(
+    typ: &LType,
+    arms: &[(&Pattern, bool)],
+    enums: impl Fn(&str) -> Option<&'a SEnum>,
+) -> MatchReport {
+    let cx = Cx { enums: &enums };
+    let types = [typ.clone()];
+    let mut report = MatchReport::default();
+    let mut rows: Vec<Vec<Pat>> = Vec::new();
+    for (i, (pattern, guarded)) in arms.iter().enumerate() {
+        let row = vec![cx.lower(pattern, typ)];
+        if cx.useful(&rows, &row, &types).is_none() {
+            report.unreachable.push(i);
+        }
+        // a guard may fail, so it covers nothing
+        if !guarded {
+            rows.push(row);
+        }
+    }
+
+    let heads: Vec<Pat> = match cx.ctors(typ) {
+        Some(ctors) => ctors
+            .into_iter()
+            .map(|(c, fields)| Pat::Ctor(c, vec![Pat::Wild; fields.len()]))
+            .collect(),
+        None => vec![Pat::Wild],
+    };
+    for head in heads {
+        if let Some(witness) = cx.useful(&rows, &[head], &types) {
+            report.missing.push(cx.show(&witness[0], typ));
+        }
+    }
+    report
+}
+
+struct Cx<'e, 'a> {
+    enums: &'e dyn Fn(&str) -> Option<&'a SEnum>,
+}
+
+impl<'a> Cx<'_, 'a> {
+    fn lookup(&self, typ: &LType) -> Option<&'a SEnum> {
+        match typ {
+            LType::Named(ident) => (self.enums)(ident),
+            _ => None,
+        }
+    }
+
+    // every constructor of typ with its field types, None when there are
+    // too many to list
+    fn ctors(&self, typ: &LType) -> Option<Vec<(Ctor, Vec<LType>)>> {
+        if *typ == LType::Bool {
+            return Some(vec![
+                (Ctor::Bool(false), Vec::new()),
+                (Ctor::Bool(true), Vec::new()),
+            ]);
+        }
+        let e = self.lookup(typ)?;
+        Some(
+            e.variants
+                .iter()
+                .enumerate()
+                .map(|(i, (_, payload))| (Ctor::Variant(i), payload.clone()))
+                .collect(),
+        )
+    }
+
+    fn fields(&self, ctor: &Ctor, typ: &LType) -> Vec<LType> {
+        match (ctor, self.lookup(typ)) {
+            (Ctor::Variant(i), Some(e)) => e.variants[*i].1.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn lower(&self, pattern: &Pattern, typ: &LType) -> Pat {
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding(_) => Pat::Wild,
+            Pattern::Literal(Expr {
+                kind: ExprKind::BoolLiteral(b),
+                ..
+            }) => Pat::Ctor(Ctor::Bool(*b), Vec::new()),
+            Pattern::Literal(e) => Pat::Ctor(Ctor::Literal(literal_text(e)), Vec::new()),
+            Pattern::Variant(v) => {
+                let Some(e) = self.lookup(typ) else {
+                    return Pat::Wild;
+                };
+                let Some(i) = e.variants.iter().position(|(n, _)| *n == v.variant) else {
+                    return Pat::Wild;
+                };
+                let fields = v
+                    .fields
+                    .iter()
+                    .zip(&e.variants[i].1)
+                    .map(|(p, t)| self.lower(p, t))
+                    .collect();
+                Pat::Ctor(Ctor::Variant(i), fields)
+            }
+        }
+    }
+
+    // a value matched by q and by no row, as one pattern per column
+    fn useful(&self, rows: &[Vec<Pat>], q: &[Pat], types: &[LType]) -> Option<Vec<Pat>> {
+        let Some((head, rest)) = q.split_first() else {
+            return rows.is_empty().then(Vec::new);
+        };
+        let typ = &types[0];
+        match head {
+            Pat::Ctor(c, args) => {
+                let fields = self.fields(c, typ);
+                let q: Vec<Pat> = args.iter().chain(rest).cloned().collect();
+                let witness = self.useful_ctor(rows, c, &fields, &q, &types[1..])?;
+                Some(Self::rebuild(c, fields.len(), witness))
+            }
+            Pat::Wild => {
+                let used: Vec<&Ctor> = rows
+                    .iter()
+                    .filter_map(|r| match &r[0] {
+                        Pat::Ctor(c, _) => Some(c),
+                        Pat::Wild => None,
+                    })
+                    .collect();
+                let ctors = self.ctors(typ);
+                // with every constructor in use, _ is useful when one of
+                // them is
+                if let Some(all) = &ctors
+                    && all.iter().all(|(c, _)| used.contains(&c))
+                {
+                    return all.iter().find_map(|(c, fields)| {
+                        let q: Vec<Pat> = std::iter::repeat_n(Pat::Wild, fields.len())
+                            .chain(rest.iter().cloned())
+                            .collect();
+                        let witness = self.useful_ctor(rows, c, fields, &q, &types[1..])?;
+                        Some(Self::rebuild(c, fields.len(), witness))
+                    });
+                }
+                let default: Vec<Vec<Pat>> = rows
+                    .iter()
+                    .filter(|r| matches!(r[0], Pat::Wild))
+                    .map(|r| r[1..].to_vec())
+                    .collect();
+                let mut witness = self.useful(&default, rest, &types[1..])?;
+                // name a constructor nothing matched when there is one
+                let head = ctors
+                    .filter(|_| !used.is_empty())
+                    .and_then(|all| all.into_iter().find(|(c, _)| !used.contains(&c)))
+                    .map_or(Pat::Wild, |(c, fields)| {
+                        Pat::Ctor(c, vec![Pat::Wild; fields.len()])
+                    });
+                witness.insert(0, head);
+                Some(witness)
+            }
+        }
+    }
+
+    // useful on the rows that start with c, with its fields in front
+    fn useful_ctor(
+        &self,
+        rows: &[Vec<Pat>],
+        c: &Ctor,
+        fields: &[LType],
+        q: &[Pat],
+        rest: &[LType],
+    ) -> Option<Vec<Pat>> {
+        let specialized: Vec<Vec<Pat>> = rows
+            .iter()
+            .filter_map(|r| match &r[0] {
+                Pat::Ctor(c2, args) if c2 == c => {
+                    Some(args.iter().chain(&r[1..]).cloned().collect())
+                }
+                Pat::Ctor(..) => None,
+                Pat::Wild => Some(
+                    std::iter::repeat_n(Pat::Wild, fields.len())
+                        .chain(r[1..].iter().cloned())
+                        .collect(),
+                ),
+            })
+            .collect();
+        let types: Vec<LType> = fields.iter().chain(rest).cloned().collect();
+        self.useful(&specialized, q, &types)
+    }
+
+    // folds the first arity columns of a witness back under c
+    fn rebuild(c: &Ctor, arity: usize, mut witness: Vec<Pat>) -> Vec<Pat> {
+        let rest = witness.split_off(arity);
+        let mut out = vec![Pat::Ctor(c.clone(), witness)];
+        out.extend(rest);
+        out
+    }
+
+    fn show(&self, pat: &Pat, typ: &LType) -> String {
+        match pat {
+            Pat::Wild => "_".to_string(),
+            Pat::Ctor(Ctor::Bool(b), _) => b.to_string(),
+            Pat::Ctor(Ctor::Literal(s), _) => s.clone(),
+            Pat::Ctor(Ctor::Variant(i), args) => {
+                let Some(e) = self.lookup(typ) else {
+                    return "_".to_string();
+                };
+                let (variant, payload) = &e.variants[*i];
+                let name = format!("{}::{}", e.ident, variant);
+                if args.is_empty() {
+                    return name;
+                }
+                let args: Vec<String> = args
+                    .iter()
+                    .zip(payload)
+                    .map(|(p, t)| self.show(p, t))
+                    .collect();
+                format!("{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+// literals are compared by how they print
+fn literal_text(e: &Expr) -> String {
+    match &e.kind {
+        ExprKind::Unary(u) if matches!(u.op, UOp::Neg) => format!("-{}", literal_text(&u.x)),
+        // 1 and 1u8 are the same value
+        ExprKind::Intermediate(i, _) => i.to_string(),
+        ExprKind::FloatIntermediate(x, _) => format!("{:?}", x),
+        ExprKind::StringLiteral(s) => format!("{:?}", s),
+        ExprKind::CharLiteral(c) => format!("{:?}", c),
+        _ => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MatchReport, check_match};
+    use crate::ast::{INT, LType, Pattern, SEnum};
+
+    fn report(typ: LType, arms: &[&str]) -> MatchReport {
+        let shape: SEnum = "enum Shape { Circle(float), Rect(bool, Shape), Empty }"
+            .parse()
+            .unwrap();
+        let patterns: Vec<(Pattern, bool)> = arms
+            .iter()
+            .map(|a| match a.strip_suffix(" if") {
+                Some(p) => (p.parse().unwrap(), true),
+                None => (a.parse().unwrap(), false),
+            })
+            .collect();
+        let arms: Vec<(&Pattern, bool)> = patterns.iter().map(|(p, g)| (p, *g)).collect();
+        check_match(&typ, &arms, |ident| (ident == "Shape").then_some(&shape))
+    }
+
+    #[test]
+    fn exhaustive_enums() {
+        let shape = LType::Named("Shape".to_string());
+        let r = report(
+            shape.clone(),
+            &["Shape::Circle(_)", "Shape::Rect(true, s)", "Shape::Empty"],
+        );
+        assert!(r.unreachable.is_empty());
+        assert_eq!(r.missing, vec!["Shape::Rect(false, _)"]);
+
+        let r = report(shape.clone(), &["Shape::Circle(1.5)", "Shape::Empty if"]);
+        assert_eq!(
+            r.missing,
+            vec!["Shape::Circle(_)", "Shape::Rect(_, _)", "Shape::Empty"]
+        );
+
+        let r = report(
+            shape,
+            &[
+                "Shape::Rect(_, Shape::Empty)",
+                "Shape::Rect(b, _)",
+                "x",
+                "Shape::Empty",
+            ],
+        );
+        assert!(r.missing.is_empty());
+        assert_eq!(r.unreachable, vec![3]);
+    }
+
+    #[test]
+    fn exhaustive_literals() {
+        let r = report(INT, &["1", "-1", "1"]);
+        assert_eq!(r.unreachable, vec![2]);
+        assert_eq!(r.missing, vec!["_"]);
+
+        let r = report(LType::Bool, &["true", "false", "_"]);
+        assert_eq!(
+            r,
+            MatchReport {
+                unreachable: vec![2],
+                missing: Vec::new(),
+            }
+        );
+    }
+}
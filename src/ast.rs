@@ -1,59 +1,411 @@
+use crate::source::Span;
 use crate::util::TPrint;
 use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum LType {
-    Int,
-    Float,
+    Bool,
+    // int is i64
+    Int(IntType),
+    // float and real are f64
+    Float(FloatType),
+    Char,
+    String,
+    // a struct or enum by name
+    Named(String),
+    // int[8] holds 8 ints
+    Array(Box<LType>, usize),
+    // fn(int, int) -> bool, a named function or a closure
+    Fn(Vec<LType>, Box<LType>),
 }
 
-#[derive(Debug)]
-pub enum Statement {
+pub const INT: LType = LType::Int(IntType::I64);
+pub const FLOAT: LType = LType::Float(FloatType::F64);
+
+impl LType {
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, LType::Int(_) | LType::Float(_))
+    }
+}
+
+// natural is u64
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntType {
+    pub fn bits(&self) -> u32 {
+        match self {
+            IntType::I8 | IntType::U8 => 8,
+            IntType::I16 | IntType::U16 => 16,
+            IntType::I32 | IntType::U32 => 32,
+            IntType::I64 | IntType::U64 => 64,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            IntType::I8 | IntType::I16 | IntType::I32 | IntType::I64
+        )
+    }
+
+    pub fn min(&self) -> i128 {
+        if self.is_signed() {
+            -(1 << (self.bits() - 1))
+        } else {
+            0
+        }
+    }
+
+    pub fn max(&self) -> i128 {
+        if self.is_signed() {
+            (1 << (self.bits() - 1)) - 1
+        } else {
+            (1 << self.bits()) - 1
+        }
+    }
+
+    pub fn contains(&self, x: i128) -> bool {
+        (self.min()..=self.max()).contains(&x)
+    }
+
+    // keeps the low bits as two's complement, 300 becomes 44 as u8
+    pub fn wrap(&self, x: i128) -> i128 {
+        let low = x & ((1 << self.bits()) - 1);
+        if low > self.max() {
+            low - (1 << self.bits())
+        } else {
+            low
+        }
+    }
+
+    // clamps to the range, NaN becomes 0
+    pub fn saturate(&self, x: f64) -> i128 {
+        if x.is_nan() {
+            0
+        } else {
+            (x as i128).clamp(self.min(), self.max())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatType {
+    F32,
+    F64,
+}
+
+impl FloatType {
+    // rounds to the nearest value of the type
+    pub fn round(&self, x: f64) -> f64 {
+        match self {
+            FloatType::F32 => x as f32 as f64,
+            FloatType::F64 => x,
+        }
+    }
+}
+
+impl IntType {
+    // the name as a literal suffix
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            IntType::I8 => "i8",
+            IntType::I16 => "i16",
+            IntType::I32 => "i32",
+            IntType::I64 => "i64",
+            IntType::U8 => "u8",
+            IntType::U16 => "u16",
+            IntType::U32 => "u32",
+            IntType::U64 => "u64",
+        }
+    }
+}
+
+impl FloatType {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            FloatType::F32 => "f32",
+            FloatType::F64 => "f64",
+        }
+    }
+}
+
+// i64 and f64 go by their everyday names
+impl fmt::Display for IntType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntType::I64 => write!(f, "int"),
+            t => write!(f, "{}", t.suffix()),
+        }
+    }
+}
+
+impl fmt::Display for FloatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FloatType::F64 => write!(f, "float"),
+            t => write!(f, "{}", t.suffix()),
+        }
+    }
+}
+
+// a statement and the source it was parsed from
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementKind {
     If(SIf),
     Declare(SDeclare),
     Assign(SAssign),
     While(SWhile),
-    Call(SCall),
+    For(SFor),
+    Loop(SLoop),
+    DoWhile(SDoWhile),
+    // the label of the loop to leave, the innermost when None
+    Break(Option<String>),
+    Continue(Option<String>),
+    // an expression evaluated for its effect, like a call
+    Expr(Box<Expr>),
     Function(SFunction),
+    Struct(SStruct),
+    Enum(SEnum),
+    Match(SMatch),
     Block(Vec<Statement>),
     Return(Box<Expr>),
+    Import(SImport),
+}
+
+// import "math.ro"; or import math;
+#[derive(Debug, Clone)]
+pub struct SImport {
+    // namespace of the module's functions
+    pub module: String,
+    pub path: String,
+    // resolution happens after parsing, so errors need a location
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SIf {
     pub guard: Box<Expr>,
     pub t: Vec<Statement>,
+    // tried in order after guard
+    pub elifs: Vec<SElseIf>,
     pub f: Option<Vec<Statement>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct SElseIf {
+    pub guard: Box<Expr>,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Debug, Clone)]
 pub struct SDeclare {
+    pub doc: Option<String>,
     pub typ: LType,
     pub ident: String,
     pub assign: Option<Box<Expr>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SFunction {
+    pub doc: Option<String>,
     pub ret: LType,
     pub ident: String,
+    // type parameters, fn max<T>(T a, T b) -> T is only checked and run
+    // through its instances
+    pub generics: Vec<String>,
     pub params: Vec<(LType, String)>,
     pub body: Vec<Statement>,
+    // where the name is, errors in an instance point back to it
+    pub span: Span,
 }
 
-#[derive(Debug)]
-pub struct SAssign {
+// struct Point { int x; float y; }
+#[derive(Debug, Clone)]
+pub struct SStruct {
+    pub doc: Option<String>,
+    pub ident: String,
+    pub fields: Vec<(LType, String)>,
+    // where the name is
+    pub span: Span,
+}
+
+// enum Shape { Circle(float), Rect(float, float), Empty }
+#[derive(Debug, Clone)]
+pub struct SEnum {
+    pub doc: Option<String>,
+    pub ident: String,
+    // each variant with the types of its payload
+    pub variants: Vec<(String, Vec<LType>)>,
+    // where the name is
+    pub span: Span,
+}
+
+// a user defined type, structs and enums share one namespace
+#[derive(Debug, Clone, Copy)]
+pub enum TypeDef<'a> {
+    Struct(&'a SStruct),
+    Enum(&'a SEnum),
+}
+
+// match x { pattern [if guard] => { ... } }
+#[derive(Debug, Clone)]
+pub struct SMatch {
+    pub scrutinee: Box<Expr>,
+    pub arms: Vec<Arm<Vec<Statement>>>,
+}
+
+// a statement match runs a block, a match expression yields a value
+#[derive(Debug, Clone)]
+pub struct Arm<B> {
+    pub pattern: Pattern,
+    // where the pattern is
+    pub span: Span,
+    pub guard: Option<Box<Expr>>,
+    pub body: B,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    // _
+    Wildcard,
+    // binds the whole value
+    Binding(String),
+    // an int, float, char, string or bool literal
+    Literal(Expr),
+    Variant(VariantPattern),
+}
+
+// Shape::Rect(w, _), the path is split into enum and variant
+#[derive(Debug, Clone)]
+pub struct VariantPattern {
     pub ident: String,
+    pub variant: String,
+    pub fields: Vec<Pattern>,
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Binding(ident) => write!(f, "{}", ident),
+            Pattern::Literal(e) => write!(f, "{}", e),
+            Pattern::Variant(v) => {
+                write!(f, "{}::{}", v.ident, v.variant)?;
+                if !v.fields.is_empty() {
+                    let fields: Vec<String> = v.fields.iter().map(|p| p.to_string()).collect();
+                    write!(f, "({})", fields.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SAssign {
+    pub target: Place,
+    // set for compound assignments such as +=
+    pub op: Option<Op>,
     pub value: Box<Expr>,
+    // x++ or x--, whose 1 takes the type of x even when it is a float
+    pub step: bool,
+}
+
+// what an assignment writes to, a variable or a part of one like p.x
+#[derive(Debug, Clone)]
+pub struct Place {
+    pub ident: String,
+    pub path: Vec<Access>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Access {
+    Field(String),
+    Index(Expr),
 }
 
-#[derive(Debug)]
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ident)?;
+        for a in &self.path {
+            match a {
+                Access::Field(field) => write!(f, ".{}", field)?,
+                // the index is shown as a child
+                Access::Index(_) => write!(f, "[]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct SWhile {
+    pub label: Option<String>,
     pub cond: Box<Expr>,
     pub body: Vec<Statement>,
 }
 
-#[derive(Debug)]
+// for (init; cond; step), a missing cond loops forever
+#[derive(Debug, Clone)]
+pub struct SFor {
+    pub label: Option<String>,
+    pub init: Option<Box<Statement>>,
+    pub cond: Option<Box<Expr>>,
+    pub step: Option<Box<Statement>>,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SLoop {
+    pub label: Option<String>,
+    pub body: Vec<Statement>,
+}
+
+// the body runs once before cond is first tested
+#[derive(Debug, Clone)]
+pub struct SDoWhile {
+    pub label: Option<String>,
+    pub body: Vec<Statement>,
+    pub cond: Box<Expr>,
+}
+
+// "outer: " for a labeled loop
+struct LabelPrefix<'a>(&'a Option<String>);
+
+impl fmt::Display for LabelPrefix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(label) => write!(f, "{}: ", label),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct SCall {
     pub ident: String,
     pub params: Vec<Expr>,
@@ -62,37 +414,98 @@ pub struct SCall {
 impl fmt::Display for LType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LType::Float => write!(f, "float"),
-            LType::Int => write!(f, "int"),
+            LType::Bool => write!(f, "bool"),
+            LType::Float(t) => write!(f, "{}", t),
+            LType::Int(t) => write!(f, "{}", t),
+            LType::Char => write!(f, "char"),
+            LType::String => write!(f, "string"),
+            LType::Named(ident) => write!(f, "{}", ident),
+            LType::Array(elem, len) => write!(f, "{}[{}]", elem, len),
+            LType::Fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(|t| t.to_string()).collect();
+                write!(f, "fn({}) -> {}", params.join(", "), ret)
+            }
         }
     }
 }
 
 impl fmt::Display for Statement {
     fn fmt(&self, ft: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Statement::If(sif) => {
-                if let Some(_) = sif.f {
+        match &self.kind {
+            StatementKind::If(sif) => {
+                if sif.f.is_some() {
                     write!(ft, "if/else")
                 } else {
                     write!(ft, "if")
                 }
             }
-            Statement::Assign(s) => write!(ft, "assign<{}>", s.ident),
-            Statement::Block(_) => write!(ft, ""),
-            Statement::Declare(declare) => {
+            StatementKind::Assign(s) => {
+                if let Some(op) = &s.op {
+                    write!(ft, "assign<{} {}=>", s.target, op)
+                } else {
+                    write!(ft, "assign<{}>", s.target)
+                }
+            }
+            StatementKind::Block(_) => write!(ft, ""),
+            StatementKind::Declare(declare) => {
                 write!(ft, "declare<{} {}>", declare.typ, declare.ident)
             }
-            Statement::Call(c) => {
-                write!(ft, "call<{}()>", c.ident)
+            StatementKind::Expr(e) => write!(ft, "{}", e),
+            StatementKind::Function(f) => {
+                let params: Vec<String> = f
+                    .params
+                    .iter()
+                    .map(|(t, p)| format!("{} {}", t, p))
+                    .collect();
+                let generics = if f.generics.is_empty() {
+                    String::new()
+                } else {
+                    format!("<{}>", f.generics.join(", "))
+                };
+                write!(
+                    ft,
+                    "fn <{}{}({}) -> {}>",
+                    f.ident,
+                    generics,
+                    params.join(", "),
+                    f.ret
+                )
+            }
+            StatementKind::Struct(s) => {
+                let fields: Vec<String> = s
+                    .fields
+                    .iter()
+                    .map(|(t, f)| format!("{} {}", t, f))
+                    .collect();
+                write!(ft, "struct <{} {{ {} }}>", s.ident, fields.join(", "))
             }
-            Statement::Function(f) => {
-                write!(ft, "fn <{}(todo) -> todo>", f.ident)
+            StatementKind::Enum(e) => {
+                let variants: Vec<String> = e
+                    .variants
+                    .iter()
+                    .map(|(v, payload)| {
+                        if payload.is_empty() {
+                            return v.clone();
+                        }
+                        let types: Vec<String> = payload.iter().map(|t| t.to_string()).collect();
+                        format!("{}({})", v, types.join(", "))
+                    })
+                    .collect();
+                write!(ft, "enum <{} {{ {} }}>", e.ident, variants.join(", "))
             }
-            Statement::Return(_) => {
+            StatementKind::Match(_) => write!(ft, "match"),
+            StatementKind::Return(_) => {
                 write!(ft, "return")
             }
-            Statement::While(_) => write!(ft, "while"),
+            StatementKind::Import(i) => write!(ft, "import<{}>", i.module),
+            StatementKind::While(w) => write!(ft, "{}while", LabelPrefix(&w.label)),
+            StatementKind::For(f) => write!(ft, "{}for", LabelPrefix(&f.label)),
+            StatementKind::Loop(l) => write!(ft, "{}loop", LabelPrefix(&l.label)),
+            StatementKind::DoWhile(d) => write!(ft, "{}do/while", LabelPrefix(&d.label)),
+            StatementKind::Break(None) => write!(ft, "break"),
+            StatementKind::Break(Some(label)) => write!(ft, "break {}", label),
+            StatementKind::Continue(None) => write!(ft, "continue"),
+            StatementKind::Continue(Some(label)) => write!(ft, "continue {}", label),
         }
     }
 }
@@ -103,8 +516,8 @@ impl TPrint for Statement {
     }
 
     fn children<'a>(&'a self) -> Box<dyn Iterator<Item = &'a dyn TPrint> + 'a> {
-        match self {
-            Statement::Declare(declare) => {
+        match &self.kind {
+            StatementKind::Declare(declare) => {
                 if let Some(expr) = &declare.assign {
                     let ptr: &'a dyn TPrint = expr.as_ref();
                     Box::new([ptr].into_iter())
@@ -112,18 +525,23 @@ impl TPrint for Statement {
                     Box::new(std::iter::empty())
                 }
             }
-            Statement::Block(stmts) => {
+            StatementKind::Block(stmts) => {
                 let itr = stmts.iter().map(|s| s as &dyn TPrint);
                 Box::new(itr)
             }
-            Statement::Assign(assign) => {
+            StatementKind::Assign(assign) => {
+                let indices = assign.target.path.iter().filter_map(|a| match a {
+                    Access::Index(e) => Some(e as &dyn TPrint),
+                    Access::Field(_) => None,
+                });
                 let ptr: &'a dyn TPrint = assign.value.as_ref();
-                Box::new([ptr].into_iter())
+                Box::new(indices.chain([ptr]))
             }
-            Statement::If(sif) => {
+            StatementKind::If(sif) => {
                 let pg: &'a dyn TPrint = sif.guard.as_ref();
                 let pt = sif.t.iter().map(|s| s as &dyn TPrint);
-                let imm = [pg].into_iter().chain(pt);
+                let pe = sif.elifs.iter().map(|e| e as &dyn TPrint);
+                let imm = [pg].into_iter().chain(pt).chain(pe);
                 if let Some(ff) = &sif.f {
                     let pf = ff.iter().map(|s| s as &dyn TPrint);
                     Box::new(imm.chain(pf))
@@ -131,21 +549,42 @@ impl TPrint for Statement {
                     Box::new(imm)
                 }
             }
-            Statement::Call(c) => {
-                let itr = c.params.iter().map(|s| s as &dyn TPrint);
-                Box::new(itr)
-            }
-            Statement::Function(f) => {
+            // shown as the bare expression
+            StatementKind::Expr(e) => e.children(),
+            StatementKind::Function(f) => {
                 let itr = f.body.iter().map(|s| s as &dyn TPrint);
                 Box::new(itr)
             }
-            Statement::While(w) => {
+            StatementKind::While(w) => {
                 let cond: &'a dyn TPrint = w.cond.as_ref();
                 let stmts = w.body.iter().map(|s| s as &dyn TPrint);
 
                 Box::new([cond].into_iter().chain(stmts))
             }
-            Statement::Return(expr) => {
+            StatementKind::For(f) => {
+                let init = f.init.iter().map(|s| s.as_ref() as &dyn TPrint);
+                let cond = f.cond.iter().map(|e| e.as_ref() as &dyn TPrint);
+                let step = f.step.iter().map(|s| s.as_ref() as &dyn TPrint);
+                let stmts = f.body.iter().map(|s| s as &dyn TPrint);
+                Box::new(init.chain(cond).chain(step).chain(stmts))
+            }
+            StatementKind::Loop(l) => Box::new(l.body.iter().map(|s| s as &dyn TPrint)),
+            StatementKind::DoWhile(d) => {
+                let stmts = d.body.iter().map(|s| s as &dyn TPrint);
+                let cond: &'a dyn TPrint = d.cond.as_ref();
+                Box::new(stmts.chain([cond]))
+            }
+            StatementKind::Match(m) => {
+                let scrutinee: &'a dyn TPrint = m.scrutinee.as_ref();
+                let arms = m.arms.iter().map(|a| a as &dyn TPrint);
+                Box::new([scrutinee].into_iter().chain(arms))
+            }
+            StatementKind::Break(_)
+            | StatementKind::Continue(_)
+            | StatementKind::Import(_)
+            | StatementKind::Struct(_)
+            | StatementKind::Enum(_) => Box::new(std::iter::empty()),
+            StatementKind::Return(expr) => {
                 let expr: &'a dyn TPrint = expr.as_ref();
                 Box::new([expr].into_iter())
             }
@@ -153,38 +592,239 @@ impl TPrint for Statement {
     }
 }
 
-#[derive(Debug)]
+impl<B> Arm<B> {
+    fn arm_label(&self) -> String {
+        if self.guard.is_some() {
+            format!("{} if", self.pattern)
+        } else {
+            self.pattern.to_string()
+        }
+    }
+}
+
+impl TPrint for Arm<Vec<Statement>> {
+    fn label(&self) -> String {
+        self.arm_label()
+    }
+
+    fn children<'a>(&'a self) -> Box<dyn Iterator<Item = &'a dyn TPrint> + 'a> {
+        let guard = self.guard.iter().map(|g| g.as_ref() as &dyn TPrint);
+        let body = self.body.iter().map(|s| s as &dyn TPrint);
+        Box::new(guard.chain(body))
+    }
+}
+
+impl TPrint for Arm<Expr> {
+    fn label(&self) -> String {
+        self.arm_label()
+    }
+
+    fn children<'a>(&'a self) -> Box<dyn Iterator<Item = &'a dyn TPrint> + 'a> {
+        let guard = self.guard.iter().map(|g| g.as_ref() as &dyn TPrint);
+        let body: &'a dyn TPrint = &self.body;
+        Box::new(guard.chain([body]))
+    }
+}
+
+impl TPrint for SElseIf {
+    fn label(&self) -> String {
+        "else if".to_string()
+    }
+
+    fn children<'a>(&'a self) -> Box<dyn Iterator<Item = &'a dyn TPrint> + 'a> {
+        let guard: &'a dyn TPrint = self.guard.as_ref();
+        let body = self.body.iter().map(|s| s as &dyn TPrint);
+        Box::new([guard].into_iter().chain(body))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Op {
     Sub,
     Add,
     Div,
     Mul,
+    Mod,
     Lt,
     Gt,
+    Le,
+    Ge,
     Eq,
+    Ne,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+impl Op {
+    pub fn is_comparison(&self) -> bool {
+        matches!(self, Op::Lt | Op::Gt | Op::Le | Op::Ge | Op::Eq | Op::Ne)
+    }
+
+    pub fn is_logical(&self) -> bool {
+        matches!(self, Op::And | Op::Or)
+    }
+
+    // ops that only apply to integers
+    pub fn is_integral(&self) -> bool {
+        matches!(
+            self,
+            Op::Mod | Op::BitAnd | Op::BitOr | Op::BitXor | Op::Shl | Op::Shr
+        )
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum UOp {
     Neg,
     Pos,
+    Not,
+    BitNot,
+}
+
+// names an expression for passes that record facts about it, unique across
+// every file parsed, a copy of an expression keeps its id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(pub u32);
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+// an expression and the source it was parsed from
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+    pub id: NodeId,
 }
 
-#[derive(Debug)]
-pub enum Expr {
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self {
+            kind,
+            span,
+            id: NodeId(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
     Unary(Unary),
-    Intermediate(i64),
+    // the type is None without a suffix, the literal then takes the type
+    // it is used as
+    Intermediate(i128, Option<IntType>),
+    FloatIntermediate(f64, Option<FloatType>),
+    StringLiteral(String),
+    CharLiteral(char),
+    BoolLiteral(bool),
     Binop(Binop),
     Ident(String),
+    Call(SCall),
+    Block(BlockExpr),
+    // if c { a } else { b } and c ? a : b
+    If(IfExpr),
+    // Point { x: 1, y: 2.0 }
+    StructLiteral(StructLiteral),
+    // p.x
+    Field(FieldAccess),
+    // [1, 2, 3]
+    ArrayLiteral(Vec<Expr>),
+    // a[i]
+    Index(IndexExpr),
+    // match s { Shape::Circle(r) => r, _ => 0.0 }
+    Match(MatchExpr),
+    // x as u8
+    Cast(Cast),
+    // fn(int x) -> int { return x + n; }
+    Lambda(Lambda),
+    // a call of anything that is not a plain name, like p.on_click(e)
+    Apply(Apply),
+}
+
+#[derive(Debug, Clone)]
+pub struct Lambda {
+    pub params: Vec<(LType, String)>,
+    pub ret: LType,
+    pub body: Vec<Statement>,
+    // filled in by capture::resolve
+    pub captures: Vec<Capture>,
+}
+
+// a variable of an enclosing function used by a closure
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub ident: String,
+    // shared with its scope, otherwise copied when the closure is made
+    pub by_ref: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Apply {
+    pub f: Box<Expr>,
+    pub params: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cast {
+    pub x: Box<Expr>,
+    pub typ: LType,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchExpr {
+    pub scrutinee: Box<Expr>,
+    pub arms: Vec<Arm<Expr>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexExpr {
+    pub base: Box<Expr>,
+    pub index: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructLiteral {
+    pub ident: String,
+    pub fields: Vec<FieldInit>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldInit {
+    pub ident: String,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldAccess {
+    pub base: Box<Expr>,
+    pub field: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockExpr {
+    pub stmts: Vec<Statement>,
+    // the trailing expression, a block without one has no value
+    pub value: Option<Box<Expr>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IfExpr {
+    pub guard: Box<Expr>,
+    pub t: Box<Expr>,
+    pub f: Box<Expr>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Unary {
     pub op: UOp,
     pub x: Box<Expr>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Binop {
     pub a: Box<Expr>,
     pub op: Op,
@@ -196,6 +836,8 @@ impl fmt::Display for UOp {
         match self {
             UOp::Neg => write!(f, "-"),
             UOp::Pos => write!(f, "+"),
+            UOp::Not => write!(f, "!"),
+            UOp::BitNot => write!(f, "~"),
         }
     }
 }
@@ -210,17 +852,64 @@ impl fmt::Display for Op {
             Op::Lt => write!(f, "<"),
             Op::Gt => write!(f, ">"),
             Op::Eq => write!(f, "=="),
+            Op::Mod => write!(f, "%"),
+            Op::Le => write!(f, "<="),
+            Op::Ge => write!(f, ">="),
+            Op::Ne => write!(f, "!="),
+            Op::And => write!(f, "&&"),
+            Op::Or => write!(f, "||"),
+            Op::BitAnd => write!(f, "&"),
+            Op::BitOr => write!(f, "|"),
+            Op::BitXor => write!(f, "^"),
+            Op::Shl => write!(f, "<<"),
+            Op::Shr => write!(f, ">>"),
         }
     }
 }
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expr::Unary(u) => write!(f, "{}", u.op),
-            Expr::Binop(b) => write!(f, "{}", b.op),
-            Expr::Intermediate(i) => write!(f, "{}", i),
-            Expr::Ident(s) => write!(f, "{}", s),
+        match &self.kind {
+            ExprKind::Unary(u) => write!(f, "{}", u.op),
+            ExprKind::Binop(b) => write!(f, "{}", b.op),
+            ExprKind::Intermediate(i, None) => write!(f, "{}", i),
+            ExprKind::Intermediate(i, Some(t)) => write!(f, "{}{}", i, t.suffix()),
+            // debug formatting keeps the decimal point on whole floats
+            ExprKind::FloatIntermediate(x, None) => write!(f, "{:?}", x),
+            ExprKind::FloatIntermediate(x, Some(t)) => write!(f, "{:?}{}", x, t.suffix()),
+            // quoted and escaped
+            ExprKind::StringLiteral(s) => write!(f, "{:?}", s),
+            ExprKind::CharLiteral(c) => write!(f, "{:?}", c),
+            ExprKind::BoolLiteral(b) => write!(f, "{}", b),
+            ExprKind::Ident(s) => write!(f, "{}", s),
+            ExprKind::Call(c) => write!(f, "call<{}()>", c.ident),
+            ExprKind::Block(_) => write!(f, "block"),
+            ExprKind::If(_) => write!(f, "if/else"),
+            ExprKind::StructLiteral(s) => write!(f, "{} {{}}", s.ident),
+            ExprKind::Field(a) => write!(f, ".{}", a.field),
+            ExprKind::ArrayLiteral(_) => write!(f, "array"),
+            ExprKind::Index(_) => write!(f, "[]"),
+            ExprKind::Match(_) => write!(f, "match"),
+            ExprKind::Cast(c) => write!(f, "as {}", c.typ),
+            // by reference captures are marked with &
+            ExprKind::Lambda(l) => {
+                let params: Vec<String> = l
+                    .params
+                    .iter()
+                    .map(|(t, p)| format!("{} {}", t, p))
+                    .collect();
+                write!(f, "fn({}) -> {}", params.join(", "), l.ret)?;
+                if !l.captures.is_empty() {
+                    let captures: Vec<String> = l
+                        .captures
+                        .iter()
+                        .map(|c| format!("{}{}", if c.by_ref { "&" } else { "" }, c.ident))
+                        .collect();
+                    write!(f, " [{}]", captures.join(", "))?;
+                }
+                Ok(())
+            }
+            ExprKind::Apply(_) => write!(f, "call"),
         }
     }
 }
@@ -231,17 +920,68 @@ impl TPrint for Expr {
     }
 
     fn children<'a>(&'a self) -> Box<dyn Iterator<Item = &'a dyn TPrint> + 'a> {
-        match self {
-            Expr::Binop(bin) => {
+        match &self.kind {
+            ExprKind::Binop(bin) => {
                 let a: &'a dyn TPrint = bin.a.as_ref();
                 let b: &'a dyn TPrint = bin.b.as_ref();
                 Box::new([a, b].into_iter())
             }
-            Expr::Unary(u) => {
+            ExprKind::Unary(u) => {
                 let e: &'a dyn TPrint = u.x.as_ref();
                 Box::new([e].into_iter())
             }
+            ExprKind::Cast(c) => {
+                let e: &'a dyn TPrint = c.x.as_ref();
+                Box::new([e].into_iter())
+            }
+            ExprKind::Call(c) => Box::new(c.params.iter().map(|p| p as &dyn TPrint)),
+            ExprKind::Lambda(l) => Box::new(l.body.iter().map(|s| s as &dyn TPrint)),
+            ExprKind::Apply(a) => {
+                let f: &'a dyn TPrint = a.f.as_ref();
+                Box::new(
+                    [f].into_iter()
+                        .chain(a.params.iter().map(|p| p as &dyn TPrint)),
+                )
+            }
+            ExprKind::Block(b) => {
+                let stmts = b.stmts.iter().map(|s| s as &dyn TPrint);
+                let value = b.value.iter().map(|e| e.as_ref() as &dyn TPrint);
+                Box::new(stmts.chain(value))
+            }
+            ExprKind::If(i) => {
+                let guard: &'a dyn TPrint = i.guard.as_ref();
+                let t: &'a dyn TPrint = i.t.as_ref();
+                let f: &'a dyn TPrint = i.f.as_ref();
+                Box::new([guard, t, f].into_iter())
+            }
+            ExprKind::StructLiteral(s) => Box::new(s.fields.iter().map(|f| f as &dyn TPrint)),
+            ExprKind::Field(a) => {
+                let base: &'a dyn TPrint = a.base.as_ref();
+                Box::new([base].into_iter())
+            }
+            ExprKind::ArrayLiteral(items) => Box::new(items.iter().map(|e| e as &dyn TPrint)),
+            ExprKind::Index(i) => {
+                let base: &'a dyn TPrint = i.base.as_ref();
+                let index: &'a dyn TPrint = i.index.as_ref();
+                Box::new([base, index].into_iter())
+            }
+            ExprKind::Match(m) => {
+                let scrutinee: &'a dyn TPrint = m.scrutinee.as_ref();
+                let arms = m.arms.iter().map(|a| a as &dyn TPrint);
+                Box::new([scrutinee].into_iter().chain(arms))
+            }
             _ => Box::new([].into_iter()),
         }
     }
 }
+
+impl TPrint for FieldInit {
+    fn label(&self) -> String {
+        format!("{}:", self.ident)
+    }
+
+    fn children<'a>(&'a self) -> Box<dyn Iterator<Item = &'a dyn TPrint> + 'a> {
+        let value: &'a dyn TPrint = &self.value;
+        Box::new([value].into_iter())
+    }
+}
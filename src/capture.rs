@@ -0,0 +1,341 @@
+// decides what each anonymous fn captures: the variables of enclosing
+// functions and blocks its body uses, globals are reached directly. A variable
+// nothing assigns after its declaration is copied into the closure, any other
+// is shared with the scope that declared it
+use std::collections::HashMap;
+
+use crate::ast::*;
+
+pub fn resolve(stmts: &mut [Statement]) {
+    // an assignment can come after the closure that captures the variable,
+    // so the first walk only learns which variables are assigned
+    let mut walk = Walk::new(Vec::new());
+    walk.stmts(stmts);
+    let mut walk = Walk::new(walk.assigned);
+    walk.stmts(stmts);
+}
+
+struct Walk {
+    // the declaration each name refers to, scopes[0] holds the globals
+    scopes: Vec<HashMap<String, usize>>,
+    // declarations are numbered in walk order, which both walks share
+    next: usize,
+    // whether anything assigns each declaration
+    assigned: Vec<bool>,
+    // closures being walked, innermost last: the depth of their first scope
+    // and the declarations from outside it they use
+    lambdas: Vec<(usize, Vec<(String, usize)>)>,
+}
+
+impl Walk {
+    fn new(assigned: Vec<bool>) -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            next: 0,
+            assigned,
+            lambdas: Vec::new(),
+        }
+    }
+
+    fn declare(&mut self, ident: &str) {
+        if self.next == self.assigned.len() {
+            self.assigned.push(false);
+        }
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(ident.to_string(), self.next);
+        self.next += 1;
+    }
+
+    // a use of ident, which every closure between it and its declaration
+    // captures
+    fn use_var(&mut self, ident: &str, assign: bool) {
+        let Some((depth, id)) = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(d, s)| s.get(ident).map(|&id| (d, id)))
+        else {
+            return;
+        };
+        if assign {
+            self.assigned[id] = true;
+        }
+        if depth == 0 {
+            return;
+        }
+        for (base, used) in &mut self.lambdas {
+            if depth < *base && !used.iter().any(|(_, u)| *u == id) {
+                used.push((ident.to_string(), id));
+            }
+        }
+    }
+
+    fn block(&mut self, stmts: &mut [Statement]) {
+        self.scopes.push(HashMap::new());
+        self.stmts(stmts);
+        self.scopes.pop();
+    }
+
+    fn stmts(&mut self, stmts: &mut [Statement]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Statement) {
+        match &mut stmt.kind {
+            StatementKind::If(sif) => {
+                self.expr(&mut sif.guard);
+                self.block(&mut sif.t);
+                for e in &mut sif.elifs {
+                    self.expr(&mut e.guard);
+                    self.block(&mut e.body);
+                }
+                if let Some(f) = &mut sif.f {
+                    self.block(f);
+                }
+            }
+            StatementKind::Declare(d) => {
+                if let Some(e) = &mut d.assign {
+                    self.expr(e);
+                }
+                self.declare(&d.ident);
+            }
+            StatementKind::Assign(a) => {
+                for access in &mut a.target.path {
+                    if let Access::Index(index) = access {
+                        self.expr(index);
+                    }
+                }
+                self.expr(&mut a.value);
+                self.use_var(&a.target.ident, true);
+            }
+            StatementKind::While(w) => {
+                self.expr(&mut w.cond);
+                self.block(&mut w.body);
+            }
+            StatementKind::For(f) => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = &mut f.init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = &mut f.cond {
+                    self.expr(cond);
+                }
+                self.block(&mut f.body);
+                if let Some(step) = &mut f.step {
+                    self.stmt(step);
+                }
+                self.scopes.pop();
+            }
+            StatementKind::Loop(l) => self.block(&mut l.body),
+            StatementKind::DoWhile(d) => {
+                self.block(&mut d.body);
+                self.expr(&mut d.cond);
+            }
+            StatementKind::Expr(e) | StatementKind::Return(e) => self.expr(e),
+            // a function body only sees its own frame and the globals
+            StatementKind::Function(f) => {
+                let saved = self.scopes.split_off(1);
+                let lambdas = std::mem::take(&mut self.lambdas);
+                self.scopes.push(HashMap::new());
+                for (_, param) in &f.params {
+                    self.declare(param);
+                }
+                self.stmts(&mut f.body);
+                self.scopes.truncate(1);
+                self.scopes.extend(saved);
+                self.lambdas = lambdas;
+            }
+            StatementKind::Match(m) => {
+                self.expr(&mut m.scrutinee);
+                for arm in &mut m.arms {
+                    self.scopes.push(HashMap::new());
+                    self.arm(arm);
+                    self.stmts(&mut arm.body);
+                    self.scopes.pop();
+                }
+            }
+            StatementKind::Block(stmts) => self.block(stmts),
+            StatementKind::Struct(_)
+            | StatementKind::Enum(_)
+            | StatementKind::Import(_)
+            | StatementKind::Break(_)
+            | StatementKind::Continue(_) => {}
+        }
+    }
+
+    // the pattern and guard of an arm, in the arm's scope
+    fn arm<B>(&mut self, arm: &mut Arm<B>) {
+        self.pattern(&arm.pattern);
+        if let Some(guard) = &mut arm.guard {
+            self.expr(guard);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(ident) => self.declare(ident),
+            Pattern::Variant(v) => {
+                for field in &v.fields {
+                    self.pattern(field);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Ident(ident) => self.use_var(ident, false),
+            // a call may go through a variable holding a function
+            ExprKind::Call(c) => {
+                self.use_var(&c.ident, false);
+                for p in &mut c.params {
+                    self.expr(p);
+                }
+            }
+            ExprKind::Apply(a) => {
+                self.expr(&mut a.f);
+                for p in &mut a.params {
+                    self.expr(p);
+                }
+            }
+            ExprKind::Lambda(l) => {
+                self.lambdas.push((self.scopes.len(), Vec::new()));
+                self.scopes.push(HashMap::new());
+                for (_, param) in &l.params {
+                    self.declare(param);
+                }
+                self.stmts(&mut l.body);
+                self.scopes.pop();
+                let (_, used) = self.lambdas.pop().unwrap();
+                l.captures = used
+                    .into_iter()
+                    .map(|(ident, id)| Capture {
+                        ident,
+                        by_ref: self.assigned[id],
+                    })
+                    .collect();
+            }
+            ExprKind::Unary(u) => self.expr(&mut u.x),
+            ExprKind::Binop(b) => {
+                self.expr(&mut b.a);
+                self.expr(&mut b.b);
+            }
+            ExprKind::Cast(c) => self.expr(&mut c.x),
+            ExprKind::Block(b) => {
+                self.scopes.push(HashMap::new());
+                self.stmts(&mut b.stmts);
+                if let Some(value) = &mut b.value {
+                    self.expr(value);
+                }
+                self.scopes.pop();
+            }
+            ExprKind::If(i) => {
+                self.expr(&mut i.guard);
+                self.expr(&mut i.t);
+                self.expr(&mut i.f);
+            }
+            ExprKind::StructLiteral(s) => {
+                for f in &mut s.fields {
+                    self.expr(&mut f.value);
+                }
+            }
+            ExprKind::Field(a) => self.expr(&mut a.base),
+            ExprKind::ArrayLiteral(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            ExprKind::Index(i) => {
+                self.expr(&mut i.base);
+                self.expr(&mut i.index);
+            }
+            ExprKind::Match(m) => {
+                self.expr(&mut m.scrutinee);
+                for arm in &mut m.arms {
+                    self.scopes.push(HashMap::new());
+                    self.arm(arm);
+                    self.expr(&mut arm.body);
+                    self.scopes.pop();
+                }
+            }
+            ExprKind::Intermediate(..)
+            | ExprKind::FloatIntermediate(..)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::CharLiteral(_)
+            | ExprKind::BoolLiteral(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve;
+    use crate::ast::{ExprKind, Statement, StatementKind};
+    use crate::parser::Parser;
+
+    // the labels of the lambdas declared at the top level, in order
+    fn lambdas(source: &str) -> Vec<String> {
+        let mut stmts = Parser::new(source.chars()).parse_program().unwrap();
+        resolve(&mut stmts);
+        let mut labels = Vec::new();
+        let mut stack: Vec<&Statement> = stmts.iter().rev().collect();
+        while let Some(stmt) = stack.pop() {
+            match &stmt.kind {
+                StatementKind::Declare(d) => {
+                    if let Some(ExprKind::Lambda(l)) = d.assign.as_deref().map(|e| &e.kind) {
+                        labels.push(d.assign.as_ref().unwrap().to_string());
+                        stack.extend(l.body.iter().rev());
+                    }
+                }
+                StatementKind::Function(f) => stack.extend(f.body.iter().rev()),
+                StatementKind::Block(b) => stack.extend(b.iter().rev()),
+                _ => {}
+            }
+        }
+        labels
+    }
+
+    #[test]
+    fn resolve_captures() {
+        let source = "
+            int global = 0;
+            fn make(int step, int start) -> int {
+                int count = start;
+                fn() -> int next = fn() -> int {
+                    count += step;
+                    global += 1;
+                    return count;
+                };
+                fn(int) -> int add = fn(int x) -> int { return x + step; };
+                return next() + add(1);
+            };
+        ";
+        assert_eq!(
+            lambdas(source),
+            vec!["fn() -> int [step, &count]", "fn(int x) -> int [step]"]
+        );
+
+        // a nested closure makes the one around it capture too, and a
+        // variable assigned after the closure is made is shared
+        let source = "
+            {
+                int n = 1;
+                fn() -> int outer = fn() -> int {
+                    fn() -> int inner = fn() -> int { return n; };
+                    return inner();
+                };
+                n = 2;
+            };
+        ";
+        assert_eq!(
+            lambdas(source),
+            vec!["fn() -> int [&n]", "fn() -> int [&n]"]
+        );
+    }
+}
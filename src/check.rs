@@ -0,0 +1,1994 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::analysis;
+use crate::ast::*;
+use crate::diag::Diagnostic;
+use crate::interp::is_builtin;
+use crate::mono::{self, Instance, Instances};
+use crate::source::Span;
+
+#[derive(Debug)]
+pub struct CheckError {
+    pub span: Span,
+    pub reason: CheckErrorReason,
+    // the instances the error is in, innermost first
+    pub notes: Vec<String>,
+}
+
+impl CheckError {
+    pub fn new(reason: CheckErrorReason, span: Span) -> Self {
+        Self {
+            span,
+            reason,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut diag = Diagnostic::error(self.reason.to_string()).with_primary(self.span, "");
+        for note in &self.notes {
+            diag = diag.with_note(note.clone());
+        }
+        diag
+    }
+}
+
+// instances nested deeper than this are taken to never end, as in
+// fn f<T>(T x) -> int { return f([x]); }
+const MAX_INSTANCE_DEPTH: usize = 32;
+
+#[derive(Debug)]
+pub enum CheckErrorReason {
+    ArgumentCount {
+        ident: String,
+        expected: usize,
+        found: usize,
+    },
+    // a generic function named where nothing gives its type arguments
+    CannotInfer(String),
+    EmptyArray,
+    // an enum without variants has no values
+    EmptyEnum(String),
+    // return, break or continue leaving a block expression
+    EscapingValueBlock(String),
+    GenericNotTopLevel(String),
+    // instances of a generic function that keep needing bigger ones
+    InstanceDepth(String),
+    // a constant index past the end of an array
+    IndexOutOfBounds {
+        index: i128,
+        len: usize,
+    },
+    InvalidCast {
+        from: LType,
+        to: LType,
+    },
+    InvalidOperand {
+        op: String,
+        found: LType,
+    },
+    // an integer literal outside the range of the type it is used as
+    LiteralOutOfRange {
+        value: i128,
+        typ: LType,
+    },
+    MissingBlockValue,
+    // fields a struct literal leaves out
+    MissingFields {
+        ident: String,
+        fields: Vec<String>,
+    },
+    NoField {
+        typ: LType,
+        field: String,
+    },
+    // a variable declared without a value whose type has none to give
+    NoDefault {
+        ident: String,
+        typ: LType,
+    },
+    NonBoolCondition(LType),
+    // values no arm of a match covers
+    NonExhaustive(Vec<String>),
+    NotCallable(LType),
+    NotIndexable(LType),
+    // a struct or enum holding itself by value
+    RecursiveType(String),
+    Redefinition(String),
+    ReturnOutsideFunction,
+    TypeMismatch {
+        expected: LType,
+        found: LType,
+    },
+    UndefinedFunction(String),
+    UndefinedType(String),
+    UnresolvedImport(String),
+    // a type parameter no parameter mentions, which calls cannot infer
+    UnusedTypeParam {
+        ident: String,
+        param: String,
+    },
+    UndefinedVariable(String),
+    UnknownVariant {
+        ident: String,
+        variant: String,
+    },
+    // an arm the arms before it already cover
+    UnreachableArm,
+    VariantArity {
+        ident: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for CheckErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckErrorReason::ArgumentCount {
+                ident,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function `{ident}` takes {expected} arguments but {found} were supplied"
+            ),
+            CheckErrorReason::CannotInfer(ident) => write!(
+                f,
+                "cannot infer the type arguments of `{ident}`, call it or use it where a function type is expected"
+            ),
+            CheckErrorReason::EmptyArray => {
+                write!(f, "cannot infer the type of an empty array literal")
+            }
+            CheckErrorReason::EmptyEnum(ident) => write!(f, "enum `{ident}` has no variants"),
+            CheckErrorReason::EscapingValueBlock(kw) => {
+                write!(f, "`{kw}` cannot leave a block expression")
+            }
+            CheckErrorReason::GenericNotTopLevel(ident) => write!(
+                f,
+                "generic function `{ident}` must be declared at the top level"
+            ),
+            CheckErrorReason::InstanceDepth(ident) => write!(
+                f,
+                "instances of `{ident}` never end, each one needs a bigger one"
+            ),
+            CheckErrorReason::IndexOutOfBounds { index, len } => write!(
+                f,
+                "index out of bounds: the length is {len} but the index is {index}"
+            ),
+            CheckErrorReason::InvalidCast { from, to } => {
+                write!(f, "cannot cast `{from}` to `{to}`")
+            }
+            CheckErrorReason::InvalidOperand { op, found } => {
+                write!(f, "cannot apply `{op}` to a value of type `{found}`")
+            }
+            CheckErrorReason::LiteralOutOfRange { value, typ } => {
+                write!(f, "literal `{value}` does not fit in `{typ}`")
+            }
+            CheckErrorReason::MissingBlockValue => {
+                write!(
+                    f,
+                    "block expression has no value, end it with an expression"
+                )
+            }
+            CheckErrorReason::MissingFields { ident, fields } => {
+                let fields: Vec<String> = fields.iter().map(|f| format!("`{f}`")).collect();
+                write!(
+                    f,
+                    "missing fields {} in initializer of `{ident}`",
+                    fields.join(", ")
+                )
+            }
+            CheckErrorReason::NoField { typ, field } => {
+                write!(f, "no field `{field}` on type `{typ}`")
+            }
+            CheckErrorReason::NoDefault { ident, typ } => {
+                write!(
+                    f,
+                    "variable `{ident}` needs a value, `{typ}` has no default"
+                )
+            }
+            CheckErrorReason::NonBoolCondition(found) => {
+                write!(f, "condition must be `bool`, found `{found}`")
+            }
+            CheckErrorReason::NonExhaustive(missing) => {
+                let missing: Vec<String> = missing.iter().map(|p| format!("`{p}`")).collect();
+                write!(
+                    f,
+                    "non-exhaustive patterns: {} not covered",
+                    missing.join(", ")
+                )
+            }
+            CheckErrorReason::NotCallable(found) => write!(f, "`{found}` is not a function"),
+            CheckErrorReason::NotIndexable(found) => {
+                write!(f, "cannot index into a value of type `{found}`")
+            }
+            CheckErrorReason::RecursiveType(ident) => {
+                write!(f, "type `{ident}` contains itself and has no finite size")
+            }
+            CheckErrorReason::Redefinition(ident) => write!(f, "`{ident}` is defined twice"),
+            CheckErrorReason::ReturnOutsideFunction => write!(f, "`return` outside of a function"),
+            CheckErrorReason::TypeMismatch { expected, found } => {
+                write!(
+                    f,
+                    "mismatched types: expected `{expected}`, found `{found}`"
+                )
+            }
+            CheckErrorReason::UndefinedFunction(ident) => {
+                write!(f, "cannot find function `{ident}`")
+            }
+            CheckErrorReason::UndefinedType(ident) => write!(f, "cannot find type `{ident}`"),
+            CheckErrorReason::UnresolvedImport(module) => write!(
+                f,
+                "import of `{module}` was not resolved, imports must be at the top level"
+            ),
+            CheckErrorReason::UnusedTypeParam { ident, param } => write!(
+                f,
+                "type parameter `{param}` of `{ident}` is not used by its parameters"
+            ),
+            CheckErrorReason::UndefinedVariable(ident) => {
+                write!(f, "cannot find variable `{ident}`")
+            }
+            CheckErrorReason::UnknownVariant { ident, variant } => {
+                write!(f, "no variant `{variant}` in enum `{ident}`")
+            }
+            CheckErrorReason::UnreachableArm => {
+                write!(
+                    f,
+                    "unreachable pattern: the arms before it cover its values"
+                )
+            }
+            CheckErrorReason::VariantArity {
+                ident,
+                expected,
+                found,
+            } => write!(
+                f,
+                "variant `{ident}` has {expected} fields but {found} were supplied"
+            ),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Scope<'a> {
+    vars: HashMap<String, LType>,
+    fns: HashMap<String, &'a SFunction>,
+    types: HashMap<String, TypeDef<'a>>,
+}
+
+// type checker, scopes[0] holds the globals
+struct Checker<'a> {
+    scopes: Vec<Scope<'a>>,
+    // return type of the function being checked
+    ret: Option<LType>,
+    // loops that break and continue can reach, reset by functions and
+    // block expressions
+    loops: Vec<Option<String>>,
+    // inside a block expression, which control flow cannot leave
+    in_value: bool,
+    // type arguments of the instance being checked, and its index
+    types: HashMap<String, LType>,
+    instance: Option<usize>,
+    depth: usize,
+    // generic functions whose instances never end, not instantiated again
+    endless: HashSet<String>,
+    instances: Instances,
+    errors: Vec<CheckError>,
+    // the statement or expression being checked, where errors point
+    span: Span,
+}
+
+// the instances of generic functions the program uses, for mono
+pub fn check(stmts: &[Statement]) -> Result<Instances, Vec<CheckError>> {
+    let mut checker = Checker::new();
+    checker.check_stmts(stmts);
+    if checker.errors.is_empty() {
+        Ok(checker.instances)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn new() -> Self {
+        Self {
+            scopes: vec![Scope::default()],
+            ret: None,
+            loops: Vec::new(),
+            in_value: false,
+            types: HashMap::new(),
+            instance: None,
+            depth: 0,
+            endless: HashSet::new(),
+            instances: Instances::default(),
+            errors: Vec::new(),
+            span: Span::default(),
+        }
+    }
+
+    fn error(&mut self, reason: CheckErrorReason) {
+        self.errors.push(CheckError::new(reason, self.span));
+    }
+
+    // f with errors pointing at span
+    fn at<T>(&mut self, span: Span, f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = std::mem::replace(&mut self.span, span);
+        let t = f(self);
+        self.span = saved;
+        t
+    }
+
+    fn check_block(&mut self, stmts: &'a [Statement]) {
+        self.scopes.push(Scope::default());
+        self.check_stmts(stmts);
+        self.scopes.pop();
+    }
+
+    // checks stmts in the current scope, functions and types are hoisted
+    fn check_stmts(&mut self, stmts: &'a [Statement]) {
+        for s in stmts {
+            let (ident, span, prev) = match &s.kind {
+                StatementKind::Function(f) => (
+                    &f.ident,
+                    f.span,
+                    self.scope().fns.insert(f.ident.clone(), f).is_some(),
+                ),
+                StatementKind::Struct(st) => (
+                    &st.ident,
+                    st.span,
+                    self.scope()
+                        .types
+                        .insert(st.ident.clone(), TypeDef::Struct(st))
+                        .is_some(),
+                ),
+                StatementKind::Enum(e) => (
+                    &e.ident,
+                    e.span,
+                    self.scope()
+                        .types
+                        .insert(e.ident.clone(), TypeDef::Enum(e))
+                        .is_some(),
+                ),
+                _ => continue,
+            };
+            if prev {
+                self.at(span, |c| {
+                    c.error(CheckErrorReason::Redefinition(ident.clone()))
+                });
+            }
+        }
+        for s in stmts {
+            self.check_stmt(s);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &'a Statement) {
+        // declarations point at their name
+        let span = match &stmt.kind {
+            StatementKind::Function(f) => f.span,
+            StatementKind::Struct(s) => s.span,
+            StatementKind::Enum(e) => e.span,
+            StatementKind::Import(i) => i.span,
+            _ => stmt.span,
+        };
+        self.at(span, |c| c.check_stmt_kind(stmt));
+    }
+
+    fn check_stmt_kind(&mut self, stmt: &'a Statement) {
+        match &stmt.kind {
+            StatementKind::Declare(d) => {
+                let typ = self.concrete(&d.typ);
+                self.check_type(&typ);
+                match &d.assign {
+                    Some(e) => self.check_value(e, &typ),
+                    None if !self.has_default(&typ, &mut HashSet::new()) => {
+                        self.error(CheckErrorReason::NoDefault {
+                            ident: d.ident.clone(),
+                            typ: typ.clone(),
+                        });
+                    }
+                    None => {}
+                }
+                self.scope().vars.insert(d.ident.clone(), typ);
+            }
+            StatementKind::Assign(a) => {
+                let typ = self.place_type(&a.target);
+                // the 1 of x++ is a number of the type of x, float or not
+                let value = match &typ {
+                    Some(t) if a.step && t.is_numeric() => Some(self.literal(&a.value, t.clone())),
+                    _ => self.check_expr_hint(&a.value, typ.as_ref()),
+                };
+                if let Some(typ) = typ
+                    && let Some(t) = value
+                {
+                    if !a.step {
+                        self.at(a.value.span, |c| c.expect_type(&typ, t));
+                    }
+                    if let Some(op) = &a.op {
+                        let ok = Self::supports(op, &typ);
+                        // x++ is named as written
+                        let shown = if a.step {
+                            format!("{op}{op}")
+                        } else {
+                            op.to_string()
+                        };
+                        self.expect_operand(shown, &typ, ok);
+                    }
+                }
+            }
+            StatementKind::If(sif) => {
+                self.check_cond(&sif.guard);
+                self.check_block(&sif.t);
+                for e in &sif.elifs {
+                    self.check_cond(&e.guard);
+                    self.check_block(&e.body);
+                }
+                if let Some(f) = &sif.f {
+                    self.check_block(f);
+                }
+            }
+            StatementKind::While(w) => {
+                self.check_cond(&w.cond);
+                self.check_loop_body(&w.label, &w.body);
+            }
+            StatementKind::For(f) => {
+                // init is scoped to the loop
+                self.scopes.push(Scope::default());
+                if let Some(init) = &f.init {
+                    self.check_stmt(init);
+                }
+                if let Some(cond) = &f.cond {
+                    self.check_cond(cond);
+                }
+                if let Some(step) = &f.step {
+                    self.check_stmt(step);
+                }
+                self.check_loop_body(&f.label, &f.body);
+                self.scopes.pop();
+            }
+            StatementKind::Loop(l) => self.check_loop_body(&l.label, &l.body),
+            StatementKind::DoWhile(d) => {
+                self.check_loop_body(&d.label, &d.body);
+                self.check_cond(&d.cond);
+            }
+            // labels are resolved by the parser, only block expressions
+            // can hide the target loop
+            StatementKind::Break(label) | StatementKind::Continue(label) => {
+                let reachable = match label {
+                    Some(l) => self.loops.iter().any(|x| x.as_ref() == Some(l)),
+                    None => !self.loops.is_empty(),
+                };
+                if self.in_value && !reachable {
+                    let kw = if matches!(stmt.kind, StatementKind::Break(_)) {
+                        "break"
+                    } else {
+                        "continue"
+                    };
+                    self.error(CheckErrorReason::EscapingValueBlock(kw.to_string()));
+                }
+            }
+            // top level imports are removed by the module resolver
+            StatementKind::Import(i) => {
+                self.error(CheckErrorReason::UnresolvedImport(i.module.clone()));
+            }
+            StatementKind::Expr(e) => {
+                self.check_expr(e);
+            }
+            StatementKind::Struct(s) => {
+                let mut seen = HashSet::new();
+                for (typ, field) in &s.fields {
+                    self.check_type(typ);
+                    if !seen.insert(field) {
+                        self.error(CheckErrorReason::Redefinition(format!(
+                            "{}.{}",
+                            s.ident, field
+                        )));
+                    }
+                }
+                let mut seen = HashSet::new();
+                if s.fields
+                    .iter()
+                    .any(|(t, _)| self.contains_type(t, &s.ident, &mut seen))
+                {
+                    self.error(CheckErrorReason::RecursiveType(s.ident.clone()));
+                }
+            }
+            StatementKind::Enum(e) => {
+                if e.variants.is_empty() {
+                    self.error(CheckErrorReason::EmptyEnum(e.ident.clone()));
+                }
+                let mut seen = HashSet::new();
+                for (variant, payload) in &e.variants {
+                    for typ in payload {
+                        self.check_type(typ);
+                    }
+                    if !seen.insert(variant) {
+                        self.error(CheckErrorReason::Redefinition(format!(
+                            "{}::{}",
+                            e.ident, variant
+                        )));
+                    }
+                }
+                let mut seen = HashSet::new();
+                if e.variants
+                    .iter()
+                    .flat_map(|(_, payload)| payload)
+                    .any(|t| self.contains_type(t, &e.ident, &mut seen))
+                {
+                    self.error(CheckErrorReason::RecursiveType(e.ident.clone()));
+                }
+            }
+            StatementKind::Match(m) => {
+                self.check_match(&m.scrutinee, &m.arms, |c, body| {
+                    c.check_stmts(body);
+                    None
+                });
+            }
+            // a generic function is checked through its instances
+            StatementKind::Function(f) if !f.generics.is_empty() => {
+                if self.scopes.len() > 1 {
+                    self.error(CheckErrorReason::GenericNotTopLevel(f.ident.clone()));
+                }
+                for param in &f.generics {
+                    if !f.params.iter().any(|(t, _)| mentions(t, param)) {
+                        self.error(CheckErrorReason::UnusedTypeParam {
+                            ident: f.ident.clone(),
+                            param: param.clone(),
+                        });
+                    }
+                }
+            }
+            StatementKind::Function(f) => self.check_function(f),
+            StatementKind::Block(stmts) => self.check_block(stmts),
+
+            StatementKind::Return(e) => {
+                if self.in_value {
+                    self.error(CheckErrorReason::EscapingValueBlock("return".to_string()));
+                }
+                let ret = self.ret.clone();
+                let t = self.check_expr_hint(e, ret.as_ref());
+                match (ret, t) {
+                    (Some(ret), Some(t)) => self.at(e.span, |c| c.expect_type(&ret, t)),
+                    (None, _) => self.error(CheckErrorReason::ReturnOutsideFunction),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn check_function(&mut self, f: &'a SFunction) {
+        let params: Vec<LType> = f.params.iter().map(|(t, _)| self.concrete(t)).collect();
+        let ret = self.concrete(&f.ret);
+        for typ in &params {
+            self.check_type(typ);
+        }
+        self.check_type(&ret);
+        let mut frame = Scope::default();
+        for (typ, (_, name)) in params.into_iter().zip(&f.params) {
+            frame.vars.insert(name.clone(), typ);
+        }
+
+        // a function body only sees its own frame and the globals
+        let saved = self.scopes.split_off(1);
+        let saved_ret = self.ret.replace(ret);
+        let saved_loops = std::mem::take(&mut self.loops);
+        let saved_in_value = std::mem::replace(&mut self.in_value, false);
+        self.scopes.push(frame);
+        self.check_stmts(&f.body);
+        self.scopes.truncate(1);
+        self.scopes.extend(saved);
+        self.ret = saved_ret;
+        self.loops = saved_loops;
+        self.in_value = saved_in_value;
+    }
+
+    // the instance of a generic function at args, checked the first time it
+    // is needed, None when it cannot be made
+    fn instantiate(&mut self, f: &'a SFunction, args: Vec<LType>) -> Option<usize> {
+        let instance = Instance {
+            generic: f.ident.clone(),
+            args,
+        };
+        if let Some(id) = self.instances.list.iter().position(|i| *i == instance) {
+            return Some(id);
+        }
+        if self.endless.contains(&f.ident) {
+            return None;
+        }
+        // the generic function is to blame rather than the call that got
+        // too deep
+        if self.depth == MAX_INSTANCE_DEPTH {
+            self.at(f.span, |c| {
+                c.error(CheckErrorReason::InstanceDepth(f.ident.clone()))
+            });
+            self.endless.insert(f.ident.clone());
+            return None;
+        }
+        let id = self.instances.list.len();
+        let types: Vec<String> = f
+            .generics
+            .iter()
+            .zip(&instance.args)
+            .map(|(g, t)| format!("`{g} = {t}`"))
+            .collect();
+        let note = format!("in `{}` with {}", f.ident, types.join(", "));
+        let types: HashMap<String, LType> = f
+            .generics
+            .iter()
+            .cloned()
+            .zip(instance.args.iter().cloned())
+            .collect();
+        self.instances.list.push(instance);
+
+        let errors = self.errors.len();
+        let saved_types = std::mem::replace(&mut self.types, types);
+        let saved_instance = self.instance.replace(id);
+        self.depth += 1;
+        self.check_function(f);
+        self.depth -= 1;
+        self.types = saved_types;
+        self.instance = saved_instance;
+
+        // the types of instances that never end grow without meaning, only
+        // the report that they never end is kept
+        if self.endless.contains(&f.ident) {
+            let deeper = self.errors.split_off(errors);
+            let depth = deeper
+                .into_iter()
+                .filter(|e| matches!(e.reason, CheckErrorReason::InstanceDepth(_)));
+            self.errors.extend(depth);
+            return None;
+        }
+        // errors in the body say which instance they are in
+        for e in &mut self.errors[errors..] {
+            if matches!(e.reason, CheckErrorReason::InstanceDepth(_)) {
+                continue;
+            }
+            e.notes.push(note.clone());
+        }
+        Some(id)
+    }
+
+    // a call of a generic function, typed arguments bind the type
+    // parameters before literals take their type from them
+    fn check_generic_call(
+        &mut self,
+        site: &'a Expr,
+        f: &'a SFunction,
+        params: &'a [Expr],
+    ) -> Option<LType> {
+        if f.params.len() != params.len() {
+            for p in params {
+                self.check_expr(p);
+            }
+            self.error(CheckErrorReason::ArgumentCount {
+                ident: f.ident.clone(),
+                expected: f.params.len(),
+                found: params.len(),
+            });
+            return None;
+        }
+        let (typed, untyped): (Vec<usize>, Vec<usize>) =
+            (0..params.len()).partition(|&i| !is_untyped(&params[i]));
+        let mut bound = HashMap::new();
+        let mut ok = true;
+        for i in typed.into_iter().chain(untyped) {
+            let param = &f.params[i].0;
+            let hint = closed(param, &f.generics, &bound);
+            let Some(t) = self.check_expr_hint(&params[i], hint.as_ref()) else {
+                ok = false;
+                continue;
+            };
+            if !unify(param, &t, &f.generics, &mut bound) {
+                let expected = mono::substitute(param, &bound);
+                self.at(params[i].span, |c| {
+                    c.error(CheckErrorReason::TypeMismatch { expected, found: t })
+                });
+                ok = false;
+            }
+        }
+        if !ok {
+            return None;
+        }
+        self.use_instance(site, f, &bound)?;
+        Some(mono::substitute(&f.ret, &bound))
+    }
+
+    // a generic function used as a value, the function type the context
+    // wants gives its type arguments
+    fn check_generic_value(
+        &mut self,
+        site: &'a Expr,
+        f: &'a SFunction,
+        hint: Option<&LType>,
+    ) -> Option<LType> {
+        let mut bound = HashMap::new();
+        let generic = LType::Fn(
+            f.params.iter().map(|(t, _)| t.clone()).collect(),
+            Box::new(f.ret.clone()),
+        );
+        let Some(hint) = hint else {
+            self.error(CheckErrorReason::CannotInfer(f.ident.clone()));
+            return None;
+        };
+        if !unify(&generic, hint, &f.generics, &mut bound) {
+            self.error(CheckErrorReason::TypeMismatch {
+                expected: hint.clone(),
+                found: generic,
+            });
+            return None;
+        }
+        self.use_instance(site, f, &bound)?;
+        Some(mono::substitute(&generic, &bound))
+    }
+
+    // records that site refers to the instance of f at the bound type
+    // arguments
+    fn use_instance(
+        &mut self,
+        site: &'a Expr,
+        f: &'a SFunction,
+        bound: &HashMap<String, LType>,
+    ) -> Option<()> {
+        let args = f
+            .generics
+            .iter()
+            .map(|g| bound.get(g).cloned())
+            .collect::<Option<Vec<LType>>>()?;
+        let id = self.instantiate(f, args)?;
+        self.instances.sites.insert((site.id, self.instance), id);
+        Some(())
+    }
+
+    // typ with the type arguments of the instance being checked
+    fn concrete(&self, typ: &LType) -> LType {
+        if self.types.is_empty() {
+            return typ.clone();
+        }
+        mono::substitute(typ, &self.types)
+    }
+
+    fn check_loop_body(&mut self, label: &Option<String>, body: &'a [Statement]) {
+        self.loops.push(label.clone());
+        self.check_block(body);
+        self.loops.pop();
+    }
+
+    fn check_block_expr(&mut self, b: &'a BlockExpr, hint: Option<&LType>) -> Option<LType> {
+        let saved_loops = std::mem::take(&mut self.loops);
+        let saved_in_value = std::mem::replace(&mut self.in_value, true);
+        self.scopes.push(Scope::default());
+        self.check_stmts(&b.stmts);
+        let typ = match &b.value {
+            Some(value) => self.check_expr_hint(value, hint),
+            None => {
+                self.error(CheckErrorReason::MissingBlockValue);
+                None
+            }
+        };
+        self.scopes.pop();
+        self.loops = saved_loops;
+        self.in_value = saved_in_value;
+        typ
+    }
+
+    // a call names a function declaration, a variant, a builtin or a
+    // variable holding a function, in that order
+    fn check_call(&mut self, site: &'a Expr, ident: &str, params: &'a [Expr]) -> Option<LType> {
+        if let Some(f) = self.lookup_fn(ident)
+            && !f.generics.is_empty()
+        {
+            return self.check_generic_call(site, f, params);
+        }
+        // parameter types give untyped literal arguments their type
+        let hints: Vec<LType> = match (self.lookup_fn(ident), self.lookup_variant_enum(ident)) {
+            (Some(f), _) => f.params.iter().map(|(t, _)| self.concrete(t)).collect(),
+            (None, Some((e, variant))) => e
+                .variants
+                .iter()
+                .find(|(v, _)| v == variant)
+                .map(|(_, payload)| payload.clone())
+                .unwrap_or_default(),
+            _ => match self.find_var(ident) {
+                Some(LType::Fn(params, _)) if !is_builtin(ident) => params,
+                _ => Vec::new(),
+            },
+        };
+        let args: Vec<(Option<LType>, Span)> = params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (self.check_expr_hint(p, hints.get(i)), p.span))
+            .collect();
+
+        let Some(f) = self.lookup_fn(ident) else {
+            if let Some((e, variant)) = self.lookup_variant_enum(ident) {
+                return self.check_variant(e, variant, args);
+            }
+            if is_builtin(ident) {
+                return Some(INT);
+            }
+            if let Some(typ) = self.find_var(ident) {
+                return self.check_apply(ident, typ, args);
+            }
+            self.error(CheckErrorReason::UndefinedFunction(ident.to_string()));
+            return None;
+        };
+        let typ = self.fn_type(f);
+        self.check_apply(ident, typ, args)
+    }
+
+    // the type of a function as a value
+    fn fn_type(&self, f: &SFunction) -> LType {
+        LType::Fn(
+            f.params.iter().map(|(t, _)| self.concrete(t)).collect(),
+            Box::new(self.concrete(&f.ret)),
+        )
+    }
+
+    // a call of a value of typ, named ident in errors, with the types of the
+    // arguments and where they are
+    fn check_apply(
+        &mut self,
+        ident: &str,
+        typ: LType,
+        args: Vec<(Option<LType>, Span)>,
+    ) -> Option<LType> {
+        let LType::Fn(params, ret) = typ else {
+            self.error(CheckErrorReason::NotCallable(typ));
+            return None;
+        };
+        if params.len() != args.len() {
+            self.error(CheckErrorReason::ArgumentCount {
+                ident: ident.to_string(),
+                expected: params.len(),
+                found: args.len(),
+            });
+        } else {
+            for (typ, (arg, span)) in params.iter().zip(args) {
+                if let Some(t) = arg {
+                    self.at(span, |c| c.expect_type(typ, t));
+                }
+            }
+        }
+        Some(*ret)
+    }
+
+    // an anonymous function sees the scopes around it, which it captures
+    fn check_lambda(&mut self, l: &'a Lambda) -> Option<LType> {
+        let params: Vec<LType> = l.params.iter().map(|(t, _)| self.concrete(t)).collect();
+        let ret = self.concrete(&l.ret);
+        for typ in &params {
+            self.check_type(typ);
+        }
+        self.check_type(&ret);
+        let mut frame = Scope::default();
+        for (typ, (_, name)) in params.iter().zip(&l.params) {
+            frame.vars.insert(name.clone(), typ.clone());
+        }
+
+        let saved_ret = self.ret.replace(ret.clone());
+        let saved_loops = std::mem::take(&mut self.loops);
+        let saved_in_value = std::mem::replace(&mut self.in_value, false);
+        self.scopes.push(frame);
+        self.check_stmts(&l.body);
+        self.scopes.pop();
+        self.ret = saved_ret;
+        self.loops = saved_loops;
+        self.in_value = saved_in_value;
+        Some(LType::Fn(params, Box::new(ret)))
+    }
+
+    // a constructor like Shape::Rect(1.0, 2.0), unit variants have no args
+    fn check_variant(
+        &mut self,
+        e: &'a SEnum,
+        variant: &str,
+        args: Vec<(Option<LType>, Span)>,
+    ) -> Option<LType> {
+        let payload = self.variant_payload(e, variant)?;
+        if payload.len() != args.len() {
+            self.error(CheckErrorReason::VariantArity {
+                ident: format!("{}::{}", e.ident, variant),
+                expected: payload.len(),
+                found: args.len(),
+            });
+        } else {
+            for (typ, (arg, span)) in payload.iter().zip(args) {
+                if let Some(t) = arg {
+                    self.at(span, |c| c.expect_type(typ, t));
+                }
+            }
+        }
+        Some(LType::Named(e.ident.clone()))
+    }
+
+    fn variant_payload(&mut self, e: &'a SEnum, variant: &str) -> Option<&'a [LType]> {
+        let found = e.variants.iter().find(|(v, _)| v == variant);
+        if found.is_none() {
+            self.error(CheckErrorReason::UnknownVariant {
+                ident: e.ident.clone(),
+                variant: variant.to_string(),
+            });
+        }
+        found.map(|(_, payload)| payload.as_slice())
+    }
+
+    // the arms of a match statement or expression, each in its own scope with
+    // the bindings of its pattern, body checks an arm and gives its type
+    fn check_match<B>(
+        &mut self,
+        scrutinee: &'a Expr,
+        arms: &'a [Arm<B>],
+        mut body: impl FnMut(&mut Self, &'a B) -> Option<LType>,
+    ) -> Vec<Option<LType>> {
+        let typ = self.check_expr(scrutinee);
+        let mut bad_pattern = false;
+        let mut types = Vec::new();
+        for arm in arms {
+            self.scopes.push(Scope::default());
+            if let Some(t) = &typ {
+                let errors = self.errors.len();
+                self.at(arm.span, |c| c.check_pattern(&arm.pattern, t));
+                bad_pattern |= self.errors.len() > errors;
+            }
+            if let Some(guard) = &arm.guard {
+                self.check_cond(guard);
+            }
+            types.push(body(self, &arm.body));
+            self.scopes.pop();
+        }
+
+        // patterns that did not check would only add confusing reports
+        let Some(typ) = typ.filter(|_| !bad_pattern) else {
+            return types;
+        };
+        let patterns: Vec<(&Pattern, bool)> = arms
+            .iter()
+            .map(|a| (&a.pattern, a.guard.is_some()))
+            .collect();
+        let report = analysis::check_match(&typ, &patterns, |ident| self.lookup_enum(ident));
+        for i in report.unreachable {
+            self.at(arms[i].span, |c| c.error(CheckErrorReason::UnreachableArm));
+        }
+        // the match itself is what leaves values out
+        if !report.missing.is_empty() {
+            self.error(CheckErrorReason::NonExhaustive(report.missing));
+        }
+        types
+    }
+
+    // a pattern against values of typ, bindings go in the current scope
+    fn check_pattern(&mut self, pattern: &'a Pattern, typ: &LType) {
+        match pattern {
+            Pattern::Wildcard => {}
+            Pattern::Binding(ident) => {
+                if self
+                    .scope()
+                    .vars
+                    .insert(ident.clone(), typ.clone())
+                    .is_some()
+                {
+                    self.error(CheckErrorReason::Redefinition(ident.clone()));
+                }
+            }
+            Pattern::Literal(e) => self.check_value(e, typ),
+            Pattern::Variant(v) => {
+                let Some(e) = self.lookup_enum(&v.ident) else {
+                    self.error(CheckErrorReason::UndefinedType(v.ident.clone()));
+                    return;
+                };
+                let found = LType::Named(e.ident.clone());
+                if *typ != found {
+                    self.expect_type(typ, found);
+                    return;
+                }
+                let Some(payload) = self.variant_payload(e, &v.variant) else {
+                    return;
+                };
+                if payload.len() != v.fields.len() {
+                    self.error(CheckErrorReason::VariantArity {
+                        ident: format!("{}::{}", v.ident, v.variant),
+                        expected: payload.len(),
+                        found: v.fields.len(),
+                    });
+                    return;
+                }
+                for (p, t) in v.fields.iter().zip(payload) {
+                    self.check_pattern(p, t);
+                }
+            }
+        }
+    }
+
+    fn check_struct_literal(&mut self, s: &'a StructLiteral) -> Option<LType> {
+        let def = self.lookup_struct(&s.ident);
+        let values: Vec<Option<LType>> = s
+            .fields
+            .iter()
+            .map(|init| {
+                let hint = def
+                    .and_then(|d| d.fields.iter().find(|(_, f)| *f == init.ident))
+                    .map(|(t, _)| t);
+                self.check_expr_hint(&init.value, hint)
+            })
+            .collect();
+        let Some(def) = def else {
+            self.error(CheckErrorReason::UndefinedType(s.ident.clone()));
+            return None;
+        };
+        let typ = LType::Named(s.ident.clone());
+
+        let mut seen = HashSet::new();
+        for (init, value) in s.fields.iter().zip(values) {
+            let Some((field_type, _)) = def.fields.iter().find(|(_, f)| *f == init.ident) else {
+                self.error(CheckErrorReason::NoField {
+                    typ: typ.clone(),
+                    field: init.ident.clone(),
+                });
+                continue;
+            };
+            if !seen.insert(&init.ident) {
+                self.error(CheckErrorReason::Redefinition(format!(
+                    "{}.{}",
+                    s.ident, init.ident
+                )));
+            }
+            if let Some(t) = value {
+                self.at(init.value.span, |c| c.expect_type(field_type, t));
+            }
+        }
+        let missing: Vec<String> = def
+            .fields
+            .iter()
+            .filter(|(_, f)| !seen.contains(f))
+            .map(|(_, f)| f.clone())
+            .collect();
+        if !missing.is_empty() {
+            self.error(CheckErrorReason::MissingFields {
+                ident: s.ident.clone(),
+                fields: missing,
+            });
+        }
+        Some(typ)
+    }
+
+    // the type of field on a value of typ
+    fn field_type(&mut self, typ: &LType, field: &str) -> Option<LType> {
+        let found = match typ {
+            LType::Named(ident) => self
+                .lookup_struct(ident)
+                .and_then(|s| s.fields.iter().find(|(_, f)| f == field))
+                .map(|(t, _)| t.clone()),
+            _ => None,
+        };
+        if found.is_none() {
+            self.error(CheckErrorReason::NoField {
+                typ: typ.clone(),
+                field: field.to_string(),
+            });
+        }
+        found
+    }
+
+    // the element type of base[index], constant indices are bounds checked
+    fn index_type(&mut self, base: &LType, index: &'a Expr) -> Option<LType> {
+        // any integer type indexes
+        if let Some(t) = self.check_expr(index)
+            && !matches!(t, LType::Int(_))
+        {
+            self.expect_type(&INT, t);
+        }
+        let LType::Array(elem, len) = base else {
+            self.error(CheckErrorReason::NotIndexable(base.clone()));
+            return None;
+        };
+        if let Some(i) = const_int(index)
+            && !usize::try_from(i).is_ok_and(|i| i < *len)
+        {
+            self.error(CheckErrorReason::IndexOutOfBounds {
+                index: i,
+                len: *len,
+            });
+        }
+        Some(elem.as_ref().clone())
+    }
+
+    fn place_type(&mut self, place: &'a Place) -> Option<LType> {
+        let mut typ = self.lookup_var(&place.ident)?;
+        for access in &place.path {
+            typ = match access {
+                Access::Field(field) => self.field_type(&typ, field)?,
+                Access::Index(index) => self.index_type(&typ, index)?,
+            };
+        }
+        Some(typ)
+    }
+
+    // None when the expression has an error that was already reported
+    fn check_expr(&mut self, expr: &'a Expr) -> Option<LType> {
+        self.check_expr_hint(expr, None)
+    }
+
+    // an expression that must have type expected
+    fn check_value(&mut self, expr: &'a Expr, expected: &LType) {
+        if let Some(t) = self.check_expr_hint(expr, Some(expected)) {
+            self.at(expr.span, |c| c.expect_type(expected, t));
+        }
+    }
+
+    // records the type of a literal for mono to write into it
+    fn literal(&mut self, expr: &Expr, typ: LType) -> LType {
+        self.instances
+            .literals
+            .insert((expr.id, self.instance), typ.clone());
+        typ
+    }
+
+    // hint is the type the context wants, unsuffixed literals take it when
+    // they can and are int or float otherwise
+    fn check_expr_hint(&mut self, expr: &'a Expr, hint: Option<&LType>) -> Option<LType> {
+        self.at(expr.span, |c| c.check_expr_kind(expr, hint))
+    }
+
+    fn check_expr_kind(&mut self, expr: &'a Expr, hint: Option<&LType>) -> Option<LType> {
+        match &expr.kind {
+            ExprKind::Intermediate(value, suffix) => {
+                let typ = match (suffix, hint) {
+                    (Some(t), _) | (None, Some(LType::Int(t))) => *t,
+                    _ => IntType::I64,
+                };
+                if !typ.contains(*value) {
+                    self.error(CheckErrorReason::LiteralOutOfRange {
+                        value: *value,
+                        typ: LType::Int(typ),
+                    });
+                }
+                Some(self.literal(expr, LType::Int(typ)))
+            }
+            ExprKind::FloatIntermediate(_, suffix) => {
+                let typ = match (suffix, hint) {
+                    (Some(t), _) | (None, Some(LType::Float(t))) => *t,
+                    _ => FloatType::F64,
+                };
+                Some(self.literal(expr, LType::Float(typ)))
+            }
+            // a function declaration named without a call is a value
+            ExprKind::Ident(s) => match self.lookup_variant_enum(s) {
+                Some((e, variant)) => self.check_variant(e, variant, Vec::new()),
+                None => match (self.find_var(s), self.lookup_fn(s)) {
+                    (None, Some(f)) if !f.generics.is_empty() => {
+                        self.check_generic_value(expr, f, hint)
+                    }
+                    (None, Some(f)) => Some(self.fn_type(f)),
+                    _ => self.lookup_var(s),
+                },
+            },
+            ExprKind::StringLiteral(_) => Some(LType::String),
+            ExprKind::CharLiteral(_) => Some(LType::Char),
+            ExprKind::BoolLiteral(_) => Some(LType::Bool),
+            ExprKind::Call(c) => self.check_call(expr, &c.ident, &c.params),
+            ExprKind::Lambda(l) => self.check_lambda(l),
+            ExprKind::Apply(a) => {
+                let f = self.check_expr(&a.f);
+                let hints = match &f {
+                    Some(LType::Fn(params, _)) => params.clone(),
+                    _ => Vec::new(),
+                };
+                let args: Vec<(Option<LType>, Span)> = a
+                    .params
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| (self.check_expr_hint(p, hints.get(i)), p.span))
+                    .collect();
+                let f = f?;
+                self.check_apply(&f.to_string(), f, args)
+            }
+            ExprKind::Block(b) => self.check_block_expr(b, hint),
+            ExprKind::StructLiteral(s) => self.check_struct_literal(s),
+            ExprKind::Field(a) => {
+                let base = self.check_expr(&a.base)?;
+                self.field_type(&base, &a.field)
+            }
+            ExprKind::ArrayLiteral(items) => {
+                let Some((first, rest)) = items.split_first() else {
+                    self.error(CheckErrorReason::EmptyArray);
+                    return None;
+                };
+                // the first element types the rest
+                let hint = match hint {
+                    Some(LType::Array(elem, _)) => Some(elem.as_ref()),
+                    _ => None,
+                };
+                let elem = self.check_expr_hint(first, hint);
+                let types: Vec<Option<LType>> = rest
+                    .iter()
+                    .map(|e| self.check_expr_hint(e, elem.as_ref()))
+                    .collect();
+                let elem = elem?;
+                for t in types.into_iter().flatten() {
+                    self.expect_type(&elem, t);
+                }
+                Some(LType::Array(Box::new(elem), items.len()))
+            }
+            ExprKind::Index(i) => {
+                let base = self.check_expr(&i.base)?;
+                self.index_type(&base, &i.index)
+            }
+            ExprKind::Match(m) => {
+                // the first arm types the rest when nothing else does
+                let mut hint = hint.cloned();
+                let types = self.check_match(&m.scrutinee, &m.arms, |c, body| {
+                    let t = c.check_expr_hint(body, hint.as_ref());
+                    if hint.is_none() {
+                        hint = t.clone();
+                    }
+                    t
+                });
+                let mut types = types.into_iter();
+                let first = types.next()??;
+                for t in types.flatten() {
+                    self.expect_type(&first, t);
+                }
+                Some(first)
+            }
+            ExprKind::If(i) => {
+                self.check_cond(&i.guard);
+                let t = self.check_expr_hint(&i.t, hint);
+                let f = self.check_expr_hint(&i.f, t.as_ref().or(hint));
+                let (t, f) = (t?, f?);
+                self.expect_type(&t, f);
+                Some(t)
+            }
+            ExprKind::Unary(u) => {
+                let t = self.check_expr_hint(&u.x, hint)?;
+                let ok = match u.op {
+                    UOp::Neg => {
+                        matches!(t, LType::Int(i) if i.is_signed()) || matches!(t, LType::Float(_))
+                    }
+                    UOp::Pos => t.is_numeric(),
+                    UOp::Not => t == LType::Bool,
+                    UOp::BitNot => matches!(t, LType::Int(_)),
+                };
+                self.expect_operand(u.op.to_string(), &t, ok);
+                Some(t)
+            }
+            ExprKind::Cast(c) => {
+                let from = self.check_expr(&c.x)?;
+                let to = self.concrete(&c.typ);
+                self.check_type(&to);
+                let ok = match (&from, &to) {
+                    (f, t) if f.is_numeric() && t.is_numeric() => true,
+                    (LType::Bool | LType::Char, LType::Int(_)) => true,
+                    // only a byte is always a valid char
+                    (LType::Int(IntType::U8), LType::Char) => true,
+                    _ => false,
+                };
+                if !ok {
+                    self.error(CheckErrorReason::InvalidCast {
+                        from,
+                        to: to.clone(),
+                    });
+                }
+                Some(to)
+            }
+            // both sides of && and || are conditions
+            ExprKind::Binop(b) if b.op.is_logical() => {
+                self.check_cond(&b.a);
+                self.check_cond(&b.b);
+                Some(LType::Bool)
+            }
+            ExprKind::Binop(b) => {
+                // a comparison gives its operands no type
+                let hint = hint.filter(|_| !b.op.is_comparison());
+                // the left side gives the type, except in 1 + x where x
+                // types the literal
+                let (first, second) = if is_untyped(&b.a) && !is_untyped(&b.b) {
+                    (&b.b, &b.a)
+                } else {
+                    (&b.a, &b.b)
+                };
+                let a = self.check_expr_hint(first, hint);
+                let c = self.check_expr_hint(second, a.as_ref());
+                let (a, c) = (a?, c?);
+                // one error for the operands, the binop then has no type
+                if a != c {
+                    self.at(second.span, |s| s.expect_type(&a, c));
+                    return None;
+                }
+                self.expect_operand(b.op.to_string(), &a, Self::supports(&b.op, &a));
+                if b.op.is_comparison() {
+                    Some(LType::Bool)
+                } else {
+                    Some(a)
+                }
+            }
+        }
+    }
+
+    // if, loop, ?: and logical conditions take no ints
+    fn check_cond(&mut self, cond: &'a Expr) {
+        if let Some(t) = self.check_expr(cond)
+            && t != LType::Bool
+        {
+            self.at(cond.span, |c| {
+                c.error(CheckErrorReason::NonBoolCondition(t))
+            });
+        }
+    }
+
+    // struct and enum types must be declared
+    fn check_type(&mut self, typ: &LType) {
+        match typ {
+            LType::Named(ident) if self.lookup_type(ident).is_none() => {
+                self.error(CheckErrorReason::UndefinedType(ident.clone()));
+            }
+            LType::Array(elem, _) => self.check_type(elem),
+            LType::Fn(params, ret) => {
+                for typ in params {
+                    self.check_type(typ);
+                }
+                self.check_type(ret);
+            }
+            _ => {}
+        }
+    }
+
+    // whether a variable of typ can be declared without a value, seen
+    // breaks cycles through enums
+    fn has_default(&self, typ: &LType, seen: &mut HashSet<String>) -> bool {
+        match typ {
+            LType::Fn(..) => false,
+            LType::Array(elem, _) => self.has_default(elem, seen),
+            LType::Named(ident) if seen.insert(ident.clone()) => match self.lookup_type(ident) {
+                Some(TypeDef::Struct(s)) => s.fields.iter().all(|(t, _)| self.has_default(t, seen)),
+                // the first variant is the default
+                Some(TypeDef::Enum(e)) => e
+                    .variants
+                    .first()
+                    .is_none_or(|(_, payload)| payload.iter().all(|t| self.has_default(t, seen))),
+                None => true,
+            },
+            _ => true,
+        }
+    }
+
+    // whether a value of typ holds a target by value, seen breaks other cycles
+    fn contains_type(&self, typ: &LType, target: &str, seen: &mut HashSet<String>) -> bool {
+        match typ {
+            LType::Array(elem, _) => self.contains_type(elem, target, seen),
+            LType::Named(ident) if ident == target => true,
+            LType::Named(ident) if seen.insert(ident.clone()) => match self.lookup_type(ident) {
+                Some(TypeDef::Struct(s)) => s
+                    .fields
+                    .iter()
+                    .any(|(t, _)| self.contains_type(t, target, seen)),
+                Some(TypeDef::Enum(e)) => e
+                    .variants
+                    .iter()
+                    .flat_map(|(_, payload)| payload)
+                    .any(|t| self.contains_type(t, target, seen)),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn expect_type(&mut self, expected: &LType, found: LType) {
+        if *expected != found {
+            self.error(CheckErrorReason::TypeMismatch {
+                expected: expected.clone(),
+                found,
+            });
+        }
+    }
+
+    // whether a binary op applies to two operands of typ
+    fn supports(op: &Op, typ: &LType) -> bool {
+        match typ {
+            LType::Bool => matches!(op, Op::Eq | Op::Ne),
+            LType::Int(_) => !op.is_logical(),
+            LType::Float(_) => !op.is_integral() && !op.is_logical(),
+            LType::Char => op.is_comparison(),
+            // + concatenates
+            LType::String => matches!(op, Op::Add | Op::Eq | Op::Ne),
+            // compared field by field or element by element
+            LType::Named(_) | LType::Array(..) => matches!(op, Op::Eq | Op::Ne),
+            LType::Fn(..) => false,
+        }
+    }
+
+    fn expect_operand(&mut self, op: String, found: &LType, ok: bool) {
+        if !ok {
+            self.error(CheckErrorReason::InvalidOperand {
+                op,
+                found: found.clone(),
+            });
+        }
+    }
+
+    fn scope(&mut self) -> &mut Scope<'a> {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn find_var(&self, ident: &str) -> Option<LType> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.vars.get(ident))
+            .cloned()
+    }
+
+    fn lookup_var(&mut self, ident: &str) -> Option<LType> {
+        let found = self.find_var(ident);
+        if found.is_none() {
+            self.error(CheckErrorReason::UndefinedVariable(ident.to_string()));
+        }
+        found
+    }
+
+    fn lookup_type(&self, ident: &str) -> Option<TypeDef<'a>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.types.get(ident).copied())
+    }
+
+    fn lookup_struct(&self, ident: &str) -> Option<&'a SStruct> {
+        match self.lookup_type(ident)? {
+            TypeDef::Struct(s) => Some(s),
+            TypeDef::Enum(_) => None,
+        }
+    }
+
+    fn lookup_enum(&self, ident: &str) -> Option<&'a SEnum> {
+        match self.lookup_type(ident)? {
+            TypeDef::Enum(e) => Some(e),
+            TypeDef::Struct(_) => None,
+        }
+    }
+
+    // the enum of a constructor path like Shape::Circle, with the variant
+    fn lookup_variant_enum<'p>(&self, path: &'p str) -> Option<(&'a SEnum, &'p str)> {
+        let (ident, variant) = path.rsplit_once("::")?;
+        Some((self.lookup_enum(ident)?, variant))
+    }
+
+    fn lookup_fn(&self, ident: &str) -> Option<&'a SFunction> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.fns.get(ident).copied())
+    }
+}
+
+// binds the type parameters in param so it matches arg, false when no
+// binding does
+fn unify(
+    param: &LType,
+    arg: &LType,
+    generics: &[String],
+    bound: &mut HashMap<String, LType>,
+) -> bool {
+    match (param, arg) {
+        (LType::Named(t), _) if generics.contains(t) => match bound.get(t) {
+            Some(b) => b == arg,
+            None => {
+                bound.insert(t.clone(), arg.clone());
+                true
+            }
+        },
+        (LType::Array(p, n), LType::Array(a, m)) => n == m && unify(p, a, generics, bound),
+        (LType::Fn(ps, pr), LType::Fn(args, ar)) => {
+            ps.len() == args.len()
+                && ps
+                    .iter()
+                    .zip(args)
+                    .all(|(p, a)| unify(p, a, generics, bound))
+                && unify(pr, ar, generics, bound)
+        }
+        _ => param == arg,
+    }
+}
+
+// typ once every type parameter in it is bound
+fn closed(typ: &LType, generics: &[String], bound: &HashMap<String, LType>) -> Option<LType> {
+    generics
+        .iter()
+        .all(|g| !mentions(typ, g) || bound.contains_key(g))
+        .then(|| mono::substitute(typ, bound))
+}
+
+// whether typ uses the type parameter param
+fn mentions(typ: &LType, param: &str) -> bool {
+    match typ {
+        LType::Named(ident) => ident == param,
+        LType::Array(elem, _) => mentions(elem, param),
+        LType::Fn(params, ret) => params.iter().any(|t| mentions(t, param)) || mentions(ret, param),
+        _ => false,
+    }
+}
+
+// a literal with no suffix, or arithmetic on only those
+fn is_untyped(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Intermediate(_, None) | ExprKind::FloatIntermediate(_, None) => true,
+        ExprKind::Unary(u) => !matches!(u.op, UOp::Not) && is_untyped(&u.x),
+        ExprKind::Binop(b) => {
+            !b.op.is_comparison() && !b.op.is_logical() && is_untyped(&b.a) && is_untyped(&b.b)
+        }
+        _ => false,
+    }
+}
+
+// the value of an integer expression made of literals, for bounds checks
+fn const_int(expr: &Expr) -> Option<i128> {
+    match &expr.kind {
+        ExprKind::Intermediate(i, _) => Some(*i),
+        ExprKind::Unary(u) => {
+            let x = const_int(&u.x)?;
+            match u.op {
+                UOp::Neg => x.checked_neg(),
+                UOp::Pos => Some(x),
+                UOp::BitNot => Some(!x),
+                UOp::Not => None,
+            }
+        }
+        ExprKind::Binop(b) => {
+            let (x, y) = (const_int(&b.a)?, const_int(&b.b)?);
+            match b.op {
+                Op::Add => x.checked_add(y),
+                Op::Sub => x.checked_sub(y),
+                Op::Mul => x.checked_mul(y),
+                Op::Div => x.checked_div(y),
+                Op::Mod => x.checked_rem(y),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckErrorReason, check};
+    use crate::parser::Parser;
+
+    #[test]
+    fn check_fib() {
+        let source = include_str!("ro/fib.ro");
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+
+        check(&stmts).expect("fib.ro should type check");
+    }
+
+    #[test]
+    fn check_undefined() {
+        let stmts = Parser::new("int a = b; float c = a;".chars())
+            .parse_program()
+            .unwrap();
+
+        let errors = check(&stmts).unwrap_err();
+        assert!(matches!(
+            errors[0].reason,
+            CheckErrorReason::UndefinedVariable(_)
+        ));
+        assert!(matches!(
+            errors[1].reason,
+            CheckErrorReason::TypeMismatch { .. }
+        ));
+        // each error points at the expression it is about
+        let spans: Vec<(u32, u32)> = errors.iter().map(|e| (e.span.start, e.span.end)).collect();
+        assert_eq!(spans, [(8, 9), (21, 22)]);
+    }
+
+    #[test]
+    fn check_integral_ops() {
+        let stmts = Parser::new("float a = 1.5 % 2.0; bool b = !(a < 1.0) && a < 2.0;".chars())
+            .parse_program()
+            .unwrap();
+
+        let errors = check(&stmts).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].reason,
+            CheckErrorReason::InvalidOperand { .. }
+        ));
+    }
+
+    #[test]
+    fn check_step() {
+        let stmts = Parser::new("float f = 1.5; f++; f32 g; g--; u8 b = 255; b++;".chars())
+            .parse_program()
+            .unwrap();
+        check(&stmts).expect("numbers should step");
+
+        let stmts = Parser::new("bool b; b++; string s; s--;".chars())
+            .parse_program()
+            .unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "cannot apply `++` to a value of type `bool`",
+                "cannot apply `--` to a value of type `string`",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_text() {
+        let source = "string s = \"a\" + \"b\"; char c = 'c'; bool t = c < 'd' && s == \"ab\"; s -= \"b\"; char n = -c;";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+
+        let errors = check(&stmts).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        for e in errors {
+            assert!(matches!(e.reason, CheckErrorReason::InvalidOperand { .. }));
+        }
+
+        // one error for mixed operands, at the side that does not match
+        let source = "int x = \"é\" + 1; string s = 1 + \"a\";";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "mismatched types: expected `string`, found `int`",
+                "mismatched types: expected `string`, found `int`",
+            ]
+        );
+        let at = |i: usize| &source[errors[i].span.start as usize..errors[i].span.end as usize];
+        assert_eq!((at(0), at(1)), ("1", "1"));
+    }
+
+    #[test]
+    fn check_value_exprs() {
+        let source =
+            "int a = if true { 2 } else { 3 }; float b = a > 0 ? 1.0 : 2.0; int c = { a };";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        check(&stmts).expect("value expressions should check");
+
+        let source = "
+            int a = true ? 2 : 3.0;
+            int b = { a = 1; };
+            while (true) { int c = { break; 1 }; int d = { loop { break; } 1 }; }
+            fn f() -> int { return { return 1; 2 }; };
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "mismatched types: expected `int`, found `float`",
+                "block expression has no value, end it with an expression",
+                "`break` cannot leave a block expression",
+                "`return` cannot leave a block expression",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_conditions() {
+        let source = "bool b = 1 < 2 && !false; while (b) { b = b == false; } int a = b ? 1 : 0;";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        check(&stmts).expect("boolean conditions should check");
+
+        let source =
+            "int a = 1; while (a) { a--; } int b = a ? 1 : 0; bool c = a && true; int d = a < 2;";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "condition must be `bool`, found `int`",
+                "condition must be `bool`, found `int`",
+                "condition must be `bool`, found `int`",
+                "mismatched types: expected `int`, found `bool`",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_structs() {
+        let source = "
+            struct Line { Point a; Point b; };
+            struct Point { int x; float y; };
+            fn shift(Point p, int dx) -> Point { p.x += dx; return p; };
+            Line l = Line { a: Point { x: 1, y: 2.0 }, b: shift(Point { y: 0.0, x: 0 }, 3) };
+            l.b.y = l.a.y * 2.0;
+            bool same = l.a == l.b;
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        check(&stmts).expect("structs should check");
+
+        let source = "
+            struct Point { int x; float y; };
+            struct Node { int v; Node next; };
+            Point p = Point { x: 1.0, z: 2 };
+            p.z = 1;
+            int y = p.y;
+            Shape s;
+            int n = y.x;
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "type `Node` contains itself and has no finite size",
+                "mismatched types: expected `int`, found `float`",
+                "no field `z` on type `Point`",
+                "missing fields `y` in initializer of `Point`",
+                "no field `z` on type `Point`",
+                "mismatched types: expected `int`, found `float`",
+                "cannot find type `Shape`",
+                "no field `x` on type `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_arrays() {
+        let source = "
+            fn sum(int[3] xs) -> int {
+                int total = 0;
+                for (int i = 0; i < 3; i++) { total += xs[i]; }
+                return total;
+            };
+            int[3] a = [1, 2, 3];
+            a[2] = sum(a) + a[1 + 1];
+            float[2][2] m;
+            m[1][0] = 1.5;
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        check(&stmts).expect("arrays should check");
+
+        let source = "
+            int[3] a = [1, 2.0];
+            a[3] = 1;
+            int b = a[-1] + a[2 * 2 - 2];
+            int c = b[0];
+            float f = a[0.5];
+            int[2] e = [];
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "mismatched types: expected `int`, found `float`",
+                "mismatched types: expected `int[3]`, found `int[2]`",
+                "index out of bounds: the length is 3 but the index is 3",
+                "index out of bounds: the length is 3 but the index is -1",
+                "cannot index into a value of type `int`",
+                "mismatched types: expected `int`, found `float`",
+                "mismatched types: expected `float`, found `int`",
+                "cannot infer the type of an empty array literal",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_match() {
+        let source = "
+            enum Shape { Circle(float), Rect(float, float), Empty };
+            fn area(Shape s) -> float {
+                return match s {
+                    Shape::Circle(r) => 3.0 * r * r,
+                    Shape::Rect(w, h) if w > 0.0 => w * h,
+                    _ => 0.0,
+                };
+            };
+            Shape s = Shape::Rect(2.0, 3.0);
+            match s {
+                Shape::Empty => { s = Shape::Circle(1.0); }
+                other => { float a = area(other); }
+            };
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        check(&stmts).expect("match should check");
+
+        let source = "
+            enum Shape { Circle(float), Rect(float, float), Empty };
+            enum Dup { A, A };
+            enum List { Nil, Cons(int, List) };
+            Shape s = Shape::Rect(1.0);
+            Shape t = Shape::Square;
+            float x = match s {
+                Shape::Circle(r) => r,
+                Shape::Rect(w, h) if w > h => w,
+            };
+            int n = match 3 { 1 => 1, x => x, 2 => 2 };
+            match s { Shape::Circle(1) => { } _ => { } };
+            bool b = match true { true => false };
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "`Dup::A` is defined twice",
+                "type `List` contains itself and has no finite size",
+                "variant `Shape::Rect` has 2 fields but 1 were supplied",
+                "no variant `Square` in enum `Shape`",
+                "non-exhaustive patterns: `Shape::Rect(_, _)`, `Shape::Empty` not covered",
+                "unreachable pattern: the arms before it cover its values",
+                "mismatched types: expected `float`, found `int`",
+                "non-exhaustive patterns: `false` not covered",
+            ]
+        );
+        // a missing value is the match's fault, an unreachable arm its own
+        let at = |i: usize| &source[errors[i].span.start as usize..errors[i].span.end as usize];
+        assert!(at(4).starts_with("match s {"));
+        assert_eq!(at(5), "2");
+    }
+
+    #[test]
+    fn check_numeric() {
+        let source = "
+            fn scale(u8 x, f32 k) -> f32 { return x as f32 * k; };
+            u8 a = 255;
+            i16[3] xs = [1, -2, 300];
+            natural n = 18446744073709551615;
+            f32 f = scale(a + 1, 0.5) + 1.0;
+            u16 b = if a > 1 { 1 } else { a as u16 };
+            char c = 65u8 as char;
+            int i = xs[a - 250] as int + 'x' as int + true as int;
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        check(&stmts).expect("numeric types should check");
+
+        let source = "
+            u8 a = 256;
+            int b = 9223372036854775808;
+            i8 c = 1i16;
+            u32 d = -a;
+            float e = 1 as float + 2;
+            char f = 300 as char;
+            string g = 1 as string;
+            int h = a;
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "literal `256` does not fit in `u8`",
+                "literal `9223372036854775808` does not fit in `int`",
+                "mismatched types: expected `i8`, found `i16`",
+                "cannot apply `-` to a value of type `u8`",
+                "mismatched types: expected `u32`, found `u8`",
+                // no implicit int to float
+                "mismatched types: expected `float`, found `int`",
+                "cannot cast `int` to `char`",
+                "cannot cast `int` to `string`",
+                "mismatched types: expected `int`, found `u8`",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_closures() {
+        let source = "
+            struct Button { string label; fn(int) -> int on_click; };
+            fn twice(fn(u8) -> u8 f, u8 x) -> u8 { return f(f(x)); };
+            fn adder(int n) -> fn(int) -> int {
+                return fn(int x) -> int { return x + n; };
+            };
+            fn inc(u8 x) -> u8 { return x + 1; };
+            u8 a = twice(inc, 250);
+            u8 b = twice(fn(u8 x) -> u8 { return x * 2; }, 3);
+            Button ok = Button { label: \"ok\", on_click: adder(1) };
+            int c = ok.on_click(2) + adder(2)(3);
+            fn(int) -> int f = ok.on_click;
+            int d = f(1);
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        check(&stmts).expect("closures should check");
+
+        let source = "
+            int x = 1;
+            x(2);
+            fn(int) -> int f = fn(int a) -> int { return a; };
+            f(1, 2);
+            fn(int) -> bool g = f;
+            fn() -> int h = fn() -> int { return y; };
+            bool same = f == f;
+            fn(int) -> int k;
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "`int` is not a function",
+                "function `f` takes 1 arguments but 2 were supplied",
+                "mismatched types: expected `fn(int) -> bool`, found `fn(int) -> int`",
+                "cannot find variable `y`",
+                "cannot apply `==` to a value of type `fn(int) -> int`",
+                "variable `k` needs a value, `fn(int) -> int` has no default",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_generics() {
+        let source = "
+            fn max<T>(T a, T b) -> T { if a > b { return a; }; return b; };
+            fn apply<T, U>(fn(T) -> U f, T x) -> U { return f(x); };
+            fn inc(int x) -> int { return x + 1; };
+            u8 small = 3;
+            u8 a = max(small, 200);
+            float b = max(1.5, 2.0);
+            int c = apply(inc, 41);
+            fn(char, char) -> char m = max;
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let instances = check(&stmts).expect("generics should check");
+        let idents: Vec<String> = instances.list.iter().map(|i| i.ident()).collect();
+        assert_eq!(
+            idents,
+            vec!["max<u8>", "max<float>", "apply<int, int>", "max<char>"]
+        );
+
+        let source = "
+            fn max<T>(T a, T b) -> T { if a > b { return a; }; return b; };
+            fn make<T>() -> T { return 0; };
+            fn grow<T>(T x) -> int { return grow([x]); };
+            fn outer() -> int {
+                fn inner<T>(T x) -> T { return x; };
+                return 0;
+            };
+            int a = max(1, 2.0);
+            int b = max(1);
+            fn(int) -> int c = max;
+            int d = grow(1);
+            bool e = max(true, false);
+            print(max);
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        let reasons: Vec<String> = errors.iter().map(|e| e.reason.to_string()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "type parameter `T` of `make` is not used by its parameters",
+                "generic function `inner` must be declared at the top level",
+                "mismatched types: expected `int`, found `float`",
+                "function `max` takes 2 arguments but 1 were supplied",
+                "mismatched types: expected `fn(int) -> int`, found `fn(T, T) -> T`",
+                "instances of `grow` never end, each one needs a bigger one",
+                "cannot apply `>` to a value of type `bool`",
+                "cannot infer the type arguments of `max`, call it or use it where a function type is expected",
+            ]
+        );
+        // an error inside an instance says which one and points into the
+        // generic function, endless instances point at the function
+        assert_eq!(errors[6].notes, vec!["in `max` with `T = bool`"]);
+        let at = |i: usize| &source[errors[i].span.start as usize..errors[i].span.end as usize];
+        assert_eq!(at(6), "a > b");
+        assert_eq!(at(5), "grow");
+
+        // the types of deeper instances are meaningless, so their errors
+        // are not reported on top of the endless one
+        let source = "fn r<T>(T a) -> T { return r([a]); }; r(1);";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let errors = check(&stmts).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].reason.to_string(),
+            "instances of `r` never end, each one needs a bigger one"
+        );
+    }
+}
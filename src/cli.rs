@@ -0,0 +1,230 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::diag::{ColorChoice, ErrorFormat};
+
+pub const USAGE: &str = "\
+usage: ro <command> [options] [FILE...]
+
+commands:
+    lex                 print the token stream
+    parse               check that the input parses
+    check               parse and type check the input
+    run                 interpret the input
+    emit --stage=STAGE  print an intermediate stage (ast)
+
+options:
+    --error-format=FMT  how to print errors (human, json)
+    --color=WHEN        color errors (auto, always, never)
+    -I DIR              also look for imported modules in DIR
+
+FILE defaults to stdin, `-` also reads stdin";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Lex,
+    Parse,
+    Check,
+    Run,
+    Emit(Stage),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Stage {
+    Ast,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub inputs: Vec<Input>,
+    pub error_format: ErrorFormat,
+    pub color: ColorChoice,
+    // where imports are looked for after the importer's directory
+    pub search: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    Help,
+    MissingCommand,
+    MissingSearchDir,
+    MissingStage,
+    UnknownColor(String),
+    UnknownCommand(String),
+    UnknownErrorFormat(String),
+    UnknownOption(String),
+    UnknownStage(String),
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Stdin => write!(f, "stdin"),
+            Input::File(path) => write!(f, "`{}`", path.display()),
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "help requested"),
+            CliError::MissingCommand => write!(f, "no command given"),
+            CliError::MissingSearchDir => write!(f, "`-I` needs a directory"),
+            CliError::MissingStage => write!(f, "`emit` needs a `--stage`"),
+            CliError::UnknownColor(s) => write!(f, "unknown color choice `{s}`"),
+            CliError::UnknownCommand(s) => write!(f, "unknown command `{s}`"),
+            CliError::UnknownErrorFormat(s) => write!(f, "unknown error format `{s}`"),
+            CliError::UnknownOption(s) => write!(f, "unknown option `{s}`"),
+            CliError::UnknownStage(s) => write!(f, "unknown stage `{s}`"),
+        }
+    }
+}
+
+impl Stage {
+    fn lookup(s: &str) -> Result<Stage, CliError> {
+        match s {
+            "ast" => Ok(Stage::Ast),
+            _ => Err(CliError::UnknownStage(s.to_string())),
+        }
+    }
+}
+
+fn lookup_error_format(s: &str) -> Result<ErrorFormat, CliError> {
+    match s {
+        "human" => Ok(ErrorFormat::Human),
+        "json" => Ok(ErrorFormat::Json),
+        _ => Err(CliError::UnknownErrorFormat(s.to_string())),
+    }
+}
+
+fn lookup_color(s: &str) -> Result<ColorChoice, CliError> {
+    match s {
+        "auto" => Ok(ColorChoice::Auto),
+        "always" => Ok(ColorChoice::Always),
+        "never" => Ok(ColorChoice::Never),
+        _ => Err(CliError::UnknownColor(s.to_string())),
+    }
+}
+
+// args should not include the program name
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Options, CliError> {
+    let mut command: Option<String> = None;
+    let mut stage: Option<Stage> = None;
+    let mut inputs: Vec<Input> = Vec::new();
+    let mut error_format = ErrorFormat::Human;
+    let mut color = ColorChoice::Auto;
+    let mut search: Vec<PathBuf> = Vec::new();
+
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Err(CliError::Help);
+        } else if let Some(s) = arg.strip_prefix("--stage=") {
+            stage = Some(Stage::lookup(s)?);
+        } else if arg == "--stage" {
+            let s = args.next().ok_or(CliError::MissingStage)?;
+            stage = Some(Stage::lookup(&s)?);
+        } else if let Some(s) = arg.strip_prefix("--error-format=") {
+            error_format = lookup_error_format(s)?;
+        } else if let Some(s) = arg.strip_prefix("--color=") {
+            color = lookup_color(s)?;
+        } else if arg == "-I" {
+            search.push(PathBuf::from(
+                args.next().ok_or(CliError::MissingSearchDir)?,
+            ));
+        } else if let Some(dir) = arg.strip_prefix("-I") {
+            search.push(PathBuf::from(dir));
+        } else if arg == "-" {
+            inputs.push(Input::Stdin);
+        } else if arg.starts_with('-') {
+            return Err(CliError::UnknownOption(arg));
+        } else if command.is_none() {
+            command = Some(arg);
+        } else {
+            inputs.push(Input::File(PathBuf::from(arg)));
+        }
+    }
+
+    let command = match command.as_deref() {
+        Some("lex") => Command::Lex,
+        Some("parse") => Command::Parse,
+        Some("check") => Command::Check,
+        Some("run") => Command::Run,
+        Some("emit") => Command::Emit(stage.take().ok_or(CliError::MissingStage)?),
+        Some(c) => return Err(CliError::UnknownCommand(c.to_string())),
+        None => return Err(CliError::MissingCommand),
+    };
+    if stage.is_some() {
+        return Err(CliError::UnknownOption("--stage".to_string()));
+    }
+
+    if inputs.is_empty() {
+        inputs.push(Input::Stdin);
+    }
+
+    Ok(Options {
+        command,
+        inputs,
+        error_format,
+        color,
+        search,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> impl Iterator<Item = String> {
+        s.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn parse_emit() {
+        let opts = parse_args(args("emit --stage=ast a.ro -")).unwrap();
+        assert_eq!(opts.command, Command::Emit(Stage::Ast));
+        assert_eq!(
+            opts.inputs,
+            vec![Input::File(PathBuf::from("a.ro")), Input::Stdin]
+        );
+        assert_eq!(opts.error_format, ErrorFormat::Human);
+
+        let opts = parse_args(args("check --error-format=json --color=never")).unwrap();
+        assert_eq!(opts.error_format, ErrorFormat::Json);
+        assert_eq!(opts.color, ColorChoice::Never);
+
+        let opts = parse_args(args("run -I lib -Istd main.ro")).unwrap();
+        assert_eq!(
+            opts.search,
+            vec![PathBuf::from("lib"), PathBuf::from("std")]
+        );
+        assert_eq!(opts.inputs, vec![Input::File(PathBuf::from("main.ro"))]);
+        assert_eq!(opts.inputs[0].to_string(), "`main.ro`");
+        assert_eq!(
+            parse_args(args("check --color=sometimes")),
+            Err(CliError::UnknownColor("sometimes".to_string()))
+        );
+        assert_eq!(
+            CliError::UnknownColor("sometimes".to_string()).to_string(),
+            "unknown color choice `sometimes`"
+        );
+
+        assert_eq!(parse_args(args("emit")), Err(CliError::MissingStage));
+        assert_eq!(
+            parse_args(args("emit --stage=ir")),
+            Err(CliError::UnknownStage("ir".to_string()))
+        );
+        assert_eq!(
+            parse_args(args("run --stage ast")),
+            Err(CliError::UnknownOption("--stage".to_string()))
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use std::fmt::Write;
+
+use crate::source::{FileId, SourceMap, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+// a message with the source it points at, rendered like rustc
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    // the span the message is about, underlined with ^
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    // related spans, underlined with -
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|l| l.primary).map(|l| l.span)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+pub struct Emitter {
+    pub format: ErrorFormat,
+    pub color: bool,
+}
+
+impl Emitter {
+    pub fn new(format: ErrorFormat, color: ColorChoice) -> Self {
+        let color = match color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => std::io::IsTerminal::is_terminal(&std::io::stderr()),
+        };
+        Self { format, color }
+    }
+
+    // writes to stderr, file names the input when nothing has a span
+    pub fn emit(&self, map: &SourceMap, file: FileId, diag: &Diagnostic) {
+        eprintln!("{}", self.render(map, file, diag));
+    }
+
+    pub fn render(&self, map: &SourceMap, file: FileId, diag: &Diagnostic) -> String {
+        match self.format {
+            ErrorFormat::Human => self.render_human(map, file, diag),
+            ErrorFormat::Json => render_json(map, file, diag),
+        }
+    }
+
+    fn paint(&self, color: &str, s: &str) -> String {
+        if self.color {
+            format!("{color}{s}{RESET}")
+        } else {
+            s.to_string()
+        }
+    }
+
+    fn render_human(&self, map: &SourceMap, file: FileId, diag: &Diagnostic) -> String {
+        let sev = diag.severity;
+        let mut out = format!(
+            "{}{}",
+            self.paint(sev.color(), sev.name()),
+            self.paint(BOLD, &format!(": {}", diag.message))
+        );
+
+        let location = diag
+            .primary_span()
+            .or(diag.labels.first().map(|l| l.span))
+            .map_or_else(|| map.get(file).name.clone(), |span| map.location(span));
+
+        // labels grouped by the 0-based line they start on
+        let mut lines: Vec<(FileId, usize, &Label)> = diag
+            .labels
+            .iter()
+            .map(|l| {
+                (
+                    l.span.file,
+                    map.get(l.span.file).line_index(l.span.start),
+                    l,
+                )
+            })
+            .collect();
+        lines.sort_by_key(|&(f, line, l)| (f.0, line, l.span.start));
+
+        let width = lines
+            .iter()
+            .map(|&(_, line, _)| (line + 1).to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = self.paint(BLUE, &format!("{:width$} |", ""));
+
+        let _ = write!(
+            out,
+            "\n{}{location}",
+            self.paint(BLUE, &format!("{:width$}--> ", ""))
+        );
+        if !lines.is_empty() {
+            let _ = write!(out, "\n{gutter}");
+        }
+
+        let mut prev: Option<(FileId, usize)> = None;
+        for &(f, line, label) in &lines {
+            let source = map.get(f);
+            if prev != Some((f, line)) {
+                if let Some((pf, pl)) = prev
+                    && pf == f
+                    && line > pl + 1
+                {
+                    let _ = write!(out, "\n{}", self.paint(BLUE, "..."));
+                }
+                let number = self.paint(BLUE, &format!("{:>width$} |", line + 1));
+                let _ = write!(out, "\n{number} {}", source.line(line));
+            }
+            prev = Some((f, line));
+
+            let text = source.line(line);
+            let start = source.line_col(label.span.start).col as usize - 1;
+            let end_offset = (source.line_col(label.span.start).line
+                == source.line_col(label.span.end).line)
+                .then_some(label.span.end);
+            let len = match end_offset {
+                Some(end) => source.line_col(end).col as usize - 1 - start,
+                // multi-line spans are underlined to the end of the first line
+                None => text.chars().count().saturating_sub(start),
+            };
+            let (mark, color) = if label.primary {
+                ("^", sev.color())
+            } else {
+                ("-", BLUE)
+            };
+            let underline = mark.repeat(len.max(1));
+            // tabs before the span are kept so the marks line up with it
+            let pad: String = text
+                .chars()
+                .take(start)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let mut marker = format!("{pad}{underline}");
+            if !label.message.is_empty() {
+                let _ = write!(marker, " {}", label.message);
+            }
+            let _ = write!(out, "\n{gutter} {}", self.paint(color, &marker));
+        }
+
+        for note in &diag.notes {
+            let _ = write!(
+                out,
+                "\n{:width$} {} {note}",
+                "",
+                self.paint(BOLD, "= note:")
+            );
+        }
+        out
+    }
+}
+
+// one JSON object per diagnostic, for editors and CI
+fn render_json(map: &SourceMap, file: FileId, diag: &Diagnostic) -> String {
+    let spans: Vec<String> = diag
+        .labels
+        .iter()
+        .map(|l| {
+            let source = map.get(l.span.file);
+            let start = source.line_col(l.span.start);
+            let end = source.line_col(l.span.end);
+            format!(
+                "{{\"file\":{},\"byte_start\":{},\"byte_end\":{},\"line_start\":{},\"column_start\":{},\"line_end\":{},\"column_end\":{},\"is_primary\":{},\"label\":{}}}",
+                json_str(&source.name),
+                l.span.start,
+                l.span.end,
+                start.line,
+                start.col,
+                end.line,
+                end.col,
+                l.primary,
+                json_str(&l.message),
+            )
+        })
+        .collect();
+    let notes: Vec<String> = diag.notes.iter().map(|n| json_str(n)).collect();
+    format!(
+        "{{\"severity\":{},\"message\":{},\"file\":{},\"spans\":[{}],\"notes\":[{}]}}",
+        json_str(diag.severity.name()),
+        json_str(&diag.message),
+        json_str(&map.get(file).name),
+        spans.join(","),
+        notes.join(","),
+    )
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorChoice, Diagnostic, Emitter, ErrorFormat};
+    use crate::source::{SourceMap, Span};
+
+    #[test]
+    fn render_human() {
+        let mut map = SourceMap::new();
+        let f = map.add("a.ro", "int a = (1;\nint b = 2;\nint c = 3 4;\n");
+        let diag = Diagnostic::error("expected `)`, found `;`")
+            .with_primary(Span::new(f, 10, 11), "expected `)`")
+            .with_secondary(Span::new(f, 8, 9), "unclosed delimiter")
+            .with_note("parentheses must be balanced");
+
+        let out = Emitter::new(ErrorFormat::Human, ColorChoice::Never).render(&map, f, &diag);
+        assert_eq!(
+            out,
+            "error: expected `)`, found `;`
+ --> a.ro:1:11
+  |
+1 | int a = (1;
+  |         - unclosed delimiter
+  |           ^ expected `)`
+  = note: parentheses must be balanced"
+        );
+
+        let diag = Diagnostic::warning("w")
+            .with_primary(Span::new(f, 33, 34), "")
+            .with_secondary(Span::new(f, 0, 3), "here");
+        let out = Emitter::new(ErrorFormat::Human, ColorChoice::Never).render(&map, f, &diag);
+        assert_eq!(
+            out,
+            "warning: w
+ --> a.ro:3:11
+  |
+1 | int a = (1;
+  | --- here
+...
+3 | int c = 3 4;
+  |           ^"
+        );
+
+        let f = map.add("b.ro", "fn f() {\n\t\tx = 1;\n};\n");
+        let diag =
+            Diagnostic::error("undefined variable `x`").with_primary(Span::new(f, 11, 12), "");
+        let out = Emitter::new(ErrorFormat::Human, ColorChoice::Never).render(&map, f, &diag);
+        assert_eq!(
+            out,
+            "error: undefined variable `x`
+ --> b.ro:2:3
+  |
+2 | \t\tx = 1;
+  | \t\t^"
+        );
+    }
+
+    #[test]
+    fn render_json() {
+        let mut map = SourceMap::new();
+        let f = map.add("a.ro", "x = \"\n");
+        let diag = Diagnostic::error("unterminated \"literal\"")
+            .with_primary(Span::new(f, 4, 5), "")
+            .with_note("a\nb");
+
+        let out = Emitter::new(ErrorFormat::Json, ColorChoice::Never).render(&map, f, &diag);
+        assert_eq!(
+            out,
+            r#"{"severity":"error","message":"unterminated \"literal\"","file":"a.ro","spans":[{"file":"a.ro","byte_start":4,"byte_end":5,"line_start":1,"column_start":5,"line_end":1,"column_end":6,"is_primary":true,"label":""}],"notes":["a\nb"]}"#
+        );
+    }
+}
@@ -0,0 +1,1427 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::source::Span;
+
+// functions provided by the runtime rather than by the program
+pub fn is_builtin(ident: &str) -> bool {
+    matches!(ident, "print")
+}
+
+// numbers carry their type, which the checker gave every literal
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Bool(bool),
+    Int(i128, IntType),
+    Float(f64, FloatType),
+    Char(char),
+    Str(String),
+    Struct(StructValue<'a>),
+    Array(Vec<Value<'a>>),
+    Enum(EnumValue<'a>),
+    Fn(FnValue<'a>),
+}
+
+// a function declaration or a closure, either can be stored and called
+#[derive(Debug, Clone)]
+pub enum FnValue<'a> {
+    Named(&'a SFunction),
+    Closure(Rc<Closure<'a>>),
+}
+
+// an anonymous function with the variables it captured, shared ones alias
+// the scope they were declared in
+#[derive(Debug)]
+pub struct Closure<'a> {
+    lambda: &'a Lambda,
+    env: Scope<'a>,
+}
+
+impl FnValue<'_> {
+    pub fn typ(&self) -> LType {
+        let (params, ret) = match self {
+            FnValue::Named(f) => (&f.params, &f.ret),
+            FnValue::Closure(c) => (&c.lambda.params, &c.lambda.ret),
+        };
+        LType::Fn(
+            params.iter().map(|(t, _)| t.clone()).collect(),
+            Box::new(ret.clone()),
+        )
+    }
+}
+
+// Shape::Rect(1.0, 2.0)
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumValue<'a> {
+    pub ident: String,
+    pub variant: String,
+    pub payload: Vec<Value<'a>>,
+}
+
+// fields are kept in declaration order
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructValue<'a> {
+    pub ident: String,
+    pub fields: Vec<(String, Value<'a>)>,
+}
+
+impl<'a> StructValue<'a> {
+    pub fn field(&self, field: &str) -> Option<&Value<'a>> {
+        self.fields.iter().find(|(f, _)| f == field).map(|(_, v)| v)
+    }
+
+    pub fn field_mut(&mut self, field: &str) -> Option<&mut Value<'a>> {
+        self.fields
+            .iter_mut()
+            .find(|(f, _)| f == field)
+            .map(|(_, v)| v)
+    }
+}
+
+// the checker keeps both sides the same type, so numbers compare by value
+impl PartialEq for Value<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a, _), Value::Int(b, _)) => a == b,
+            (Value::Float(a, _), Value::Float(b, _)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Struct(a), Value::Struct(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Enum(a), Value::Enum(b)) => a == b,
+            // functions are only equal to themselves
+            (Value::Fn(FnValue::Named(a)), Value::Fn(FnValue::Named(b))) => std::ptr::eq(*a, *b),
+            (Value::Fn(FnValue::Closure(a)), Value::Fn(FnValue::Closure(b))) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i, _) => write!(f, "{}", i),
+            Value::Float(x, FloatType::F32) => write!(f, "{:?}", *x as f32),
+            Value::Float(x, FloatType::F64) => write!(f, "{:?}", x),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Struct(s) => {
+                write!(f, "{} {{ ", s.ident)?;
+                for (i, (field, v)) in s.fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, v)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, v) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+            Value::Enum(e) => {
+                write!(f, "{}::{}", e.ident, e.variant)?;
+                if !e.payload.is_empty() {
+                    let payload: Vec<String> = e.payload.iter().map(|v| v.to_string()).collect();
+                    write!(f, "({})", payload.join(", "))?;
+                }
+                Ok(())
+            }
+            Value::Fn(func) => write!(f, "{}", func.typ()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub reason: RuntimeErrorReason,
+    // the innermost statement or expression that failed
+    pub span: Option<Span>,
+}
+
+impl RuntimeError {
+    pub fn new(reason: RuntimeErrorReason) -> Self {
+        Self { reason, span: None }
+    }
+
+    fn at(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum RuntimeErrorReason {
+    ArgumentCount {
+        ident: String,
+        expected: usize,
+        found: usize,
+    },
+    DivisionByZero,
+    EmptyEnum(String),
+    IndexOutOfBounds {
+        index: i128,
+        len: usize,
+    },
+    MissingReturn(String),
+    NoField(String),
+    // no arm of a match took the value
+    NoMatch(String),
+    OutputFailed,
+    ReturnOutsideFunction,
+    ShiftOutOfRange(i128),
+    // calls nested deeper than MAX_CALL_DEPTH
+    StackOverflow,
+    TypeMismatch,
+    UndefinedFunction(String),
+    UndefinedType(String),
+    UndefinedVariable(String),
+}
+
+impl fmt::Display for RuntimeErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeErrorReason::ArgumentCount {
+                ident,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function `{ident}` takes {expected} arguments but {found} were supplied"
+            ),
+            RuntimeErrorReason::DivisionByZero => write!(f, "division by zero"),
+            RuntimeErrorReason::EmptyEnum(ident) => write!(f, "enum `{ident}` has no variants"),
+            RuntimeErrorReason::IndexOutOfBounds { index, len } => write!(
+                f,
+                "index out of bounds: the length is {len} but the index is {index}"
+            ),
+            RuntimeErrorReason::MissingReturn(ident) => {
+                write!(f, "function `{ident}` ended without returning a value")
+            }
+            RuntimeErrorReason::NoField(field) => write!(f, "no field `{field}`"),
+            RuntimeErrorReason::NoMatch(value) => write!(f, "no match arm takes `{value}`"),
+            RuntimeErrorReason::OutputFailed => write!(f, "failed to write output"),
+            RuntimeErrorReason::ReturnOutsideFunction => {
+                write!(f, "`return` outside of a function")
+            }
+            RuntimeErrorReason::ShiftOutOfRange(n) => {
+                write!(f, "shift amount {n} is out of range")
+            }
+            RuntimeErrorReason::StackOverflow => {
+                write!(
+                    f,
+                    "stack overflow: calls nested deeper than {MAX_CALL_DEPTH}"
+                )
+            }
+            RuntimeErrorReason::TypeMismatch => write!(f, "mismatched types"),
+            RuntimeErrorReason::UndefinedFunction(ident) => {
+                write!(f, "cannot find function `{ident}`")
+            }
+            RuntimeErrorReason::UndefinedType(ident) => write!(f, "cannot find type `{ident}`"),
+            RuntimeErrorReason::UndefinedVariable(ident) => {
+                write!(f, "cannot find variable `{ident}`")
+            }
+        }
+    }
+}
+
+// result of executing a statement
+enum Flow<'a> {
+    Next,
+    Return(Value<'a>),
+    // leaves the loop with the label, the innermost when None
+    Break(Option<String>),
+    Continue(Option<String>),
+}
+
+impl<'a> Flow<'a> {
+    // what a loop does after its body ran, None to keep looping
+    fn after_body(self, label: &Option<String>) -> Option<Flow<'a>> {
+        let targets = |l: &Option<String>| l.is_none() || l == label;
+        match self {
+            Flow::Next => None,
+            Flow::Continue(l) if targets(&l) => None,
+            Flow::Break(l) if targets(&l) => Some(Flow::Next),
+            flow => Some(flow),
+        }
+    }
+}
+
+// variables are cells so closures can share them
+#[derive(Debug, Default, Clone)]
+struct Scope<'a> {
+    vars: HashMap<String, Rc<RefCell<Value<'a>>>>,
+    fns: HashMap<String, &'a SFunction>,
+    types: HashMap<String, TypeDef<'a>>,
+}
+
+impl<'a> Scope<'a> {
+    fn define(&mut self, ident: &str, value: Value<'a>) {
+        self.vars
+            .insert(ident.to_string(), Rc::new(RefCell::new(value)));
+    }
+}
+
+// calls nested deeper than this are a runtime error rather than an overflow
+// of the host stack, STACK_SIZE leaves room for them in a debug build
+const MAX_CALL_DEPTH: usize = 5000;
+const STACK_SIZE: usize = 512 << 20;
+
+// runs stmts on a thread with a stack big enough for MAX_CALL_DEPTH calls
+pub fn run<W: Write + Send>(stmts: &[Statement], out: W) -> Result<(), RuntimeError> {
+    std::thread::scope(|s| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(s, || Interpreter::new(out).run(stmts))
+            .expect("the interpreter thread should start")
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    })
+}
+
+// tree walking interpreter, scopes[0] holds the globals
+pub struct Interpreter<'a, W: Write> {
+    scopes: Vec<Scope<'a>>,
+    // calls in progress
+    depth: usize,
+    out: W,
+}
+
+impl<'a, W: Write> Interpreter<'a, W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            scopes: vec![Scope::default()],
+            depth: 0,
+            out,
+        }
+    }
+
+    pub fn run(&mut self, stmts: &'a [Statement]) -> Result<(), RuntimeError> {
+        match self.exec_stmts(stmts)? {
+            Flow::Next => Ok(()),
+            Flow::Return(_) => Err(RuntimeError::new(RuntimeErrorReason::ReturnOutsideFunction)),
+            Flow::Break(_) | Flow::Continue(_) => unreachable!("rejected by the parser"),
+        }
+    }
+
+    fn exec_block(&mut self, stmts: &'a [Statement]) -> Result<Flow<'a>, RuntimeError> {
+        self.scopes.push(Scope::default());
+        let flow = self.exec_stmts(stmts);
+        self.scopes.pop();
+        flow
+    }
+
+    // runs stmts in the current scope, functions and types are hoisted
+    fn exec_stmts(&mut self, stmts: &'a [Statement]) -> Result<Flow<'a>, RuntimeError> {
+        for s in stmts {
+            match &s.kind {
+                StatementKind::Function(f) => {
+                    self.scope().fns.insert(f.ident.clone(), f);
+                }
+                StatementKind::Struct(st) => {
+                    self.scope()
+                        .types
+                        .insert(st.ident.clone(), TypeDef::Struct(st));
+                }
+                StatementKind::Enum(e) => {
+                    self.scope().types.insert(e.ident.clone(), TypeDef::Enum(e));
+                }
+                _ => {}
+            }
+        }
+        for s in stmts {
+            match self.exec(s)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec(&mut self, stmt: &'a Statement) -> Result<Flow<'a>, RuntimeError> {
+        self.exec_kind(stmt).map_err(|e| e.at(stmt.span))
+    }
+
+    fn exec_kind(&mut self, stmt: &'a Statement) -> Result<Flow<'a>, RuntimeError> {
+        match &stmt.kind {
+            StatementKind::Declare(d) => {
+                let v = if let Some(e) = &d.assign {
+                    self.eval(e)?
+                } else {
+                    self.default_for(&d.typ)?
+                };
+                self.scope().define(&d.ident, v);
+            }
+            StatementKind::Assign(a) => {
+                let v = self.eval(&a.value)?;
+                self.assign(&a.target, a.op.as_ref(), v)
+                    .map_err(|e| e.at(a.target.span))?;
+            }
+            StatementKind::If(sif) => {
+                if self.test(&sif.guard)? {
+                    return self.exec_block(&sif.t);
+                }
+                for e in &sif.elifs {
+                    if self.test(&e.guard)? {
+                        return self.exec_block(&e.body);
+                    }
+                }
+                if let Some(f) = &sif.f {
+                    return self.exec_block(f);
+                }
+            }
+            StatementKind::While(w) => {
+                while self.test(&w.cond)? {
+                    if let Some(flow) = self.exec_block(&w.body)?.after_body(&w.label) {
+                        return Ok(flow);
+                    }
+                }
+            }
+            StatementKind::For(f) => {
+                // init is scoped to the loop
+                self.scopes.push(Scope::default());
+                let flow = self.exec_for(f);
+                self.scopes.pop();
+                return flow;
+            }
+            StatementKind::Loop(l) => loop {
+                if let Some(flow) = self.exec_block(&l.body)?.after_body(&l.label) {
+                    return Ok(flow);
+                }
+            },
+            StatementKind::DoWhile(d) => loop {
+                if let Some(flow) = self.exec_block(&d.body)?.after_body(&d.label) {
+                    return Ok(flow);
+                }
+                if !self.test(&d.cond)? {
+                    break;
+                }
+            },
+            // resolved before running
+            StatementKind::Import(_) => {}
+            StatementKind::Break(label) => return Ok(Flow::Break(label.clone())),
+            StatementKind::Continue(label) => return Ok(Flow::Continue(label.clone())),
+            StatementKind::Expr(e) => {
+                self.eval(e)?;
+            }
+            StatementKind::Match(m) => {
+                let value = self.eval(&m.scrutinee)?;
+                return self.exec_match(&m.arms, value, |i, body| i.exec_stmts(body));
+            }
+            // hoisted by exec_stmts
+            StatementKind::Function(_) | StatementKind::Struct(_) | StatementKind::Enum(_) => {}
+            StatementKind::Block(stmts) => return self.exec_block(stmts),
+            StatementKind::Return(e) => return Ok(Flow::Return(self.eval(e)?)),
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec_for(&mut self, f: &'a SFor) -> Result<Flow<'a>, RuntimeError> {
+        if let Some(init) = &f.init {
+            self.exec(init)?;
+        }
+        loop {
+            if let Some(cond) = &f.cond
+                && !self.test(cond)?
+            {
+                return Ok(Flow::Next);
+            }
+            if let Some(flow) = self.exec_block(&f.body)?.after_body(&f.label) {
+                return Ok(flow);
+            }
+            if let Some(step) = &f.step {
+                self.exec(step)?;
+            }
+        }
+    }
+
+    // runs the body of the first arm that takes value in a scope with its
+    // bindings
+    fn exec_match<B, T>(
+        &mut self,
+        arms: &'a [Arm<B>],
+        value: Value<'a>,
+        mut body: impl FnMut(&mut Self, &'a B) -> Result<T, RuntimeError>,
+    ) -> Result<T, RuntimeError> {
+        for arm in arms {
+            self.scopes.push(Scope::default());
+            let taken = match self.bind(&arm.pattern, &value) {
+                Ok(true) => match &arm.guard {
+                    Some(guard) => self.test(guard),
+                    None => Ok(true),
+                },
+                other => other,
+            };
+            let result = match taken {
+                Ok(true) => Some(body(self, &arm.body)),
+                Ok(false) => None,
+                Err(e) => Some(Err(e)),
+            };
+            self.scopes.pop();
+            if let Some(result) = result {
+                return result;
+            }
+        }
+        Err(RuntimeError::new(RuntimeErrorReason::NoMatch(
+            value.to_string(),
+        )))
+    }
+
+    // whether pattern matches value, its bindings go in the current scope
+    fn bind(&mut self, pattern: &'a Pattern, value: &Value<'a>) -> Result<bool, RuntimeError> {
+        match (pattern, value) {
+            (Pattern::Wildcard, _) => Ok(true),
+            (Pattern::Binding(ident), v) => {
+                self.scope().define(ident, v.clone());
+                Ok(true)
+            }
+            (Pattern::Literal(e), v) => Ok(self.eval(e)? == *v),
+            (Pattern::Variant(p), Value::Enum(e)) => {
+                if p.ident != e.ident || p.variant != e.variant {
+                    return Ok(false);
+                }
+                for (field, v) in p.fields.iter().zip(&e.payload) {
+                    if !self.bind(field, v)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            (Pattern::Variant(_), _) => Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+        }
+    }
+
+    // a constructor like Shape::Circle(1.0), None when path names no variant
+    fn construct(
+        &self,
+        path: &str,
+        args: Vec<Value<'a>>,
+    ) -> Option<Result<Value<'a>, RuntimeError>> {
+        let (ident, variant) = path.rsplit_once("::")?;
+        let e = self.lookup_enum(ident)?;
+        let (_, payload) = e.variants.iter().find(|(v, _)| v == variant)?;
+        if payload.len() != args.len() {
+            return Some(Err(RuntimeError::new(RuntimeErrorReason::ArgumentCount {
+                ident: path.to_string(),
+                expected: payload.len(),
+                found: args.len(),
+            })));
+        }
+        Some(Ok(Value::Enum(EnumValue {
+            ident: ident.to_string(),
+            variant: variant.to_string(),
+            payload: args,
+        })))
+    }
+
+    fn eval_block(&mut self, b: &'a BlockExpr) -> Result<Value<'a>, RuntimeError> {
+        match self.exec_stmts(&b.stmts)? {
+            Flow::Next => {}
+            _ => unreachable!("rejected by the checker"),
+        }
+        match &b.value {
+            Some(value) => self.eval(value),
+            None => unreachable!("rejected by the checker"),
+        }
+    }
+
+    // zero values, structs get the default of each field and enums their
+    // first variant
+    fn default_for(&self, typ: &LType) -> Result<Value<'a>, RuntimeError> {
+        Ok(match typ {
+            LType::Bool => Value::Bool(false),
+            LType::Int(t) => Value::Int(0, *t),
+            LType::Float(t) => Value::Float(0.0, *t),
+            LType::Char => Value::Char('\0'),
+            LType::String => Value::Str(String::new()),
+            LType::Named(ident) if let Some(e) = self.lookup_enum(ident) => {
+                let Some((variant, payload)) = e.variants.first() else {
+                    return Err(RuntimeError::new(RuntimeErrorReason::EmptyEnum(
+                        ident.clone(),
+                    )));
+                };
+                let payload = payload
+                    .iter()
+                    .map(|t| self.default_for(t))
+                    .collect::<Result<_, _>>()?;
+                Value::Enum(EnumValue {
+                    ident: ident.clone(),
+                    variant: variant.clone(),
+                    payload,
+                })
+            }
+            LType::Named(ident) => {
+                let s = self.lookup_struct(ident)?;
+                let fields = s
+                    .fields
+                    .iter()
+                    .map(|(t, f)| Ok((f.clone(), self.default_for(t)?)))
+                    .collect::<Result<_, RuntimeError>>()?;
+                Value::Struct(StructValue {
+                    ident: ident.clone(),
+                    fields,
+                })
+            }
+            LType::Array(elem, len) => {
+                let zero = self.default_for(elem)?;
+                Value::Array(vec![zero; *len])
+            }
+            // rejected by the checker, a function type has no default
+            LType::Fn(..) => return Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+        })
+    }
+
+    // fields are evaluated in the order written and stored in declaration order
+    fn eval_struct(&mut self, s: &'a StructLiteral) -> Result<Value<'a>, RuntimeError> {
+        let mut values = HashMap::new();
+        for f in &s.fields {
+            values.insert(f.ident.as_str(), self.eval(&f.value)?);
+        }
+        let def = self.lookup_struct(&s.ident)?;
+        let fields = def
+            .fields
+            .iter()
+            .map(|(_, f)| match values.remove(f.as_str()) {
+                Some(v) => Ok((f.clone(), v)),
+                None => Err(RuntimeError::new(RuntimeErrorReason::NoField(f.clone()))),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Value::Struct(StructValue {
+            ident: s.ident.clone(),
+            fields,
+        }))
+    }
+
+    // writes v to a place, or combines it with op, the indices of the place
+    // are evaluated first
+    fn assign(
+        &mut self,
+        place: &'a Place,
+        op: Option<&Op>,
+        v: Value<'a>,
+    ) -> Result<(), RuntimeError> {
+        let mut indices = Vec::new();
+        for access in &place.path {
+            if let Access::Index(index) = access {
+                indices.push(self.eval(index)?);
+            }
+        }
+        let mut indices = indices.into_iter();
+
+        let cell = self.lookup_var(&place.ident)?;
+        let mut var = cell.borrow_mut();
+        let mut value = &mut *var;
+        for access in &place.path {
+            value = match (access, value) {
+                (Access::Field(f), Value::Struct(s)) => s
+                    .field_mut(f)
+                    .ok_or_else(|| RuntimeError::new(RuntimeErrorReason::NoField(f.clone())))?,
+                (Access::Index(_), Value::Array(items)) => {
+                    let i = Self::bounds(items.len(), indices.next().unwrap())?;
+                    &mut items[i]
+                }
+                _ => return Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+            };
+        }
+        *value = match op {
+            Some(op) => Self::binop(op, value.clone(), v)?,
+            None => v,
+        };
+        Ok(())
+    }
+
+    // a chain of field and index reads on a variable like ps[i].xy[0], which
+    // borrows its way to the value instead of copying the whole variable,
+    // None when the chain starts at something else
+    fn read_place(&mut self, expr: &'a Expr) -> Option<Result<Value<'a>, RuntimeError>> {
+        let mut chain = Vec::new();
+        let mut root = expr;
+        while let ExprKind::Field(FieldAccess { base, .. })
+        | ExprKind::Index(IndexExpr { base, .. }) = &root.kind
+        {
+            chain.push(root);
+            root = base;
+        }
+        let ExprKind::Ident(ident) = &root.kind else {
+            return None;
+        };
+        let cell = self.find_var(ident)?;
+        Some(self.read_chain(&cell, &chain))
+    }
+
+    // the value a chain of reads, outermost first, selects in cell, the
+    // indices are evaluated first as in assign
+    fn read_chain(
+        &mut self,
+        cell: &RefCell<Value<'a>>,
+        chain: &[&'a Expr],
+    ) -> Result<Value<'a>, RuntimeError> {
+        let mut indices = Vec::new();
+        for e in chain.iter().rev() {
+            if let ExprKind::Index(i) = &e.kind {
+                indices.push(self.eval(&i.index)?);
+            }
+        }
+        let mut indices = indices.into_iter();
+
+        let var = cell.borrow();
+        let mut value = &*var;
+        for e in chain.iter().rev() {
+            let next = match (&e.kind, value) {
+                (ExprKind::Field(a), Value::Struct(s)) => s
+                    .field(&a.field)
+                    .ok_or_else(|| RuntimeError::new(RuntimeErrorReason::NoField(a.field.clone()))),
+                (ExprKind::Index(_), Value::Array(items)) => {
+                    Self::bounds(items.len(), indices.next().unwrap()).map(|i| &items[i])
+                }
+                _ => Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+            };
+            value = next.map_err(|err| err.at(e.span))?;
+        }
+        Ok(value.clone())
+    }
+
+    // the position an index value selects in an array of len
+    fn bounds(len: usize, index: Value<'a>) -> Result<usize, RuntimeError> {
+        let Value::Int(index, _) = index else {
+            return Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch));
+        };
+        usize::try_from(index)
+            .ok()
+            .filter(|&i| i < len)
+            .ok_or_else(|| RuntimeError::new(RuntimeErrorReason::IndexOutOfBounds { index, len }))
+    }
+
+    // a call names a function declaration, a variant, a builtin or a
+    // variable holding a function, in that order
+    fn call(&mut self, ident: &str, params: &'a [Expr]) -> Result<Value<'a>, RuntimeError> {
+        let args = params
+            .iter()
+            .map(|p| self.eval(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(f) = self.lookup_fn(ident) {
+            return self.invoke(ident, FnValue::Named(f), args);
+        }
+        if let Some(value) = self.construct(ident, args.clone()) {
+            return value;
+        }
+        if is_builtin(ident) {
+            return self.call_builtin(ident, &args);
+        }
+        let Some(cell) = self.find_var(ident) else {
+            return Err(RuntimeError::new(RuntimeErrorReason::UndefinedFunction(
+                ident.to_string(),
+            )));
+        };
+        let f = cell.borrow().clone();
+        match f {
+            Value::Fn(f) => self.invoke(ident, f, args),
+            _ => Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+        }
+    }
+
+    // runs a function on args, ident names it in errors
+    fn invoke(
+        &mut self,
+        ident: &str,
+        f: FnValue<'a>,
+        args: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let (params, body, env): (&'a [(LType, String)], &'a [Statement], _) = match &f {
+            FnValue::Named(f) => (&f.params, &f.body, None),
+            FnValue::Closure(c) => {
+                let l: &'a Lambda = c.lambda;
+                (&l.params, &l.body, Some(c.env.clone()))
+            }
+        };
+        if params.len() != args.len() {
+            return Err(RuntimeError::new(RuntimeErrorReason::ArgumentCount {
+                ident: ident.to_string(),
+                expected: params.len(),
+                found: args.len(),
+            }));
+        }
+
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(RuntimeErrorReason::StackOverflow));
+        }
+        let mut frame = Scope::default();
+        for ((_, name), v) in params.iter().zip(args) {
+            frame.define(name, v);
+        }
+
+        // a call only sees its own frame and the globals, and a closure
+        // what it captured
+        let saved = self.scopes.split_off(1);
+        self.scopes.extend(env);
+        self.scopes.push(frame);
+        self.depth += 1;
+        let flow = self.exec_stmts(body);
+        self.depth -= 1;
+        self.scopes.truncate(1);
+        self.scopes.extend(saved);
+
+        match flow? {
+            Flow::Return(v) => Ok(v),
+            Flow::Break(_) | Flow::Continue(_) => unreachable!("rejected by the parser"),
+            Flow::Next => Err(RuntimeError::new(RuntimeErrorReason::MissingReturn(
+                ident.to_string(),
+            ))),
+        }
+    }
+
+    // captures are shared or copied as capture::resolve decided, and the
+    // local functions and types around the closure stay visible to it
+    fn make_closure(&self, lambda: &'a Lambda) -> Result<Value<'a>, RuntimeError> {
+        let mut env = Scope::default();
+        for s in &self.scopes[1..] {
+            env.fns.extend(s.fns.iter().map(|(k, v)| (k.clone(), *v)));
+            env.types
+                .extend(s.types.iter().map(|(k, v)| (k.clone(), *v)));
+        }
+        for c in &lambda.captures {
+            let cell = self.lookup_var(&c.ident)?;
+            let cell = if c.by_ref {
+                cell
+            } else {
+                Rc::new(RefCell::new(cell.borrow().clone()))
+            };
+            env.vars.insert(c.ident.clone(), cell);
+        }
+        Ok(Value::Fn(FnValue::Closure(Rc::new(Closure {
+            lambda,
+            env,
+        }))))
+    }
+
+    fn call_builtin(&mut self, ident: &str, args: &[Value<'a>]) -> Result<Value<'a>, RuntimeError> {
+        match ident {
+            "print" => {
+                let line = args
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                writeln!(self.out, "{}", line)
+                    .map_err(|_| RuntimeError::new(RuntimeErrorReason::OutputFailed))?;
+                Ok(Value::Int(0, IntType::I64))
+            }
+            _ => Err(RuntimeError::new(RuntimeErrorReason::UndefinedFunction(
+                ident.to_string(),
+            ))),
+        }
+    }
+
+    fn eval(&mut self, expr: &'a Expr) -> Result<Value<'a>, RuntimeError> {
+        self.eval_kind(expr).map_err(|e| e.at(expr.span))
+    }
+
+    fn eval_kind(&mut self, expr: &'a Expr) -> Result<Value<'a>, RuntimeError> {
+        match &expr.kind {
+            ExprKind::Intermediate(i, t) => Ok(Value::Int(*i, t.expect("typed by the checker"))),
+            ExprKind::FloatIntermediate(x, t) => {
+                let t = t.expect("typed by the checker");
+                Ok(Value::Float(t.round(*x), t))
+            }
+            ExprKind::StringLiteral(s) => Ok(Value::Str(s.clone())),
+            ExprKind::CharLiteral(c) => Ok(Value::Char(*c)),
+            ExprKind::BoolLiteral(b) => Ok(Value::Bool(*b)),
+            // a function declaration named without a call is a value
+            ExprKind::Ident(s) => match self.construct(s, Vec::new()) {
+                Some(value) => value,
+                None => match (self.find_var(s), self.lookup_fn(s)) {
+                    (None, Some(f)) => Ok(Value::Fn(FnValue::Named(f))),
+                    _ => Ok(self.lookup_var(s)?.borrow().clone()),
+                },
+            },
+            ExprKind::Call(c) => self.call(&c.ident, &c.params),
+            ExprKind::Lambda(l) => self.make_closure(l),
+            ExprKind::Apply(a) => {
+                let f = self.eval(&a.f)?;
+                let args = a
+                    .params
+                    .iter()
+                    .map(|p| self.eval(p))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match f {
+                    Value::Fn(f) => self.invoke(&f.typ().to_string(), f, args),
+                    _ => Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+                }
+            }
+            ExprKind::StructLiteral(s) => self.eval_struct(s),
+            ExprKind::Field(a) => {
+                if let Some(value) = self.read_place(expr) {
+                    return value;
+                }
+                match self.eval(&a.base)? {
+                    Value::Struct(s) => s.field(&a.field).cloned().ok_or_else(|| {
+                        RuntimeError::new(RuntimeErrorReason::NoField(a.field.clone()))
+                    }),
+                    _ => Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+                }
+            }
+            ExprKind::ArrayLiteral(items) => Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|e| self.eval(e))
+                    .collect::<Result<_, _>>()?,
+            )),
+            ExprKind::Index(i) => {
+                if let Some(value) = self.read_place(expr) {
+                    return value;
+                }
+                let base = self.eval(&i.base)?;
+                let index = self.eval(&i.index)?;
+                match base {
+                    Value::Array(mut items) => {
+                        let i = Self::bounds(items.len(), index)?;
+                        Ok(items.swap_remove(i))
+                    }
+                    _ => Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+                }
+            }
+            ExprKind::Block(b) => {
+                self.scopes.push(Scope::default());
+                let value = self.eval_block(b);
+                self.scopes.pop();
+                value
+            }
+            ExprKind::Match(m) => {
+                let value = self.eval(&m.scrutinee)?;
+                self.exec_match(&m.arms, value, |i, body| i.eval(body))
+            }
+            ExprKind::If(i) => {
+                if self.test(&i.guard)? {
+                    self.eval(&i.t)
+                } else {
+                    self.eval(&i.f)
+                }
+            }
+            ExprKind::Unary(u) => {
+                let x = self.eval(&u.x)?;
+                Ok(match (&u.op, x) {
+                    (UOp::Pos, x) => x,
+                    (UOp::Neg, Value::Int(i, t)) => Value::Int(t.wrap(-i), t),
+                    (UOp::Neg, Value::Float(x, t)) => Value::Float(-x, t),
+                    (UOp::Not, Value::Bool(b)) => Value::Bool(!b),
+                    (UOp::BitNot, Value::Int(i, t)) => Value::Int(t.wrap(!i), t),
+                    (UOp::Neg | UOp::Not | UOp::BitNot, _) => {
+                        return Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch));
+                    }
+                })
+            }
+            ExprKind::Binop(b) if matches!(b.op, Op::And | Op::Or) => {
+                let a = self.test(&b.a)?;
+                // the right side is skipped once the result is known
+                if a == matches!(b.op, Op::Or) {
+                    return Ok(Value::Bool(a));
+                }
+                Ok(Value::Bool(self.test(&b.b)?))
+            }
+            ExprKind::Binop(b) => {
+                let a = self.eval(&b.a)?;
+                let c = self.eval(&b.b)?;
+                Self::binop(&b.op, a, c)
+            }
+            ExprKind::Cast(c) => Self::cast(self.eval(&c.x)?, &c.typ),
+        }
+    }
+
+    // int to int keeps the low bits, float to int rounds toward zero and
+    // saturates, the rest round to the nearest value
+    fn cast(value: Value<'a>, typ: &LType) -> Result<Value<'a>, RuntimeError> {
+        Ok(match (value, typ) {
+            (Value::Int(i, _), LType::Int(t)) => Value::Int(t.wrap(i), *t),
+            (Value::Int(i, _), LType::Float(t)) => Value::Float(t.round(i as f64), *t),
+            (Value::Float(x, _), LType::Int(t)) => Value::Int(t.saturate(x), *t),
+            (Value::Float(x, _), LType::Float(t)) => Value::Float(t.round(x), *t),
+            (Value::Bool(b), LType::Int(t)) => Value::Int(b as i128, *t),
+            (Value::Char(c), LType::Int(t)) => Value::Int(t.wrap(c as i128), *t),
+            (Value::Int(i, IntType::U8), LType::Char) => Value::Char(char::from(i as u8)),
+            _ => return Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+        })
+    }
+
+    // an unsuffixed literal operand takes the type of the other side
+    fn binop(op: &Op, a: Value<'a>, b: Value<'a>) -> Result<Value<'a>, RuntimeError> {
+        Ok(match (a, b) {
+            (Value::Int(a, _), Value::Int(b, _)) if op.is_comparison() => {
+                Value::Bool(Self::compare(op, a, b))
+            }
+            // the checker gives both sides the same type
+            (Value::Int(a, t), Value::Int(b, _)) => {
+                Value::Int(t.wrap(Self::int_op(op, t, a, b)?), t)
+            }
+            (Value::Float(a, t), Value::Float(b, _)) => {
+                if op.is_comparison() {
+                    return Ok(Value::Bool(Self::compare(op, a, b)));
+                }
+                Value::Float(
+                    t.round(match op {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        _ => return Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+                    }),
+                    t,
+                )
+            }
+            (Value::Char(a), Value::Char(b)) if op.is_comparison() => {
+                Value::Bool(Self::compare(op, a, b))
+            }
+            (Value::Bool(a), Value::Bool(b)) if matches!(op, Op::Eq | Op::Ne) => {
+                Value::Bool(Self::compare(op, a, b))
+            }
+            (a @ (Value::Struct(_) | Value::Array(_) | Value::Enum(_)), b)
+                if matches!(op, Op::Eq | Op::Ne) =>
+            {
+                Value::Bool((a == b) == matches!(op, Op::Eq))
+            }
+            (Value::Str(a), Value::Str(b)) => match op {
+                Op::Add => Value::Str(a + &b),
+                Op::Eq => Value::Bool(a == b),
+                Op::Ne => Value::Bool(a != b),
+                _ => return Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+            },
+            _ => return Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+        })
+    }
+
+    // a op b before wrapping to t, shifts must stay under the width of t
+    fn int_op(op: &Op, t: IntType, a: i128, b: i128) -> Result<i128, RuntimeError> {
+        Ok(match op {
+            Op::Add => a.wrapping_add(b),
+            Op::Sub => a.wrapping_sub(b),
+            Op::Mul => a.wrapping_mul(b),
+            Op::Div | Op::Mod if b == 0 => {
+                return Err(RuntimeError::new(RuntimeErrorReason::DivisionByZero));
+            }
+            Op::Div => a.wrapping_div(b),
+            Op::Mod => a.wrapping_rem(b),
+            Op::BitAnd => a & b,
+            Op::BitOr => a | b,
+            Op::BitXor => a ^ b,
+            Op::Shl | Op::Shr if !(0..t.bits() as i128).contains(&b) => {
+                return Err(RuntimeError::new(RuntimeErrorReason::ShiftOutOfRange(b)));
+            }
+            Op::Shl => a << b,
+            Op::Shr => a >> b,
+            Op::Lt | Op::Gt | Op::Le | Op::Ge | Op::Eq | Op::Ne => {
+                unreachable!("compared above")
+            }
+            Op::And | Op::Or => unreachable!("short circuited in eval"),
+        })
+    }
+
+    fn compare<T: PartialOrd>(op: &Op, a: T, b: T) -> bool {
+        match op {
+            Op::Lt => a < b,
+            Op::Gt => a > b,
+            Op::Le => a <= b,
+            Op::Ge => a >= b,
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+            _ => unreachable!("not a comparison"),
+        }
+    }
+
+    // conditions must be bools, there is no truthiness
+    fn test(&mut self, cond: &'a Expr) -> Result<bool, RuntimeError> {
+        match self.eval(cond)? {
+            Value::Bool(b) => Ok(b),
+            _ => Err(RuntimeError::new(RuntimeErrorReason::TypeMismatch)),
+        }
+    }
+
+    fn scope(&mut self) -> &mut Scope<'a> {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn find_var(&self, ident: &str) -> Option<Rc<RefCell<Value<'a>>>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.vars.get(ident))
+            .cloned()
+    }
+
+    fn lookup_var(&self, ident: &str) -> Result<Rc<RefCell<Value<'a>>>, RuntimeError> {
+        self.find_var(ident).ok_or_else(|| {
+            RuntimeError::new(RuntimeErrorReason::UndefinedVariable(ident.to_string()))
+        })
+    }
+
+    fn lookup_type(&self, ident: &str) -> Option<TypeDef<'a>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.types.get(ident).copied())
+    }
+
+    fn lookup_struct(&self, ident: &str) -> Result<&'a SStruct, RuntimeError> {
+        match self.lookup_type(ident) {
+            Some(TypeDef::Struct(s)) => Ok(s),
+            _ => Err(RuntimeError::new(RuntimeErrorReason::UndefinedType(
+                ident.to_string(),
+            ))),
+        }
+    }
+
+    fn lookup_enum(&self, ident: &str) -> Option<&'a SEnum> {
+        match self.lookup_type(ident)? {
+            TypeDef::Enum(e) => Some(e),
+            TypeDef::Struct(_) => None,
+        }
+    }
+
+    fn lookup_fn(&self, ident: &str) -> Option<&'a SFunction> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.fns.get(ident).copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interpreter, RuntimeErrorReason};
+    use crate::ast::Statement;
+    use crate::capture;
+    use crate::check::check;
+    use crate::mono::monomorphize;
+    use crate::parser::Parser;
+
+    // source as the interpreter gets it, checked and with literals typed
+    fn program(source: &str) -> Vec<Statement> {
+        let mut stmts = Parser::new(source.chars())
+            .parse_program()
+            .expect("source should parse");
+        capture::resolve(&mut stmts);
+        let instances = check(&stmts).expect("source should check");
+        monomorphize(stmts, &instances)
+    }
+
+    fn run(source: &str) -> String {
+        let stmts = program(source);
+        let mut out = Vec::new();
+        Interpreter::new(&mut out)
+            .run(&stmts)
+            .expect("source should run");
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn run_while() {
+        let out = run("int i = 0; while (i < 3) { print(i); i = i + 1; }");
+        assert_eq!(out, "0\n1\n2\n");
+    }
+
+    #[test]
+    fn run_float() {
+        let out = run("float x = 1.5; print(x * 2.0, 1.0 / 4.0);");
+        assert_eq!(out, "3.0 0.25\n");
+    }
+
+    #[test]
+    fn run_operators() {
+        let out =
+            run("print(7 % 3, 1 << 4, -16 >> 2, 6 & 3, 6 | 3, 6 ^ 3, ~0, !true, 2 <= 2, 1 != 1);");
+        assert_eq!(out, "1 16 -4 2 7 5 -1 false true false\n");
+
+        // the division by zero on the right is never evaluated
+        let out = run("print(false && 1 / 0 == 0, true || 1 / 0 == 0);");
+        assert_eq!(out, "false true\n");
+    }
+
+    #[test]
+    fn run_compound_assign() {
+        let out =
+            run("int a = 10; a += 5; a -= 1; a *= 2; a /= 4; a %= 4; a++; a++; a--; print(a);");
+        assert_eq!(out, "4\n");
+
+        let out = run("float f = 1.5; f++; f++; f32 g = 0.5; g--; print(f, g);");
+        assert_eq!(out, "3.5 -0.5\n");
+    }
+
+    #[test]
+    fn run_text() {
+        let out = run(
+            r#"string s = "hello"; s += ", world"; char c = '!'; print(s, c == '!', "\u{1F600}");"#,
+        );
+        assert_eq!(out, "hello, world true \u{1F600}\n");
+    }
+
+    #[test]
+    fn run_call() {
+        let out = run("fn show(int n) -> int { print(n * 2); return 0; }; show(21);");
+        assert_eq!(out, "42\n");
+    }
+
+    #[test]
+    fn run_recursion() {
+        let out = run("
+            fn fib(int n) -> int {
+                if n < 2 { return n; };
+                return fib(n - 1) + fib(n - 2);
+            };
+            int x = fib(10);
+            print(x, fib(fib(5)));
+        ");
+        assert_eq!(out, "55 5\n");
+
+        // deep recursion runs on a big stack and too deep is an error
+        let depth = |n: u32| {
+            let stmts = program(&format!(
+                "fn f(int n) -> int {{ if n == 0 {{ return 0; }}; return f(n - 1) + 1; }}; print(f({n}));"
+            ));
+            let mut out = Vec::new();
+            super::run(&stmts, &mut out).map(|_| String::from_utf8(out).unwrap())
+        };
+        assert_eq!(depth(1000).unwrap(), "1000\n");
+        let err = depth(100_000).unwrap_err();
+        assert!(matches!(err.reason, RuntimeErrorReason::StackOverflow));
+    }
+
+    #[test]
+    fn run_else_if() {
+        let out = run("
+            fn sign(int n) -> int {
+                if n < 0 { return -1; } else if n == 0 { return 0; } else { return 1; };
+            };
+            print(sign(-5), sign(0), sign(7));
+        ");
+        assert_eq!(out, "-1 0 1\n");
+    }
+
+    #[test]
+    fn run_loops() {
+        let out = run("
+            outer: for (int i = 0; i < 3; i++) {
+                for (int j = 0; ; j++) {
+                    if j == i { continue outer; };
+                    if i == 2 { break outer; };
+                    print(i, j);
+                }
+            }
+            int n = 0;
+            loop { n++; if n > 4 { break; }; }
+            do { n--; } while (n > 10);
+            print(n);
+        ");
+        assert_eq!(out, "1 0\n4\n");
+    }
+
+    #[test]
+    fn run_value_exprs() {
+        let out = run("
+            int a = 0;
+            int b = { int t = a; a = 5; t + 1 };
+            string s = if a > 3 { \"big\" } else if a > 1 { \"mid\" } else { \"small\" };
+            print(a, b, s, b == 1 ? 'y' : 'n');
+        ");
+        assert_eq!(out, "5 1 big y\n");
+    }
+
+    #[test]
+    fn run_nested_tails() {
+        let out = run("
+            int x = { { 1 } };
+            int v = { int t = 1; if t > 0 { t } else { 0 } };
+            int w = if true { if false { 1 } else { 2 } } else { 3 };
+            int m = if true { match 1 { _ => 7 } } else { 3 };
+            int s = { if x > 0 { x = 4; }; x };
+            print(x, v, w, m, s);
+        ");
+        assert_eq!(out, "4 1 2 7 4\n");
+    }
+
+    #[test]
+    fn run_structs() {
+        let out = run("
+            struct Point { int x; float y; };
+            fn scale(Point p, int k) -> Point {
+                p.x *= k;
+                return p;
+            };
+            Point a = Point { y: 0.5, x: 2 };
+            Point b = scale(a, 3);
+            Point c;
+            c.y = b.y + 1.0;
+            print(a, b.x, c, a == b, scale(a, 1) == a);
+        ");
+        assert_eq!(
+            out,
+            "Point { x: 2, y: 0.5 } 6 Point { x: 0, y: 1.5 } false true\n"
+        );
+    }
+
+    #[test]
+    fn run_arrays() {
+        let out = run("
+            struct P { int[2] xy; };
+            int[4] squares;
+            for (int i = 0; i < 4; i++) { squares[i] = i * i; }
+            P[2] ps;
+            ps[1].xy[0] = 7;
+            int[3] a = [1, 2, 3];
+            int[3] b = a;
+            b[0] = 9;
+            print(squares, ps[1], a, b[0], a == [1, 2, 3]);
+        ");
+        assert_eq!(out, "[0, 1, 4, 9] P { xy: [7, 0] } [1, 2, 3] 9 true\n");
+
+        let stmts = program("int[2] a; int i = 2; a[i] = 1;");
+        let err = Interpreter::new(Vec::new()).run(&stmts).unwrap_err();
+        assert_eq!(
+            err.reason.to_string(),
+            "index out of bounds: the length is 2 but the index is 2"
+        );
+        let span = err.span.expect("the error should have a span");
+        assert_eq!((span.start, span.end), (21, 25));
+
+        // reads borrow down a variable, other bases are values to take from
+        let out = run("
+            struct P { int[2] xy; };
+            P[2] ps = [P { xy: [1, 2] }, P { xy: [3, 4] }];
+            fn make() -> int[2] { return [5, 6]; };
+            int i = 1;
+            print(ps[i].xy[i - 1], ps[0].xy, [7, 8][i], make()[0]);
+        ");
+        assert_eq!(out, "3 [1, 2] 8 5\n");
+
+        let stmts = program("int[2] a; int i = 2; print(a[0], a[i]);");
+        let err = Interpreter::new(Vec::new()).run(&stmts).unwrap_err();
+        let span = err.span.expect("the error should have a span");
+        assert_eq!((span.start, span.end), (33, 37));
+    }
+
+    #[test]
+    fn run_match() {
+        let out = run("
+            enum Shape { Circle(float), Rect(float, float), Empty };
+            fn area(Shape s) -> float {
+                return match s {
+                    Shape::Circle(r) => 3.0 * r * r,
+                    Shape::Rect(w, h) if w > 0.0 => w * h,
+                    _ => 0.0,
+                };
+            };
+            Shape[3] shapes = [Shape::Circle(1.0), Shape::Rect(2.0, 3.0), Shape::Empty];
+            for (int i = 0; i < 3; i++) {
+                match shapes[i] {
+                    Shape::Empty => { print(\"empty\"); }
+                    s => { print(s, area(s)); }
+                };
+            }
+            Shape d;
+            print(d, d == Shape::Circle(0.0), area(Shape::Rect(-1.0, 2.0)));
+            print(match 2 { 1 => 'a', 2 => 'b', _ => 'c' });
+        ");
+        assert_eq!(
+            out,
+            "Shape::Circle(1.0) 3.0\nShape::Rect(2.0, 3.0) 6.0\nempty\nShape::Circle(0.0) true 0.0\nb\n"
+        );
+    }
+
+    #[test]
+    fn run_numeric() {
+        let out = run("
+            u8 a = 200;
+            a += 100;
+            i8 b = -128;
+            b = -b;
+            natural n = 18446744073709551615;
+            f32 f = 0.1;
+            u8[2] bytes = [255, 1];
+            print(a, b, n + 1, f, f * 3.0, bytes == [255, 1], ~0u16);
+            print(300 as u8, -1 as u32, 3.99 as int, -1.5e10 as i32, 1e300 as u8);
+            print('A' as u8, 97u8 as char, true as int, 16777217 as f32, 2.5f32 as float);
+        ");
+        assert_eq!(
+            out,
+            "44 -128 0 0.1 0.3 true 65535\n44 4294967295 3 -2147483648 255\n65 a 1 16777216.0 2.5\n"
+        );
+
+        // literals take the type of where they are stored before any
+        // arithmetic happens
+        let out = run("
+            u8 x = 255 * 2 / 2;
+            u64 y = 1 << 63 >> 63;
+            i8 z = 100 + 100;
+            fn add<T>(T x) -> T { return x + 200; };
+            print(x, y, z, 7 / 2, add(100u8), add(100));
+        ");
+        assert_eq!(out, "127 1 -56 3 44 300\n");
+
+        let stmts = program("u8 a = 1; print(a << 8);");
+        let err = Interpreter::new(Vec::new()).run(&stmts).unwrap_err();
+        assert_eq!(err.reason.to_string(), "shift amount 8 is out of range");
+        let span = err.span.expect("the error should have a span");
+        assert_eq!((span.start, span.end), (16, 22));
+    }
+
+    #[test]
+    fn run_closures() {
+        let out = run("
+            fn map(int[4] xs, fn(int) -> int f) -> int[4] {
+                for (int i = 0; i < 4; i += 1) {
+                    xs[i] = f(xs[i]);
+                }
+                return xs;
+            };
+            fn count(int[4] xs, fn(int) -> bool keep) -> int {
+                int n = 0;
+                for (int i = 0; i < 4; i += 1) {
+                    if keep(xs[i]) { n += 1; };
+                }
+                return n;
+            };
+            fn double(int x) -> int { return x * 2; };
+            fn counter(int step) -> fn() -> int {
+                int total = 0;
+                return fn() -> int { total += step; return total; };
+            };
+            struct Button { string label; fn(int) -> int on_click; };
+
+            int[4] xs = [1, 2, 3, 4];
+            int k = 10;
+            print(map(xs, double), map(xs, fn(int x) -> int { return x + k; }));
+            print(count(xs, fn(int x) -> bool { return x % 2 == 0; }));
+            fn() -> int next = counter(5);
+            fn() -> int other = counter(1);
+            next();
+            print(next(), other(), next());
+            {
+                int clicks = 0;
+                Button b = Button {
+                    label: \"ok\",
+                    on_click: fn(int n) -> int { clicks += n; return clicks; },
+                };
+                b.on_click(2);
+                print(b.label, b.on_click(3), clicks);
+            };
+            int n = 1;
+            fn() -> int get = fn() -> int { return n; };
+            n = 7;
+            print(get(), double, counter(1)());
+        ");
+        assert_eq!(
+            out,
+            "[2, 4, 6, 8] [11, 12, 13, 14]\n2\n10 1 15\nok 5 5\n7 fn(int) -> int 1\n"
+        );
+    }
+}
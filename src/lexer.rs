@@ -1,10 +1,22 @@
 use std::{iter::Peekable, str::Chars};
 
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use unicode_ident::{is_xid_continue, is_xid_start};
+use unicode_security::{MixedScript, skeleton};
+
+use crate::source::{FileId, Span};
 use crate::tokens::*;
 
+// identifiers follow Unicode XID with _ allowed first
+fn is_ident_start(c: char) -> bool {
+    c == '_' || is_xid_start(c)
+}
+
 #[derive(Debug, Clone)]
 pub struct TokenInfo {
-    pub position: u32,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -13,25 +25,113 @@ pub struct LexedToken {
     pub token: Token,
 }
 
+impl LexedToken {
+    pub fn error(&self) -> Option<LexError> {
+        if let Token::ILLEGAL(reason) = &self.token {
+            Some(LexError {
+                span: self.info.span,
+                reason: reason.clone(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LexError {
+    pub span: Span,
+    pub reason: LexErrorReason,
+}
+
+#[derive(Debug, Clone)]
+pub struct LexWarning {
+    pub span: Span,
+    pub reason: LexWarningReason,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexWarningReason {
+    // looks like `other`, which was seen first
+    ConfusableIdent { ident: String, other: String },
+    MixedScriptIdent(String),
+}
+
+impl fmt::Display for LexWarningReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexWarningReason::ConfusableIdent { ident, other } => {
+                write!(f, "identifier `{ident}` looks like `{other}`")
+            }
+            LexWarningReason::MixedScriptIdent(ident) => {
+                write!(f, "identifier `{ident}` mixes scripts")
+            }
+        }
+    }
+}
+
+// flags non-ASCII identifiers that mix scripts or look like another
+// identifier in the same stream, at the first use of each
+pub fn check_idents<'t>(tokens: impl Iterator<Item = &'t LexedToken>) -> Vec<LexWarning> {
+    let mut warnings = Vec::new();
+    let mut skeletons: HashMap<String, &str> = HashMap::new();
+    let mut seen: HashSet<&str> = HashSet::new();
+    for tk in tokens {
+        let Token::IDENT(ident) = &tk.token else {
+            continue;
+        };
+        if !seen.insert(ident) {
+            continue;
+        }
+        let warn = |reason| LexWarning {
+            span: tk.info.span,
+            reason,
+        };
+
+        if !ident.is_ascii() && !ident.as_str().is_single_script() {
+            warnings.push(warn(LexWarningReason::MixedScriptIdent(ident.clone())));
+        }
+
+        let other = *skeletons
+            .entry(skeleton(ident).collect())
+            .or_insert(ident.as_str());
+        if other != ident && !(other.is_ascii() && ident.is_ascii()) {
+            warnings.push(warn(LexWarningReason::ConfusableIdent {
+                ident: ident.clone(),
+                other: other.to_string(),
+            }));
+        }
+    }
+    warnings
+}
+
 pub struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
     end: bool,
+    file: FileId,
+    // byte offset of the next char
     pos: u32,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(chars: Peekable<Chars<'a>>) -> Self {
+        Self::with_file(chars, FileId::default())
+    }
+
+    pub fn with_file(chars: Peekable<Chars<'a>>, file: FileId) -> Self {
         Self {
             chars,
             pos: 0,
+            file,
             end: false,
         }
     }
 
     // use instead of self.chars.next() for position reporting
     fn next_char(&mut self) -> Option<char> {
-        self.pos += 1;
-        self.chars.next()
+        let ch = self.chars.next()?;
+        self.pos += ch.len_utf8() as u32;
+        Some(ch)
     }
 
     fn consume_whitespace(&mut self) {
@@ -42,79 +142,411 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn read_num(&mut self, radix: u32) -> Option<u32> {
-        let mut result: u32 = 0;
-        let mut parsed: usize = 0;
-        while let Some(&ch) = self.chars.peek() {
-            if let Some(d) = ch.to_digit(radix) {
-                result *= radix;
-                result += d;
-                parsed += 1;
+    // looks ahead without consuming
+    fn starts_with(&self, s: &str) -> bool {
+        let mut ahead = self.chars.clone();
+        s.chars().all(|c| ahead.next() == Some(c))
+    }
+
+    fn is_doc_comment(&self) -> bool {
+        self.starts_with("///") && !self.starts_with("////")
+    }
+
+    // consumes up to the line break
+    fn consume_line(&mut self) -> String {
+        let mut s = String::new();
+        while let Some(&ch) = self.chars.peek()
+            && ch != '\n'
+        {
+            s.push(ch);
+            self.next_char();
+        }
+        s
+    }
+
+    // block comments nest, returns false if the input ends first
+    fn consume_block_comment(&mut self) -> bool {
+        let mut depth = 0;
+        loop {
+            if self.starts_with("/*") {
+                self.next_char();
+                self.next_char();
+                depth += 1;
+            } else if self.starts_with("*/") {
                 self.next_char();
+                self.next_char();
+                depth -= 1;
+                if depth == 0 {
+                    return true;
+                }
+            } else if self.next_char().is_none() {
+                return false;
+            }
+        }
+    }
+
+    // skips whitespace and comments other than doc comments,
+    // an unterminated block comment is returned as a token
+    fn consume_trivia(&mut self) -> Option<LexedToken> {
+        loop {
+            self.consume_whitespace();
+            let start = self.pos;
+            if self.starts_with("//") && !self.is_doc_comment() {
+                self.consume_line();
+            } else if self.starts_with("/*") {
+                if !self.consume_block_comment() {
+                    return Some(
+                        self.lexed(start, Token::ILLEGAL(LexErrorReason::UnterminatedComment)),
+                    );
+                }
             } else {
-                // illegal
-                if ch.is_alphabetic() {
+                return None;
+            }
+        }
+    }
+
+    fn expect_doc(&mut self) -> Token {
+        for _ in 0..3 {
+            self.next_char();
+        }
+        let line = self.consume_line();
+        let line = line.strip_prefix(' ').unwrap_or(&line);
+        Token::DOC(line.trim_end_matches('\r').to_string())
+    }
+
+    fn lexed(&self, start: u32, token: Token) -> LexedToken {
+        LexedToken {
+            info: TokenInfo {
+                span: Span::new(self.file, start, self.pos),
+            },
+            token,
+        }
+    }
+
+    // 0o17, 0b1010
+    fn expect_radix(&mut self, radix: u32) -> Result<Token, LexErrorReason> {
+        let digits = self.read_digits(radix);
+        let suffix = self.read_suffix(radix)?;
+        if digits.is_empty() {
+            return Err(LexErrorReason::MissingDigits { radix });
+        }
+        let n = u64::from_str_radix(&digits, radix).map_err(|_| LexErrorReason::NumberOverflow)?;
+        Self::int_token(n, suffix)
+    }
+
+    // digits with the _ separators dropped
+    fn read_digits(&mut self, radix: u32) -> String {
+        let mut s = String::new();
+        while let Some(&ch) = self.chars.peek()
+            && (ch.is_digit(radix) || ch == '_')
+        {
+            if ch != '_' {
+                s.push(ch);
+            }
+            self.next_char();
+        }
+        s
+    }
+
+    // [+-]digits after the exponent marker
+    fn read_exponent(&mut self) -> Result<String, LexErrorReason> {
+        let mut s = String::new();
+        if let Some(&sign) = self.chars.peek()
+            && (sign == '+' || sign == '-')
+        {
+            s.push(sign);
+            self.next_char();
+        }
+        let digits = self.read_digits(10);
+        if digits.is_empty() {
+            self.reject_glued(10)?;
+            return Err(LexErrorReason::MissingDigits { radix: 10 });
+        }
+        s.push_str(&digits);
+        Ok(s)
+    }
+
+    // type suffix such as i64 or f, anything else glued on is illegal
+    fn read_suffix(&mut self, radix: u32) -> Result<Option<NumSuffix>, LexErrorReason> {
+        if !matches!(self.chars.peek(), Some('i' | 'u' | 'f')) {
+            self.reject_glued(radix)?;
+            return Ok(None);
+        }
+        let mut s = String::new();
+        while let Some(&ch) = self.chars.peek()
+            && is_xid_continue(ch)
+        {
+            s.push(ch);
+            self.next_char();
+        }
+        match NumSuffix::lookup(&s) {
+            // only decimal literals can become floats
+            Some(suffix) if suffix.is_float() && radix != 10 => {
+                Err(LexErrorReason::InvalidSuffix(s))
+            }
+            Some(suffix) => Ok(Some(suffix)),
+            None => Err(LexErrorReason::InvalidSuffix(s)),
+        }
+    }
+
+    fn int_token(n: u64, suffix: Option<NumSuffix>) -> Result<Token, LexErrorReason> {
+        if let Some(max) = suffix.and_then(|s| s.max_magnitude())
+            && n > max
+        {
+            return Err(LexErrorReason::NumberOverflow);
+        }
+        Ok(Token::NUMBER(n, suffix))
+    }
+
+    // a '.' only starts a fraction when a digit follows it
+    fn at_fraction(&self, radix: u32) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.next() == Some('.') && ahead.next().is_some_and(|c| c.is_digit(radix))
+    }
+
+    // skips the rest of a malformed word so lexing resumes after it
+    fn skip_word(&mut self) {
+        while let Some(&ch) = self.chars.peek()
+            && is_xid_continue(ch)
+        {
+            self.next_char();
+        }
+    }
+
+    // a literal glued to letters or digits outside its radix is illegal
+    fn reject_glued(&mut self, radix: u32) -> Result<(), LexErrorReason> {
+        if let Some(&digit) = self.chars.peek()
+            && is_xid_continue(digit)
+        {
+            self.skip_word();
+            Err(LexErrorReason::InvalidDigit { digit, radix })
+        } else {
+            Ok(())
+        }
+    }
+
+    // 12, 1_000, 1.5, 2e-3, 1.5E+3, 10i64, 3f
+    fn expect_decimal(&mut self) -> Result<Token, LexErrorReason> {
+        let mut s = self.read_digits(10);
+        let mut float = false;
+        if self.at_fraction(10) {
+            self.next_char();
+            s.push('.');
+            s.push_str(&self.read_digits(10));
+            float = true;
+        }
+        if let Some('e' | 'E') = self.chars.peek() {
+            self.next_char();
+            s.push('e');
+            s.push_str(&self.read_exponent()?);
+            float = true;
+        }
+        let suffix = self.read_suffix(10)?;
+
+        match suffix {
+            Some(sfx) if float && !sfx.is_float() => {
+                Err(LexErrorReason::InvalidSuffix(sfx.name().to_string()))
+            }
+            // out of range floats round to infinity rather than failing
+            Some(sfx) if sfx.is_float() => Ok(Token::FLOATNUMBER(s.parse().unwrap(), suffix)),
+            None if float => Ok(Token::FLOATNUMBER(s.parse().unwrap(), None)),
+            _ => {
+                let n = s.parse().map_err(|_| LexErrorReason::NumberOverflow)?;
+                Self::int_token(n, suffix)
+            }
+        }
+    }
+
+    // 0x1F, or a hex float with a binary exponent such as 0x1.8p3
+    fn expect_hex(&mut self) -> Result<Token, LexErrorReason> {
+        let int = self.read_digits(16);
+        let frac = if self.at_fraction(16) {
+            self.next_char();
+            self.read_digits(16)
+        } else {
+            String::new()
+        };
+
+        if let Some('p' | 'P') = self.chars.peek() {
+            self.next_char();
+            let exp: i32 = self
+                .read_exponent()?
+                .parse()
+                .map_err(|_| LexErrorReason::NumberOverflow)?;
+            let suffix = self.read_suffix(10)?;
+            if let Some(sfx) = suffix
+                && !sfx.is_float()
+            {
+                return Err(LexErrorReason::InvalidSuffix(sfx.name().to_string()));
+            }
+
+            let mantissa = int
+                .chars()
+                .chain(frac.chars())
+                .fold(0f64, |m, c| m * 16.0 + c.to_digit(16).unwrap() as f64);
+            let exp = exp.saturating_sub(4 * frac.len() as i32);
+            Ok(Token::FLOATNUMBER(mantissa * 2f64.powi(exp), suffix))
+        } else {
+            let suffix = self.read_suffix(16)?;
+            if int.is_empty() || !frac.is_empty() {
+                // a hex fraction needs a binary exponent
+                return Err(LexErrorReason::MissingDigits { radix: 16 });
+            }
+            let n = u64::from_str_radix(&int, 16).map_err(|_| LexErrorReason::NumberOverflow)?;
+            Self::int_token(n, suffix)
+        }
+    }
+
+    // contents of a quoted literal after the opening quote, a bad escape
+    // is reported once the closing quote is found
+    fn read_text(&mut self, quote: char) -> Result<String, LexErrorReason> {
+        let mut s = String::new();
+        let mut error = None;
+        loop {
+            match self.chars.peek().copied() {
+                None | Some('\n') => return Err(LexErrorReason::UnterminatedLiteral),
+                Some('\\') => {
+                    self.next_char();
+                    match self.read_escape() {
+                        Ok(c) => s.push(c),
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+                Some(c) => {
                     self.next_char();
-                    return None;
+                    if c == quote {
+                        break;
+                    }
+                    s.push(c);
                 }
-                break;
             }
         }
-        if parsed == 0 { None } else { Some(result) }
+        error.map_or(Ok(s), Err)
+    }
+
+    // after the backslash
+    fn read_escape(&mut self) -> Result<char, LexErrorReason> {
+        let c = match self.chars.peek().copied() {
+            // left for read_text to report as unterminated
+            None | Some('\n') => return Err(LexErrorReason::UnterminatedLiteral),
+            Some(c) => c,
+        };
+        self.next_char();
+        Ok(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' | '"' | '\'' => c,
+            'u' => return self.read_unicode_escape(),
+            _ => return Err(LexErrorReason::InvalidEscape(c)),
+        })
+    }
+
+    // {1F600} after \u
+    fn read_unicode_escape(&mut self) -> Result<char, LexErrorReason> {
+        if self.chars.peek() != Some(&'{') {
+            return Err(LexErrorReason::InvalidUnicodeEscape);
+        }
+        self.next_char();
+        let mut digits = String::new();
+        while let Some(&ch) = self.chars.peek()
+            && ch.is_ascii_hexdigit()
+        {
+            digits.push(ch);
+            self.next_char();
+        }
+        if self.chars.peek() != Some(&'}') || digits.is_empty() || digits.len() > 6 {
+            return Err(LexErrorReason::InvalidUnicodeEscape);
+        }
+        self.next_char();
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or(LexErrorReason::InvalidUnicodeEscape)
+    }
+
+    fn expect_char(&mut self) -> Result<Token, LexErrorReason> {
+        let text = self.read_text('\'')?;
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Token::CHARLIT(c)),
+            _ => Err(LexErrorReason::InvalidCharLiteral),
+        }
     }
 
     fn match_single(c: char) -> Option<Token> {
         match c {
             '*' => Some(Token::ASTER),
             ',' => Some(Token::COMMA),
+            '.' => Some(Token::DOT),
+            ':' => Some(Token::COLON),
+            '?' => Some(Token::QUESTION),
             '/' => Some(Token::SLASH),
             '(' => Some(Token::LPAREN),
             ')' => Some(Token::RPAREN),
             '{' => Some(Token::LCURL),
+            '[' => Some(Token::LBRACKET),
+            ']' => Some(Token::RBRACKET),
             '<' => Some(Token::LT),
             '>' => Some(Token::GT),
             '}' => Some(Token::RCURL),
             ';' => Some(Token::SEMICOLON),
             '=' => Some(Token::EQUAL),
+            '+' => Some(Token::PLUS),
+            '-' => Some(Token::MINUS),
+            '%' => Some(Token::PERCENT),
+            '!' => Some(Token::BANG),
+            '&' => Some(Token::AMP),
+            '|' => Some(Token::PIPE),
+            '^' => Some(Token::CARET),
+            '~' => Some(Token::TILDE),
             _ => None,
         }
     }
 
-    fn match_minus_second(c: char) -> Option<Token> {
-        match c {
-            '-' => Some(Token::MINUSMINUS),
-            '>' => Some(Token::ARROW),
+    // two char tokens, checked before match_single
+    fn match_double(c: char, second: char) -> Option<Token> {
+        match (c, second) {
+            ('-', '-') => Some(Token::MINUSMINUS),
+            ('-', '>') => Some(Token::ARROW),
+            ('+', '+') => Some(Token::PLUSPLUS),
+            ('+', '=') => Some(Token::PLUSEQUAL),
+            ('-', '=') => Some(Token::MINUSEQUAL),
+            ('*', '=') => Some(Token::ASTEREQUAL),
+            ('/', '=') => Some(Token::SLASHEQUAL),
+            ('%', '=') => Some(Token::PERCENTEQUAL),
+            ('=', '=') => Some(Token::EQUALEQUAL),
+            ('=', '>') => Some(Token::FATARROW),
+            ('!', '=') => Some(Token::BANGEQUAL),
+            ('<', '=') => Some(Token::LTEQUAL),
+            ('>', '=') => Some(Token::GTEQUAL),
+            ('<', '<') => Some(Token::LTLT),
+            ('>', '>') => Some(Token::GTGT),
+            ('&', '&') => Some(Token::AMPAMP),
+            ('|', '|') => Some(Token::PIPEPIPE),
+            (':', ':') => Some(Token::COLONCOLON),
             _ => None,
         }
     }
 
-    fn match_plus_second(c: char) -> Option<Token> {
-        match c {
-            '+' => Some(Token::PLUSPLUS),
-            _ => None,
-        }
-    }
-
-    fn expect_number(&mut self, radix: u32) -> Token {
-        if let Some(n) = self.read_num(radix) {
-            Token::NUMBER(n)
-        } else {
-            Token::ILLEGAL
-        }
+    fn peek_second(&self) -> Option<char> {
+        let mut ahead = self.chars.clone();
+        ahead.next();
+        ahead.next()
     }
 
-    fn expect_string(&mut self) -> Token {
+    // the first char was already checked by is_ident_start
+    fn expect_ident(&mut self) -> Token {
         let mut s = String::with_capacity(16);
-        while let Some(x) = self.chars.peek()
-            // 0..9 not first char
-            && let 'a'..='z' | 'A'..='Z' | '0'..='9' = x
+        while let Some(&x) = self.chars.peek()
+            && is_xid_continue(x)
         {
-            s.push(*x);
+            s.push(x);
             self.next_char();
         }
-        if s.len() == 0 {
-            return Token::ILLEGAL;
-        }
         if let Some(kwd) = Self::lookup_keyword(s.as_str()) {
             kwd
         } else {
@@ -124,86 +556,114 @@ impl<'a> Lexer<'a> {
 
     fn lookup_keyword(s: &str) -> Option<Token> {
         match s {
+            "as" => Some(Token::AS),
+            "bool" => Some(Token::BOOL),
+            "break" => Some(Token::BREAK),
+            "char" => Some(Token::CHAR),
+            "continue" => Some(Token::CONTINUE),
+            "do" => Some(Token::DO),
             "else" => Some(Token::ELSE),
+            "enum" => Some(Token::ENUM),
+            "f32" => Some(Token::F32),
+            "f64" => Some(Token::F64),
+            "false" => Some(Token::FALSE),
             "float" => Some(Token::FLOAT),
             "fn" => Some(Token::FN),
+            "for" => Some(Token::FOR),
+            "i8" => Some(Token::I8),
+            "i16" => Some(Token::I16),
+            "i32" => Some(Token::I32),
+            "i64" => Some(Token::I64),
             "if" => Some(Token::IF),
+            "import" => Some(Token::IMPORT),
             "int" => Some(Token::INT),
+            "loop" => Some(Token::LOOP),
+            "match" => Some(Token::MATCH),
+            "natural" => Some(Token::NATURAL),
+            "real" => Some(Token::REAL),
             "return" => Some(Token::RETURN),
+            "string" => Some(Token::STRING),
+            "struct" => Some(Token::STRUCT),
+            "true" => Some(Token::TRUE),
+            "u8" => Some(Token::U8),
+            "u16" => Some(Token::U16),
+            "u32" => Some(Token::U32),
+            "u64" => Some(Token::U64),
             "while" => Some(Token::WHILE),
             _ => None,
         }
     }
 
     fn next_token(&mut self) -> Option<LexedToken> {
-        self.consume_whitespace();
+        if let Some(unterminated) = self.consume_trivia() {
+            return Some(unterminated);
+        }
+        let start = self.pos;
         if let Some(ch) = self.chars.peek().cloned() {
-            let tk = if let Some(t) = Lexer::match_single(ch) {
+            let tk = if self.is_doc_comment() {
+                self.expect_doc()
+            } else if let Some(second) = self.peek_second()
+                && let Some(t) = Lexer::match_double(ch, second)
+            {
+                self.next_char();
+                self.next_char();
+                t
+            } else if let Some(t) = Lexer::match_single(ch) {
                 self.next_char();
                 t
             } else {
-                match ch {
-                    '-' => {
-                        self.chars.next();
-                        if let Some(nch) = self.chars.peek()
-                            && let Some(tok) = Self::match_minus_second(*nch)
-                        {
-                            self.chars.next();
-                            tok
-                        } else {
-                            Token::MINUS
-                        }
-                    }
-                    '+' => {
-                        self.chars.next();
-                        if let Some(nch) = self.chars.peek()
-                            && let Some(tok) = Self::match_plus_second(*nch)
-                        {
-                            self.chars.next();
-                            tok
-                        } else {
-                            Token::PLUS
-                        }
-                    }
+                let result = match ch {
                     '0' => {
-                        self.next_char();
-                        if let Some(pfx) = self.chars.peek().cloned() {
-                            if pfx.is_numeric() {
-                                Token::ILLEGAL
-                            } else if pfx.is_alphabetic() {
+                        let mut ahead = self.chars.clone();
+                        ahead.next();
+                        match ahead.next() {
+                            Some('x') => {
+                                self.next_char();
                                 self.next_char();
-                                match pfx {
-                                    'x' => self.expect_number(16),
-                                    'o' => self.expect_number(8),
-                                    'b' => self.expect_number(1),
-                                    _ => Token::ILLEGAL,
-                                }
-                            } else {
-                                Token::NUMBER(0)
+                                self.expect_hex()
+                            }
+                            Some('o') => {
+                                self.next_char();
+                                self.next_char();
+                                self.expect_radix(8)
+                            }
+                            Some('b') => {
+                                self.next_char();
+                                self.next_char();
+                                self.expect_radix(2)
+                            }
+                            Some(d) if d.is_ascii_digit() => {
+                                self.skip_word();
+                                Err(LexErrorReason::LeadingZero)
                             }
-                        } else {
-                            Token::NUMBER(0)
+                            _ => self.expect_decimal(),
                         }
                     }
-                    '1'..='9' => self.expect_number(10),
-                    'a'..='z' | 'A'..='Z' => self.expect_string(),
-                    _ => Token::ILLEGAL,
-                }
+                    '1'..='9' => self.expect_decimal(),
+                    '"' => {
+                        self.next_char();
+                        self.read_text('"').map(Token::STRINGLIT)
+                    }
+                    '\'' => {
+                        self.next_char();
+                        self.expect_char()
+                    }
+                    c if is_ident_start(c) => Ok(self.expect_ident()),
+                    _ => {
+                        self.next_char();
+                        Err(LexErrorReason::UnexpectedChar(ch))
+                    }
+                };
+                result.unwrap_or_else(Token::ILLEGAL)
             };
 
-            Some(LexedToken {
-                info: TokenInfo { position: self.pos },
-                token: tk,
-            })
+            Some(self.lexed(start, tk))
         } else {
             if self.end {
                 None
             } else {
                 self.end = true;
-                Some(LexedToken {
-                    info: TokenInfo { position: self.pos },
-                    token: Token::EOF,
-                })
+                Some(self.lexed(start, Token::EOF))
             }
         }
     }
@@ -216,3 +676,238 @@ impl<'a> Iterator for Lexer<'a> {
         self.next_token()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{LexWarningReason, LexedToken, Lexer, check_idents};
+    use crate::tokens::{LexErrorReason, NumSuffix, Token};
+
+    #[test]
+    fn lex_spans() {
+        let spans: Vec<(Token, u32, u32)> = Lexer::new("a += -1 -> €".chars().peekable())
+            .map(|t| (t.token, t.info.span.start, t.info.span.end))
+            .collect();
+
+        assert_eq!(
+            spans,
+            vec![
+                (Token::IDENT("a".to_string()), 0, 1),
+                (Token::PLUSEQUAL, 2, 4),
+                (Token::MINUS, 5, 6),
+                (Token::NUMBER(1, None), 6, 7),
+                (Token::ARROW, 8, 10),
+                (Token::ILLEGAL(LexErrorReason::UnexpectedChar('€')), 11, 14),
+                (Token::EOF, 14, 14),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_floats() {
+        let source = "1.5 0.25 2e-3 1E2 0x1.8p1 0x10 0x1p-2 7 1.x 1e";
+        let tokens: Vec<Token> = Lexer::new(source.chars().peekable())
+            .map(|t| t.token)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::FLOATNUMBER(1.5, None),
+                Token::FLOATNUMBER(0.25, None),
+                Token::FLOATNUMBER(0.002, None),
+                Token::FLOATNUMBER(100.0, None),
+                Token::FLOATNUMBER(3.0, None),
+                Token::NUMBER(16, None),
+                Token::FLOATNUMBER(0.25, None),
+                Token::NUMBER(7, None),
+                Token::NUMBER(1, None),
+                Token::DOT,
+                Token::IDENT("x".to_string()),
+                Token::ILLEGAL(LexErrorReason::MissingDigits { radix: 10 }),
+                Token::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_operators() {
+        let source = "a==b!=c<=d>=e%f&&g||!h&i|j^k<<l>>~m=n";
+        let tokens: Vec<Token> = Lexer::new(source.chars().peekable())
+            .map(|t| t.token)
+            .filter(|t| !matches!(t, Token::IDENT(_)))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::EQUALEQUAL,
+                Token::BANGEQUAL,
+                Token::LTEQUAL,
+                Token::GTEQUAL,
+                Token::PERCENT,
+                Token::AMPAMP,
+                Token::PIPEPIPE,
+                Token::BANG,
+                Token::AMP,
+                Token::PIPE,
+                Token::CARET,
+                Token::LTLT,
+                Token::GTGT,
+                Token::TILDE,
+                Token::EQUAL,
+                Token::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_ints() {
+        let source =
+            "1_000_000 0b1010_1010 0o17 0xFF_FF 18446744073709551615 10i64 255u8 3f 2.5f32 0x1p4f";
+        let tokens: Vec<Token> = Lexer::new(source.chars().peekable())
+            .map(|t| t.token)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::NUMBER(1_000_000, None),
+                Token::NUMBER(0b1010_1010, None),
+                Token::NUMBER(0o17, None),
+                Token::NUMBER(0xFFFF, None),
+                Token::NUMBER(u64::MAX, None),
+                Token::NUMBER(10, Some(NumSuffix::I64)),
+                Token::NUMBER(255, Some(NumSuffix::U8)),
+                Token::FLOATNUMBER(3.0, Some(NumSuffix::F64)),
+                Token::FLOATNUMBER(2.5, Some(NumSuffix::F32)),
+                Token::FLOATNUMBER(16.0, Some(NumSuffix::F64)),
+                Token::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_errors_resync() {
+        let source = "a = 12abc $ 0x 007 99999999999999999999 0q1 300u8 1.5i32 2q8;";
+        let errors: Vec<(LexErrorReason, u32, u32)> = Lexer::new(source.chars().peekable())
+            .filter_map(|t| t.error())
+            .map(|e| (e.reason, e.span.start, e.span.end))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    LexErrorReason::InvalidDigit {
+                        digit: 'a',
+                        radix: 10
+                    },
+                    4,
+                    9
+                ),
+                (LexErrorReason::UnexpectedChar('$'), 10, 11),
+                (LexErrorReason::MissingDigits { radix: 16 }, 12, 14),
+                (LexErrorReason::LeadingZero, 15, 18),
+                (LexErrorReason::NumberOverflow, 19, 39),
+                (
+                    LexErrorReason::InvalidDigit {
+                        digit: 'q',
+                        radix: 10
+                    },
+                    40,
+                    43
+                ),
+                (LexErrorReason::NumberOverflow, 44, 49),
+                (LexErrorReason::InvalidSuffix("i32".to_string()), 50, 56),
+                (
+                    LexErrorReason::InvalidDigit {
+                        digit: 'q',
+                        radix: 10
+                    },
+                    57,
+                    60
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_idents() {
+        let source = "snake_case _tmp x1 größe 変数";
+        let tokens: Vec<Token> = Lexer::new(source.chars().peekable())
+            .map(|t| t.token)
+            .collect();
+
+        let idents = ["snake_case", "_tmp", "x1", "größe", "変数"];
+        let mut expected: Vec<Token> = idents.iter().map(|s| Token::IDENT(s.to_string())).collect();
+        expected.push(Token::EOF);
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn ident_warnings() {
+        // the second ident spells "scope" with a Cyrillic о, each ident is
+        // reported at its first use only
+        let source = "scope sc\u{43e}pe nаme sc\u{43e}pe = nаme;";
+        let tokens: Vec<LexedToken> = Lexer::new(source.chars().peekable()).collect();
+
+        let reasons: Vec<LexWarningReason> = check_idents(tokens.iter())
+            .into_iter()
+            .map(|w| w.reason)
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                LexWarningReason::MixedScriptIdent("scоpe".to_string()),
+                LexWarningReason::ConfusableIdent {
+                    ident: "scоpe".to_string(),
+                    other: "scope".to_string(),
+                },
+                LexWarningReason::MixedScriptIdent("nаme".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_text() {
+        let source = r#""hi\n\t\\\"" 'a' '\'' "\u{1F600}" "bad\q" 'ab' "open
+'x'"#;
+        let tokens: Vec<Token> = Lexer::new(source.chars().peekable())
+            .map(|t| t.token)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::STRINGLIT("hi\n\t\\\"".to_string()),
+                Token::CHARLIT('a'),
+                Token::CHARLIT('\''),
+                Token::STRINGLIT("\u{1F600}".to_string()),
+                Token::ILLEGAL(LexErrorReason::InvalidEscape('q')),
+                Token::ILLEGAL(LexErrorReason::InvalidCharLiteral),
+                Token::ILLEGAL(LexErrorReason::UnterminatedLiteral),
+                Token::CHARLIT('x'),
+                Token::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_comments() {
+        let source = "a // b\n/* c /* d */ e */ f\n/// g\n//// h\n/* i";
+        let tokens: Vec<Token> = Lexer::new(source.chars().peekable())
+            .map(|t| t.token)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::IDENT("a".to_string()),
+                Token::IDENT("f".to_string()),
+                Token::DOC("g".to_string()),
+                Token::ILLEGAL(LexErrorReason::UnterminatedComment),
+                Token::EOF,
+            ]
+        );
+    }
+}
@@ -1,29 +1,207 @@
-use crate::ast::Statement;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::ast::{Statement, StatementKind};
+use crate::cli::{CliError, Command, Input, Stage};
+use crate::diag::{Diagnostic, Emitter};
+use crate::lexer::{LexWarning, LexedToken, Lexer, check_idents};
+use crate::module::Resolver;
 use crate::parser::Parser;
+use crate::source::{FileId, SourceMap, Span};
 use crate::util::TPrint;
 
+pub mod analysis;
 pub mod ast;
+pub mod capture;
+pub mod check;
+pub mod cli;
+pub mod diag;
+pub mod interp;
 pub mod ir;
 pub mod lexer;
+pub mod module;
+pub mod mono;
 pub mod parser;
+pub mod source;
 pub mod tokens;
 pub mod util;
 
-fn main() {
-    let source = include_str!("ro/fib.ro");
-    let mut parser = Parser::new(source.chars());
+// the input had errors
+const EXIT_FAILURE: u8 = 1;
+// bad arguments or unreadable input
+const EXIT_USAGE: u8 = 2;
+
+fn read_input(input: &Input) -> io::Result<(String, String)> {
+    match input {
+        Input::Stdin => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            Ok(("<stdin>".to_string(), source))
+        }
+        Input::File(path) => Ok((path.display().to_string(), std::fs::read_to_string(path)?)),
+    }
+}
 
-    let stmts = parser.parse_top().unwrap_or_else(|e| {
-        eprintln!("parse_top failed: {e:?}");
-        panic!("fib.ro should parse");
-    });
+// where imports of an input are resolved from
+struct Imports<'a> {
+    root: Option<&'a Path>,
+    search: &'a [PathBuf],
+}
+
+// parses an input and everything it imports
+fn parse(
+    emitter: &Emitter,
+    map: &mut SourceMap,
+    file: FileId,
+    imports: &Imports,
+) -> Option<Vec<Statement>> {
+    let tokens: Vec<LexedToken> =
+        Lexer::with_file(map.get(file).source.chars().peekable(), file).collect();
+    warn(emitter, map, file, check_idents(tokens.iter()));
+    let (stmts, errors) = Parser::from_tokens(tokens.into_iter()).parse_top();
+    if !errors.is_empty() {
+        for e in errors {
+            emitter.emit(map, file, &e.diagnostic());
+        }
+        return None;
+    }
+
+    let mut resolver = Resolver::new(map, imports.search);
+    let resolved = resolver.resolve(imports.root, stmts);
+    let warnings = std::mem::take(&mut resolver.warnings);
+    warn(emitter, map, file, warnings);
+    match resolved {
+        Ok(mut stmts) => {
+            capture::resolve(&mut stmts);
+            Some(stmts)
+        }
+        Err(errors) => {
+            for e in errors {
+                emitter.emit(map, file, &e.diagnostic());
+            }
+            None
+        }
+    }
+}
+
+fn warn(emitter: &Emitter, map: &SourceMap, file: FileId, warnings: Vec<LexWarning>) {
+    for w in warnings {
+        let diag = Diagnostic::warning(w.reason.to_string()).with_primary(w.span, "");
+        emitter.emit(map, file, &diag);
+    }
+}
+
+// checks a parsed input, giving it back with generic functions replaced by
+// their instances
+fn check(
+    emitter: &Emitter,
+    map: &SourceMap,
+    file: FileId,
+    stmts: Vec<Statement>,
+) -> Option<Vec<Statement>> {
+    match check::check(&stmts) {
+        Ok(instances) => Some(mono::monomorphize(stmts, &instances)),
+        Err(errors) => {
+            for e in errors {
+                emitter.emit(map, file, &e.diagnostic());
+            }
+            None
+        }
+    }
+}
+
+// returns false if the input had errors
+fn execute(
+    command: &Command,
+    emitter: &Emitter,
+    map: &mut SourceMap,
+    file: FileId,
+    imports: &Imports,
+) -> bool {
+    match command {
+        Command::Lex => {
+            let mut ok = true;
+            for t in Lexer::with_file(map.get(file).source.chars().peekable(), file) {
+                let loc = map.line_col(file, t.info.span.start).to_string();
+                println!("{:>7} {:?}", loc, t.token);
+                if let Some(e) = t.error() {
+                    let diag = Diagnostic::error(e.reason.to_string()).with_primary(e.span, "");
+                    emitter.emit(map, file, &diag);
+                    ok = false;
+                }
+            }
+            ok
+        }
+        Command::Parse => parse(emitter, map, file, imports).is_some(),
+        Command::Check => parse(emitter, map, file, imports)
+            .is_some_and(|stmts| check(emitter, map, file, stmts).is_some()),
+        Command::Run => {
+            let Some(stmts) = parse(emitter, map, file, imports) else {
+                return false;
+            };
+            let Some(stmts) = check(emitter, map, file, stmts) else {
+                return false;
+            };
+            if let Err(e) = interp::run(&stmts, io::stdout()) {
+                let mut diag = Diagnostic::error(format!("runtime error: {}", e.reason));
+                if let Some(span) = e.span {
+                    diag = diag.with_primary(span, "");
+                }
+                emitter.emit(map, file, &diag);
+                return false;
+            }
+            true
+        }
+        Command::Emit(Stage::Ast) => {
+            let Some(stmts) = parse(emitter, map, file, imports) else {
+                return false;
+            };
+            Statement::new(StatementKind::Block(stmts), Span::default()).tprint();
+            true
+        }
+    }
+}
 
-    let blk = Statement::Block(stmts);
-    println!("basic.ro AST:");
-    blk.tprint();
+fn main() -> ExitCode {
+    let opts = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(CliError::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{}", cli::USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
 
-    /* while let Some(t) = lexer.next() {
-        println!("{:?}", t);
+    let emitter = Emitter::new(opts.error_format, opts.color);
+    let mut map = SourceMap::new();
+    let mut ok = true;
+    for input in &opts.inputs {
+        match read_input(input) {
+            Ok((name, source)) => {
+                let file = map.add(name, source);
+                let imports = Imports {
+                    root: match input {
+                        Input::File(path) => Some(path),
+                        Input::Stdin => None,
+                    },
+                    search: &opts.search,
+                };
+                ok &= execute(&opts.command, &emitter, &mut map, file, &imports);
+            }
+            Err(e) => {
+                eprintln!("error: cannot read {input}: {e}");
+                return ExitCode::from(EXIT_USAGE);
+            }
+        }
+    }
 
-    } */
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_FAILURE)
+    }
 }
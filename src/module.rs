@@ -0,0 +1,630 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::ast::*;
+use crate::diag::Diagnostic;
+use crate::lexer::{LexWarning, LexedToken, Lexer, check_idents};
+use crate::parser::{ParseError, Parser};
+use crate::source::{SourceMap, Span};
+
+#[derive(Debug)]
+pub struct ModuleError {
+    pub span: Option<Span>,
+    pub reason: ModuleErrorReason,
+}
+
+impl ModuleError {
+    pub fn new(span: Option<Span>, reason: ModuleErrorReason) -> Self {
+        Self { span, reason }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = match &self.reason {
+            ModuleErrorReason::Cycle(chain) => {
+                Diagnostic::error(format!("import cycle: {}", chain.join(" -> ")))
+            }
+            ModuleErrorReason::DuplicateModule { module, first } => {
+                Diagnostic::error(format!("two different files are named `{module}`")).with_note(
+                    format!("`{module}` was first imported from {}", first.display()),
+                )
+            }
+            ModuleErrorReason::NotFound { path, tried } => {
+                let tried: Vec<String> = tried.iter().map(|p| p.display().to_string()).collect();
+                Diagnostic::error(format!("cannot find module `{path}`"))
+                    .with_note(format!("looked for {}", tried.join(", ")))
+            }
+            ModuleErrorReason::Parse(e) => return e.diagnostic(),
+            ModuleErrorReason::Read { path, error } => {
+                Diagnostic::error(format!("cannot read {}: {error}", path.display()))
+            }
+            ModuleErrorReason::StatementInModule(module) => Diagnostic::error(format!(
+                "module `{module}` may only contain functions, types and imports"
+            )),
+        };
+        match self.span {
+            Some(span) => diag.with_primary(span, ""),
+            None => diag,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ModuleErrorReason {
+    // module names from the first import of the cycle back to itself
+    Cycle(Vec<String>),
+    DuplicateModule { module: String, first: PathBuf },
+    NotFound { path: String, tried: Vec<PathBuf> },
+    Parse(ParseError),
+    Read { path: PathBuf, error: String },
+    StatementInModule(String),
+}
+
+// loads every module a program imports and merges their functions and types
+// into it under their namespace, math.ro's sqrt becomes math::sqrt
+pub struct Resolver<'m> {
+    map: &'m mut SourceMap,
+    search: &'m [PathBuf],
+    // canonical paths of the modules being loaded, innermost last
+    stack: Vec<(PathBuf, String)>,
+    modules: HashMap<String, PathBuf>,
+    loaded: HashSet<PathBuf>,
+    // functions and types of every module, already qualified
+    items: Vec<Statement>,
+    errors: Vec<ModuleError>,
+    // identifier warnings from the modules, the program's are its own
+    pub warnings: Vec<LexWarning>,
+}
+
+impl<'m> Resolver<'m> {
+    pub fn new(map: &'m mut SourceMap, search: &'m [PathBuf]) -> Self {
+        Self {
+            map,
+            search,
+            stack: Vec::new(),
+            modules: HashMap::new(),
+            loaded: HashSet::new(),
+            items: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // root is the path of the program, None when it has no file
+    pub fn resolve(
+        &mut self,
+        root: Option<&Path>,
+        stmts: Vec<Statement>,
+    ) -> Result<Vec<Statement>, Vec<ModuleError>> {
+        let dir = root
+            .and_then(Path::parent)
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        if let Some(canon) = root.and_then(|r| r.canonicalize().ok()) {
+            let name = module_name(&canon.to_string_lossy());
+            self.stack.push((canon, name));
+        }
+
+        let stmts = self.load_imports(&dir, stmts);
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
+        let mut program = std::mem::take(&mut self.items);
+        program.extend(stmts);
+        Ok(program)
+    }
+
+    // loads the top level imports of stmts and returns the rest
+    fn load_imports(&mut self, dir: &Path, stmts: Vec<Statement>) -> Vec<Statement> {
+        let mut rest = Vec::new();
+        for stmt in stmts {
+            match &stmt.kind {
+                StatementKind::Import(i) => self.load(dir, i),
+                _ => rest.push(stmt),
+            }
+        }
+        rest
+    }
+
+    fn load(&mut self, dir: &Path, import: &SImport) {
+        let Some(path) = self.locate(dir, import) else {
+            return;
+        };
+        let canon = path.canonicalize().unwrap_or_else(|_| path.clone());
+
+        if let Some(start) = self.stack.iter().position(|(p, _)| *p == canon) {
+            let mut chain: Vec<String> =
+                self.stack[start..].iter().map(|(_, m)| m.clone()).collect();
+            chain.push(import.module.clone());
+            self.error(import.span, ModuleErrorReason::Cycle(chain));
+            return;
+        }
+        match self.modules.get(&import.module) {
+            Some(first) if *first != canon => {
+                let reason = ModuleErrorReason::DuplicateModule {
+                    module: import.module.clone(),
+                    first: first.clone(),
+                };
+                self.error(import.span, reason);
+                return;
+            }
+            _ => {}
+        }
+        if !self.loaded.insert(canon.clone()) {
+            return;
+        }
+        self.modules.insert(import.module.clone(), canon.clone());
+
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                let reason = ModuleErrorReason::Read {
+                    path,
+                    error: e.to_string(),
+                };
+                self.error(import.span, reason);
+                return;
+            }
+        };
+        let file = self.map.add(path.display().to_string(), source);
+        let tokens: Vec<LexedToken> =
+            Lexer::with_file(self.map.get(file).source.chars().peekable(), file).collect();
+        self.warnings.extend(check_idents(tokens.iter()));
+        let (stmts, errors) = Parser::from_tokens(tokens.into_iter()).parse_top();
+        // what was recovered around a parse error would only add more errors
+        if !errors.is_empty() {
+            for e in errors {
+                self.errors
+                    .push(ModuleError::new(None, ModuleErrorReason::Parse(e)));
+            }
+            return;
+        }
+
+        self.stack.push((canon, import.module.clone()));
+        let module_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let mut stmts = self.load_imports(&module_dir, stmts);
+        self.stack.pop();
+
+        let names: HashSet<String> = stmts
+            .iter()
+            .filter_map(|s| match &s.kind {
+                StatementKind::Function(f) => Some(f.ident.clone()),
+                StatementKind::Struct(s) => Some(s.ident.clone()),
+                StatementKind::Enum(e) => Some(e.ident.clone()),
+                _ => None,
+            })
+            .collect();
+        Qualifier {
+            module: &import.module,
+            names: &names,
+            locals: Vec::new(),
+            generics: Vec::new(),
+        }
+        .stmts(&mut stmts);
+        for mut stmt in stmts {
+            let ident = match &mut stmt.kind {
+                StatementKind::Function(f) => &mut f.ident,
+                StatementKind::Struct(s) => &mut s.ident,
+                StatementKind::Enum(e) => &mut e.ident,
+                _ => {
+                    let reason = ModuleErrorReason::StatementInModule(import.module.clone());
+                    self.error(import.span, reason);
+                    return;
+                }
+            };
+            *ident = format!("{}::{}", import.module, ident);
+            self.items.push(stmt);
+        }
+    }
+
+    // next to the importer first, then along the search path
+    fn locate(&mut self, dir: &Path, import: &SImport) -> Option<PathBuf> {
+        let tried: Vec<PathBuf> = std::iter::once(dir)
+            .chain(self.search.iter().map(PathBuf::as_path))
+            .map(|d| d.join(&import.path))
+            .collect();
+        if let Some(found) = tried.iter().find(|p| p.is_file()) {
+            return Some(found.clone());
+        }
+        let reason = ModuleErrorReason::NotFound {
+            path: import.path.clone(),
+            tried,
+        };
+        self.error(import.span, reason);
+        None
+    }
+
+    fn error(&mut self, span: Span, reason: ModuleErrorReason) {
+        self.errors.push(ModuleError::new(Some(span), reason));
+    }
+}
+
+fn module_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map_or(path.to_string(), |s| s.to_string_lossy().into())
+}
+
+// qualifies uses of a module's own functions and types
+struct Qualifier<'n> {
+    module: &'n str,
+    names: &'n HashSet<String>,
+    // variables in scope, which hide functions of the same name
+    locals: Vec<HashSet<String>>,
+    // type parameters of the generic function being walked
+    generics: Vec<String>,
+}
+
+impl Qualifier<'_> {
+    fn block(&mut self, stmts: &mut [Statement]) {
+        self.locals.push(HashSet::new());
+        self.stmts(stmts);
+        self.locals.pop();
+    }
+
+    fn declare(&mut self, ident: &str) {
+        if let Some(scope) = self.locals.last_mut() {
+            scope.insert(ident.to_string());
+        }
+    }
+
+    fn is_local(&self, ident: &str) -> bool {
+        self.locals.iter().any(|s| s.contains(ident))
+    }
+
+    fn stmts(&mut self, stmts: &mut [Statement]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Statement) {
+        match &mut stmt.kind {
+            StatementKind::If(sif) => {
+                self.expr(&mut sif.guard);
+                self.block(&mut sif.t);
+                for e in &mut sif.elifs {
+                    self.expr(&mut e.guard);
+                    self.block(&mut e.body);
+                }
+                if let Some(f) = &mut sif.f {
+                    self.block(f);
+                }
+            }
+            StatementKind::Declare(d) => {
+                self.typ(&mut d.typ);
+                if let Some(e) = &mut d.assign {
+                    self.expr(e);
+                }
+                self.declare(&d.ident);
+            }
+            StatementKind::Assign(a) => {
+                for access in &mut a.target.path {
+                    if let Access::Index(index) = access {
+                        self.expr(index);
+                    }
+                }
+                self.expr(&mut a.value);
+            }
+            StatementKind::While(w) => {
+                self.expr(&mut w.cond);
+                self.block(&mut w.body);
+            }
+            StatementKind::For(f) => {
+                self.locals.push(HashSet::new());
+                if let Some(init) = &mut f.init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = &mut f.cond {
+                    self.expr(cond);
+                }
+                if let Some(step) = &mut f.step {
+                    self.stmt(step);
+                }
+                self.block(&mut f.body);
+                self.locals.pop();
+            }
+            StatementKind::Loop(l) => self.block(&mut l.body),
+            StatementKind::DoWhile(d) => {
+                self.block(&mut d.body);
+                self.expr(&mut d.cond);
+            }
+            StatementKind::Expr(e) | StatementKind::Return(e) => self.expr(e),
+            StatementKind::Function(f) => {
+                self.locals.push(HashSet::new());
+                self.generics = f.generics.clone();
+                for (typ, param) in &mut f.params {
+                    self.typ(typ);
+                    self.declare(param);
+                }
+                self.typ(&mut f.ret);
+                self.stmts(&mut f.body);
+                self.generics.clear();
+                self.locals.pop();
+            }
+            StatementKind::Struct(s) => {
+                for (typ, _) in &mut s.fields {
+                    self.typ(typ);
+                }
+            }
+            StatementKind::Enum(e) => {
+                for typ in e.variants.iter_mut().flat_map(|(_, payload)| payload) {
+                    self.typ(typ);
+                }
+            }
+            StatementKind::Match(m) => {
+                self.expr(&mut m.scrutinee);
+                for arm in &mut m.arms {
+                    self.locals.push(HashSet::new());
+                    self.arm(arm);
+                    self.stmts(&mut arm.body);
+                    self.locals.pop();
+                }
+            }
+            StatementKind::Block(stmts) => self.block(stmts),
+            StatementKind::Break(_) | StatementKind::Continue(_) | StatementKind::Import(_) => {}
+        }
+    }
+
+    // a name of the module or a path starting with one, like Shape::Circle
+    fn path(&self, ident: &mut String) {
+        let first = ident.split("::").next().unwrap_or_default();
+        if self.names.contains(first) {
+            *ident = format!("{}::{ident}", self.module);
+        }
+    }
+
+    // the pattern and guard of a match arm
+    fn arm<B>(&mut self, arm: &mut Arm<B>) {
+        self.pattern(&mut arm.pattern);
+        if let Some(guard) = &mut arm.guard {
+            self.expr(guard);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::Variant(v) => {
+                self.path(&mut v.ident);
+                for field in &mut v.fields {
+                    self.pattern(field);
+                }
+            }
+            Pattern::Binding(ident) => self.declare(ident),
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+        }
+    }
+
+    fn typ(&self, typ: &mut LType) {
+        match typ {
+            LType::Named(ident) if !self.generics.contains(ident) => self.path(ident),
+            LType::Array(elem, _) => self.typ(elem),
+            LType::Fn(params, ret) => {
+                for typ in params {
+                    self.typ(typ);
+                }
+                self.typ(ret);
+            }
+            _ => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Call(c) => {
+                self.path(&mut c.ident);
+                for p in &mut c.params {
+                    self.expr(p);
+                }
+            }
+            ExprKind::Apply(a) => {
+                self.expr(&mut a.f);
+                for p in &mut a.params {
+                    self.expr(p);
+                }
+            }
+            ExprKind::Lambda(l) => {
+                self.locals.push(HashSet::new());
+                for (typ, param) in &mut l.params {
+                    self.typ(typ);
+                    self.declare(param);
+                }
+                self.typ(&mut l.ret);
+                self.stmts(&mut l.body);
+                self.locals.pop();
+            }
+            ExprKind::Unary(u) => self.expr(&mut u.x),
+            ExprKind::Block(b) => {
+                self.locals.push(HashSet::new());
+                self.stmts(&mut b.stmts);
+                if let Some(value) = &mut b.value {
+                    self.expr(value);
+                }
+                self.locals.pop();
+            }
+            ExprKind::If(i) => {
+                self.expr(&mut i.guard);
+                self.expr(&mut i.t);
+                self.expr(&mut i.f);
+            }
+            ExprKind::Binop(b) => {
+                self.expr(&mut b.a);
+                self.expr(&mut b.b);
+            }
+            ExprKind::StructLiteral(s) => {
+                self.path(&mut s.ident);
+                for f in &mut s.fields {
+                    self.expr(&mut f.value);
+                }
+            }
+            ExprKind::Field(a) => self.expr(&mut a.base),
+            ExprKind::Cast(c) => {
+                self.expr(&mut c.x);
+                self.typ(&mut c.typ);
+            }
+            ExprKind::ArrayLiteral(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            ExprKind::Index(i) => {
+                self.expr(&mut i.base);
+                self.expr(&mut i.index);
+            }
+            ExprKind::Match(m) => {
+                self.expr(&mut m.scrutinee);
+                for arm in &mut m.arms {
+                    self.locals.push(HashSet::new());
+                    self.arm(arm);
+                    self.expr(&mut arm.body);
+                    self.locals.pop();
+                }
+            }
+            // unit variants like Shape::Empty, and functions used as values
+            // unless a variable hides them
+            ExprKind::Ident(ident) if ident.contains("::") || !self.is_local(ident) => {
+                self.path(ident)
+            }
+            ExprKind::Intermediate(..)
+            | ExprKind::FloatIntermediate(..)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::CharLiteral(_)
+            | ExprKind::BoolLiteral(_)
+            | ExprKind::Ident(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ModuleErrorReason, Resolver};
+    use crate::parser::Parser;
+    use crate::source::SourceMap;
+    use std::path::{Path, PathBuf};
+
+    // a fresh directory holding the given files
+    fn write_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ro-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn resolve(root: &Path, search: &[PathBuf]) -> Result<Vec<String>, Vec<ModuleErrorReason>> {
+        let mut map = SourceMap::new();
+        let source = std::fs::read_to_string(root).unwrap();
+        let file = map.add(root.display().to_string(), source);
+        let stmts = Parser::with_file(map.get(file).source.chars(), file)
+            .parse_program()
+            .unwrap();
+        Resolver::new(&mut map, search)
+            .resolve(Some(root), stmts)
+            .map(|stmts| stmts.iter().map(|s| s.to_string()).collect())
+            .map_err(|errors| errors.into_iter().map(|e| e.reason).collect())
+    }
+
+    #[test]
+    fn resolve_imports() {
+        let dir = write_dir(
+            "imports",
+            &[
+                (
+                    "main.ro",
+                    "import \"lib/math.ro\"; import util; print(math::sq(2));",
+                ),
+                (
+                    "lib/math.ro",
+                    "import util; fn sq(int x) -> int { return util::id(x) * x; }; struct P { int x; }; fn p(P a) -> P { return P { x: a.x }; }; enum E { A(P), B }; fn e(E v) -> E { return match v { E::A(a) => E::A(p(a)), E::B => E::B }; }; fn get() -> fn(P) -> P { return p; };",
+                ),
+                ("lib/util.ro", "fn id(int x) -> int { return x; };"),
+                ("std/util.ro", "fn unused() -> int { return 0; };"),
+            ],
+        );
+        let search = [dir.join("lib")];
+        let labels = resolve(&dir.join("main.ro"), &search).unwrap();
+        assert_eq!(
+            labels,
+            vec![
+                "fn <util::id(int x) -> int>",
+                "fn <math::sq(int x) -> int>",
+                "struct <math::P { int x }>",
+                "fn <math::p(math::P a) -> math::P>",
+                "enum <math::E { A(math::P), B }>",
+                "fn <math::e(math::E v) -> math::E>",
+                "fn <math::get() -> fn(math::P) -> math::P>",
+                "call<print()>"
+            ]
+        );
+
+        let errors = resolve(&dir.join("main.ro"), &[]).unwrap_err();
+        assert!(
+            matches!(&errors[0], ModuleErrorReason::NotFound { path, .. } if path == "util.ro")
+        );
+    }
+
+    #[test]
+    fn resolve_cycle() {
+        let dir = write_dir(
+            "cycle",
+            &[
+                ("a.ro", "import b;"),
+                ("b.ro", "import c; fn f() -> int { return 0; };"),
+                ("c.ro", "import b;"),
+            ],
+        );
+        let errors = resolve(&dir.join("a.ro"), &[]).unwrap_err();
+        assert!(matches!(&errors[0], ModuleErrorReason::Cycle(chain) if chain == &["b", "c", "b"]));
+    }
+
+    #[test]
+    fn resolve_parse_error() {
+        // only the parse error, not that x is a statement in a module
+        let dir = write_dir(
+            "broken",
+            &[
+                ("main.ro", "import m;"),
+                ("m.ro", "fn f() -> int { return 1 + ; }; int x = 1;"),
+            ],
+        );
+        let errors = resolve(&dir.join("main.ro"), &[]).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], ModuleErrorReason::Parse(_)));
+    }
+
+    #[test]
+    fn resolve_warnings() {
+        // the module spells "scope" with a Cyrillic о
+        let dir = write_dir(
+            "warnings",
+            &[
+                ("main.ro", "import m;"),
+                (
+                    "m.ro",
+                    "fn sc\u{43e}pe(int scope) -> int { return sc\u{43e}pe(scope); };",
+                ),
+            ],
+        );
+        let root = dir.join("main.ro");
+        let mut map = SourceMap::new();
+        let file = map.add(root.display().to_string(), "import m;");
+        let stmts = Parser::with_file(map.get(file).source.chars(), file)
+            .parse_program()
+            .unwrap();
+        let mut resolver = Resolver::new(&mut map, &[]);
+        resolver.resolve(Some(&root), stmts).unwrap();
+        let reasons: Vec<String> = resolver
+            .warnings
+            .iter()
+            .map(|w| w.reason.to_string())
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "identifier `scоpe` mixes scripts",
+                "identifier `scope` looks like `scоpe`",
+            ]
+        );
+    }
+}
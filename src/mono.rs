@@ -0,0 +1,377 @@
+// turns generic functions into one concrete function per set of type
+// arguments they are used at. The checker infers the arguments and records
+// which instance each call refers to, here the instances are copied out of
+// their generic function with the types filled in. Literals get the types
+// the checker inferred for them on the way
+use std::collections::HashMap;
+
+use crate::ast::*;
+
+// a generic function at some type arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    // the generic function, which is declared at the top level
+    pub generic: String,
+    pub args: Vec<LType>,
+}
+
+impl Instance {
+    // max<int>, which no identifier can collide with
+    pub fn ident(&self) -> String {
+        let args: Vec<String> = self.args.iter().map(|t| t.to_string()).collect();
+        format!("{}<{}>", self.generic, args.join(", "))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Instances {
+    pub list: Vec<Instance>,
+    // the instance a call or function name refers to, by the id of the
+    // expression and the index of the instance it was checked in, None
+    // outside generic functions
+    pub sites: HashMap<(NodeId, Option<usize>), usize>,
+    // the type of each literal without a suffix, keyed the same way
+    pub literals: HashMap<(NodeId, Option<usize>), LType>,
+}
+
+// typ with the type parameters in types replaced
+pub fn substitute(typ: &LType, types: &HashMap<String, LType>) -> LType {
+    match typ {
+        LType::Named(ident) => types.get(ident).cloned().unwrap_or_else(|| typ.clone()),
+        LType::Array(elem, len) => LType::Array(Box::new(substitute(elem, types)), *len),
+        LType::Fn(params, ret) => LType::Fn(
+            params.iter().map(|t| substitute(t, types)).collect(),
+            Box::new(substitute(ret, types)),
+        ),
+        _ => typ.clone(),
+    }
+}
+
+// stmts with every generic function replaced by its instances, stmts must be
+// the tree the checker produced instances for
+pub fn monomorphize(mut stmts: Vec<Statement>, instances: &Instances) -> Vec<Statement> {
+    for stmt in &mut stmts {
+        if !is_generic(stmt) {
+            Walk {
+                expr: &mut |e| fill(e, instances, None),
+                typ: &mut |_| {},
+            }
+            .stmt(stmt);
+        }
+    }
+
+    let mut made: HashMap<String, Vec<SFunction>> = HashMap::new();
+    for (id, instance) in instances.list.iter().enumerate() {
+        let Some(f) = stmts.iter().find_map(|s| match &s.kind {
+            StatementKind::Function(f) if f.ident == instance.generic && !f.generics.is_empty() => {
+                Some(f)
+            }
+            _ => None,
+        }) else {
+            continue;
+        };
+        let mut copy = f.clone();
+        let types: HashMap<String, LType> = f
+            .generics
+            .iter()
+            .cloned()
+            .zip(instance.args.iter().cloned())
+            .collect();
+        Walk {
+            expr: &mut |e| fill(e, instances, Some(id)),
+            typ: &mut |t| *t = substitute(t, &types),
+        }
+        .function(&mut copy);
+        copy.ident = instance.ident();
+        copy.generics.clear();
+        made.entry(instance.generic.clone()).or_default().push(copy);
+    }
+
+    // instances take the place of their generic function
+    let mut out = Vec::new();
+    for stmt in stmts {
+        match &stmt.kind {
+            StatementKind::Function(f) if !f.generics.is_empty() => {
+                let span = stmt.span;
+                out.extend(
+                    made.remove(&f.ident)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|f| Statement::new(StatementKind::Function(f), span)),
+                )
+            }
+            _ => out.push(stmt),
+        }
+    }
+    out
+}
+
+fn is_generic(stmt: &Statement) -> bool {
+    matches!(&stmt.kind, StatementKind::Function(f) if !f.generics.is_empty())
+}
+
+// points a call or name of a generic function at the instance the checker
+// chose for it and types a literal, within the instance it is in
+fn fill(expr: &mut Expr, instances: &Instances, within: Option<usize>) {
+    let key = (expr.id, within);
+    match (&mut expr.kind, instances.literals.get(&key)) {
+        (ExprKind::Intermediate(_, t @ None), Some(LType::Int(typ))) => *t = Some(*typ),
+        (ExprKind::FloatIntermediate(_, t @ None), Some(LType::Float(typ))) => *t = Some(*typ),
+        // the 1 of x++ on a float
+        (ExprKind::Intermediate(i, None), Some(LType::Float(typ))) => {
+            expr.kind = ExprKind::FloatIntermediate(*i as f64, Some(*typ));
+        }
+        _ => {}
+    }
+    let Some(&id) = instances.sites.get(&key) else {
+        return;
+    };
+    let ident = instances.list[id].ident();
+    match &mut expr.kind {
+        ExprKind::Call(c) => c.ident = ident,
+        ExprKind::Ident(i) => *i = ident,
+        _ => {}
+    }
+}
+
+// every expression and type under a statement, outer expressions first
+struct Walk<'f> {
+    expr: &'f mut dyn FnMut(&mut Expr),
+    typ: &'f mut dyn FnMut(&mut LType),
+}
+
+impl Walk<'_> {
+    fn function(&mut self, f: &mut SFunction) {
+        for (typ, _) in &mut f.params {
+            (self.typ)(typ);
+        }
+        (self.typ)(&mut f.ret);
+        self.stmts(&mut f.body);
+    }
+
+    fn stmts(&mut self, stmts: &mut [Statement]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Statement) {
+        match &mut stmt.kind {
+            StatementKind::If(sif) => {
+                self.expr(&mut sif.guard);
+                self.stmts(&mut sif.t);
+                for e in &mut sif.elifs {
+                    self.expr(&mut e.guard);
+                    self.stmts(&mut e.body);
+                }
+                if let Some(f) = &mut sif.f {
+                    self.stmts(f);
+                }
+            }
+            StatementKind::Declare(d) => {
+                (self.typ)(&mut d.typ);
+                if let Some(e) = &mut d.assign {
+                    self.expr(e);
+                }
+            }
+            StatementKind::Assign(a) => {
+                for access in &mut a.target.path {
+                    if let Access::Index(index) = access {
+                        self.expr(index);
+                    }
+                }
+                self.expr(&mut a.value);
+            }
+            StatementKind::While(w) => {
+                self.expr(&mut w.cond);
+                self.stmts(&mut w.body);
+            }
+            StatementKind::For(f) => {
+                if let Some(init) = &mut f.init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = &mut f.cond {
+                    self.expr(cond);
+                }
+                if let Some(step) = &mut f.step {
+                    self.stmt(step);
+                }
+                self.stmts(&mut f.body);
+            }
+            StatementKind::Loop(l) => self.stmts(&mut l.body),
+            StatementKind::DoWhile(d) => {
+                self.stmts(&mut d.body);
+                self.expr(&mut d.cond);
+            }
+            StatementKind::Expr(e) | StatementKind::Return(e) => self.expr(e),
+            StatementKind::Function(f) => self.function(f),
+            StatementKind::Struct(s) => {
+                for (typ, _) in &mut s.fields {
+                    (self.typ)(typ);
+                }
+            }
+            StatementKind::Enum(e) => {
+                for typ in e.variants.iter_mut().flat_map(|(_, payload)| payload) {
+                    (self.typ)(typ);
+                }
+            }
+            StatementKind::Match(m) => {
+                self.expr(&mut m.scrutinee);
+                for arm in &mut m.arms {
+                    self.arm(arm);
+                    self.stmts(&mut arm.body);
+                }
+            }
+            StatementKind::Block(stmts) => self.stmts(stmts),
+            StatementKind::Break(_) | StatementKind::Continue(_) | StatementKind::Import(_) => {}
+        }
+    }
+
+    fn arm<B>(&mut self, arm: &mut Arm<B>) {
+        self.pattern(&mut arm.pattern);
+        if let Some(guard) = &mut arm.guard {
+            self.expr(guard);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::Literal(e) => self.expr(e),
+            Pattern::Variant(v) => {
+                for field in &mut v.fields {
+                    self.pattern(field);
+                }
+            }
+            Pattern::Wildcard | Pattern::Binding(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        (self.expr)(expr);
+        match &mut expr.kind {
+            ExprKind::Call(c) => {
+                for p in &mut c.params {
+                    self.expr(p);
+                }
+            }
+            ExprKind::Apply(a) => {
+                self.expr(&mut a.f);
+                for p in &mut a.params {
+                    self.expr(p);
+                }
+            }
+            ExprKind::Lambda(l) => {
+                for (typ, _) in &mut l.params {
+                    (self.typ)(typ);
+                }
+                (self.typ)(&mut l.ret);
+                self.stmts(&mut l.body);
+            }
+            ExprKind::Unary(u) => self.expr(&mut u.x),
+            ExprKind::Binop(b) => {
+                self.expr(&mut b.a);
+                self.expr(&mut b.b);
+            }
+            ExprKind::Cast(c) => {
+                self.expr(&mut c.x);
+                (self.typ)(&mut c.typ);
+            }
+            ExprKind::Block(b) => {
+                self.stmts(&mut b.stmts);
+                if let Some(value) = &mut b.value {
+                    self.expr(value);
+                }
+            }
+            ExprKind::If(i) => {
+                self.expr(&mut i.guard);
+                self.expr(&mut i.t);
+                self.expr(&mut i.f);
+            }
+            ExprKind::StructLiteral(s) => {
+                for f in &mut s.fields {
+                    self.expr(&mut f.value);
+                }
+            }
+            ExprKind::Field(a) => self.expr(&mut a.base),
+            ExprKind::ArrayLiteral(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            ExprKind::Index(i) => {
+                self.expr(&mut i.base);
+                self.expr(&mut i.index);
+            }
+            ExprKind::Match(m) => {
+                self.expr(&mut m.scrutinee);
+                for arm in &mut m.arms {
+                    self.arm(arm);
+                    self.expr(&mut arm.body);
+                }
+            }
+            ExprKind::Intermediate(..)
+            | ExprKind::FloatIntermediate(..)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::CharLiteral(_)
+            | ExprKind::BoolLiteral(_)
+            | ExprKind::Ident(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::monomorphize;
+    use crate::capture;
+    use crate::check::check;
+    use crate::interp::Interpreter;
+    use crate::parser::Parser;
+
+    // the program after monomorphization and what running it prints
+    fn run(source: &str) -> (Vec<String>, String) {
+        let mut stmts = Parser::new(source.chars())
+            .parse_program()
+            .expect("source should parse");
+        capture::resolve(&mut stmts);
+        let instances = check(&stmts).expect("source should check");
+        let stmts = monomorphize(stmts, &instances);
+        let labels = stmts.iter().map(|s| s.to_string()).collect();
+        let mut out = Vec::new();
+        Interpreter::new(&mut out)
+            .run(&stmts)
+            .expect("source should run");
+        (labels, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn run_generics() {
+        let (labels, out) = run("
+            fn max<T>(T a, T b) -> T { if a > b { return a; }; return b; };
+            fn first<T>(T[3] xs) -> T { return xs[0]; };
+            fn twice<T>(T x, fn(T) -> T f) -> T { return f(f(x)); };
+            fn pick<T>(bool left, T a, T b) -> T { return if left { a } else { max(a, b) }; };
+            u8 small = 3;
+            print(max(1, 2), max(2.5, 1.0), max('a', 'z'), max(small, 200));
+            print(first([true, false, true]), twice(1.5, fn(float x) -> float { return x * 2.0; }));
+            print(pick(false, 3, 9), pick(true, 1.5, 2.5));
+            fn(int, int) -> int m = max;
+            print(m(7, 4));
+        ");
+        assert_eq!(out, "2 2.5 z 200\ntrue 6.0\n9 1.5\n7\n");
+        // each generic is replaced by its instances, pick<int> brings in
+        // max<int> which main already used
+        assert_eq!(
+            labels[..8],
+            [
+                "fn <max<int>(int a, int b) -> int>",
+                "fn <max<float>(float a, float b) -> float>",
+                "fn <max<char>(char a, char b) -> char>",
+                "fn <max<u8>(u8 a, u8 b) -> u8>",
+                "fn <first<bool>(bool[3] xs) -> bool>",
+                "fn <twice<float>(float x, fn(float) -> float f) -> float>",
+                "fn <pick<int>(bool left, int a, int b) -> int>",
+                "fn <pick<float>(bool left, float a, float b) -> float>",
+            ]
+        );
+    }
+}
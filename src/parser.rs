@@ -1,32 +1,229 @@
 use crate::ast::*;
+use crate::diag::Diagnostic;
 use crate::lexer::*;
+use crate::source::{FileId, Span};
 use crate::tokens::*;
 
 use std::iter::Peekable;
-use std::str::Chars;
-pub trait Parse {
-    fn parse(tokens: &[LexedToken]) -> Option<Self>
-    where
-        Self: Sized;
+use std::str::{Chars, FromStr};
+
+// a node that can be parsed on its own, all input must be consumed
+pub trait Parse: Sized {
+    fn parse_with<I: Iterator<Item = LexedToken>>(
+        parser: &mut Parser<I>,
+    ) -> Result<Self, ParseError>;
+
+    fn parse(tokens: &[LexedToken]) -> Result<Self, Vec<ParseError>> {
+        Parser::from_tokens(tokens.iter().cloned()).parse_all()
+    }
+
+    fn parse_str(s: &str) -> Result<Self, Vec<ParseError>> {
+        Parser::new(s.chars()).parse_all()
+    }
+}
+
+impl Parse for Expr {
+    fn parse_with<I: Iterator<Item = LexedToken>>(
+        parser: &mut Parser<I>,
+    ) -> Result<Self, ParseError> {
+        parser.parse_expr()
+    }
+}
+
+impl Parse for Statement {
+    fn parse_with<I: Iterator<Item = LexedToken>>(
+        parser: &mut Parser<I>,
+    ) -> Result<Self, ParseError> {
+        parser.parse_statement()
+    }
+}
+
+impl Parse for SFunction {
+    fn parse_with<I: Iterator<Item = LexedToken>>(
+        parser: &mut Parser<I>,
+    ) -> Result<Self, ParseError> {
+        let doc = parser.accept_doc();
+        let mut f = parser.parse_function()?;
+        f.doc = doc;
+        Ok(f)
+    }
+}
+
+impl Parse for SStruct {
+    fn parse_with<I: Iterator<Item = LexedToken>>(
+        parser: &mut Parser<I>,
+    ) -> Result<Self, ParseError> {
+        let doc = parser.accept_doc();
+        let mut s = parser.parse_struct()?;
+        s.doc = doc;
+        Ok(s)
+    }
+}
+
+impl Parse for SEnum {
+    fn parse_with<I: Iterator<Item = LexedToken>>(
+        parser: &mut Parser<I>,
+    ) -> Result<Self, ParseError> {
+        let doc = parser.accept_doc();
+        let mut e = parser.parse_enum()?;
+        e.doc = doc;
+        Ok(e)
+    }
 }
 
+impl Parse for Pattern {
+    fn parse_with<I: Iterator<Item = LexedToken>>(
+        parser: &mut Parser<I>,
+    ) -> Result<Self, ParseError> {
+        parser.parse_pattern()
+    }
+}
+
+impl Parse for LType {
+    fn parse_with<I: Iterator<Item = LexedToken>>(
+        parser: &mut Parser<I>,
+    ) -> Result<Self, ParseError> {
+        parser.expect_type()
+    }
+}
+
+// a braced block
+impl Parse for Vec<Statement> {
+    fn parse_with<I: Iterator<Item = LexedToken>>(
+        parser: &mut Parser<I>,
+    ) -> Result<Self, ParseError> {
+        parser.parse_block()
+    }
+}
+
+macro_rules! from_str_via_parse {
+    ($($t:ty),*) => {$(
+        impl FromStr for $t {
+            type Err = Vec<ParseError>;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Self::parse_str(s)
+            }
+        }
+    )*};
+}
+
+from_str_via_parse!(Expr, Statement, SFunction, SStruct, SEnum, Pattern, LType);
+
 #[derive(Debug)]
 pub struct ParseError {
     pub token: Option<LexedToken>,
     pub reason: ParseErrorReason,
+    // other source the error relates to, like an unclosed delimiter
+    pub related: Vec<(Span, String)>,
 }
 
 impl ParseError {
     pub fn new(token: Option<LexedToken>, reason: ParseErrorReason) -> Self {
-        Self { token, reason }
+        Self {
+            token,
+            reason,
+            related: Vec::new(),
+        }
     }
 
-    pub fn get_span(&self) -> Option<u32> {
-        if let Some(tk) = &self.token {
-            Some(tk.info.position)
-        } else {
-            None
+    pub fn with_related(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.related.push((span, message.into()));
+        self
+    }
+
+    pub fn get_span(&self) -> Option<Span> {
+        self.token.as_ref().map(|tk| tk.info.span)
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let found = self
+            .token
+            .as_ref()
+            .map_or_else(|| "end of file".to_string(), |tk| tk.token.to_string());
+        let (message, label) = match &self.reason {
+            ParseErrorReason::BadBinOp => (format!("expected operator, found {found}"), ""),
+            ParseErrorReason::BadUnary => (format!("expected unary operator, found {found}"), ""),
+            ParseErrorReason::BadStatement => (
+                format!("expected statement, found {found}"),
+                "expected statement",
+            ),
+            ParseErrorReason::DanglingDocComment => (
+                "doc comment is not followed by a function or declaration".to_string(),
+                "documents nothing",
+            ),
+            ParseErrorReason::DuplicateTypeParameter(ident) => (
+                format!("type parameter `{ident}` is declared twice"),
+                "already declared",
+            ),
+            ParseErrorReason::ExpectedArrayLength => (
+                format!("expected array length, found {found}"),
+                "expected an integer literal",
+            ),
+            ParseErrorReason::ExpectedToken(t) => {
+                let message = format!("expected {t}, found {found}");
+                let label = format!("expected {t}");
+                return self.labeled(Diagnostic::error(message), &label);
+            }
+            ParseErrorReason::ExpectedLoop => (
+                format!("expected loop after label, found {found}"),
+                "only loops can be labeled",
+            ),
+            ParseErrorReason::ExpectedModule => (
+                format!("expected module name or path, found {found}"),
+                "expected module",
+            ),
+            ParseErrorReason::ExpectedType => {
+                (format!("expected type, found {found}"), "expected type")
+            }
+            ParseErrorReason::ExpectedPattern => (
+                format!("expected pattern, found {found}"),
+                "expected pattern",
+            ),
+            ParseErrorReason::ExpectedIdentifier => (
+                format!("expected identifier, found {found}"),
+                "expected identifier",
+            ),
+            ParseErrorReason::Lex(reason) => (reason.to_string(), ""),
+            ParseErrorReason::LiteralOutOfRange => (
+                "array length is out of range".to_string(),
+                "too large for this platform",
+            ),
+            ParseErrorReason::NonAtomicExpression => (
+                format!("expected expression, found {found}"),
+                "expected expression",
+            ),
+            ParseErrorReason::OutsideLoop => {
+                (format!("{found} outside of a loop"), "not inside a loop")
+            }
+            ParseErrorReason::UndefinedLabel(label) => (
+                format!("undeclared label `{label}`"),
+                "no enclosing loop has this label",
+            ),
+            ParseErrorReason::TrailingInput => (
+                format!("expected end of input, found {found}"),
+                "unexpected input",
+            ),
+            ParseErrorReason::StreamEnded => ("unexpected end of input".to_string(), ""),
+        };
+        let diag = Diagnostic::error(message);
+        let diag = match self.reason {
+            ParseErrorReason::DanglingDocComment => {
+                diag.with_note("doc comments can only precede `fn` and declarations")
+            }
+            _ => diag,
+        };
+        self.labeled(diag, label)
+    }
+
+    fn labeled(&self, mut diag: Diagnostic, label: &str) -> Diagnostic {
+        if let Some(span) = self.get_span() {
+            diag = diag.with_primary(span, label);
+        }
+        for (span, message) in &self.related {
+            diag = diag.with_secondary(*span, message.clone());
         }
+        diag
     }
 }
 
@@ -35,80 +232,666 @@ pub enum ParseErrorReason {
     BadBinOp,
     BadUnary,
     BadStatement,
+    DanglingDocComment,
+    DuplicateTypeParameter(String),
+    ExpectedArrayLength,
+    ExpectedLoop,
+    ExpectedModule,
     ExpectedToken(Token),
     ExpectedType,
     ExpectedIdentifier,
+    ExpectedPattern,
+    Lex(LexErrorReason),
+    LiteralOutOfRange,
     NonAtomicExpression,
+    OutsideLoop,
     StreamEnded,
+    TrailingInput,
+    UndefinedLabel(String),
+}
+
+// the token stream, remembering where the last token taken ends so a node
+// can span from its first token to it
+struct Tokens<I: Iterator<Item = LexedToken>> {
+    inner: Peekable<I>,
+    last: Span,
+}
+
+impl<I: Iterator<Item = LexedToken>> Tokens<I> {
+    fn peek(&mut self) -> Option<&LexedToken> {
+        self.inner.peek()
+    }
+
+    fn next(&mut self) -> Option<LexedToken> {
+        let tk = self.inner.next();
+        if let Some(tk) = &tk {
+            self.last = tk.info.span;
+        }
+        tk
+    }
 }
 
-pub struct Parser<'a> {
-    lexer: Peekable<Lexer<'a>>,
+pub struct Parser<I: Iterator<Item = LexedToken>> {
+    lexer: Tokens<I>,
+    // errors recovered from so far
+    errors: Vec<ParseError>,
+    // labels of the loops being parsed, innermost last
+    loops: Vec<Option<String>>,
+    // set in if guards, where `a {` starts the body and not a struct literal
+    no_struct: bool,
 }
 
-impl<'a> Parser<'a> {
+impl<'a> Parser<Lexer<'a>> {
     pub fn new(chars: Chars<'a>) -> Self {
+        Self::with_file(chars, FileId::default())
+    }
+
+    pub fn with_file(chars: Chars<'a>, file: FileId) -> Self {
+        Self::from_tokens(Lexer::with_file(chars.peekable(), file))
+    }
+}
+
+impl<I: Iterator<Item = LexedToken>> Parser<I> {
+    pub fn from_tokens(tokens: I) -> Self {
         Self {
-            lexer: Lexer::new(chars.peekable()).peekable(),
+            lexer: Tokens {
+                inner: tokens.peekable(),
+                last: Span::default(),
+            },
+            errors: Vec::new(),
+            loops: Vec::new(),
+            no_struct: false,
+        }
+    }
+
+    // parses a single node that must span the whole input
+    pub fn parse_all<T: Parse>(&mut self) -> Result<T, Vec<ParseError>> {
+        let node = T::parse_with(self).map_err(|e| self.report(e)).ok();
+        if node.is_some()
+            && let Some(tk) = self.lexer.peek()
+            && tk.token != Token::EOF
+        {
+            let tk = tk.clone();
+            self.report(ParseError::new(Some(tk), ParseErrorReason::TrailingInput));
+        }
+        let errors = std::mem::take(&mut self.errors);
+        match node {
+            Some(node) if errors.is_empty() => Ok(node),
+            _ => Err(errors),
         }
     }
 
+    // from start to the end of the last token taken
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.lexer.last)
+    }
+
     pub fn parse_statement(&mut self) -> Result<Statement, ParseError> {
         let tk = self.expect_peek()?;
+        if let Some(doc) = self.accept_doc() {
+            // nothing left in the file or block to document
+            if self
+                .lexer
+                .peek()
+                .is_none_or(|t| matches!(t.token, Token::EOF | Token::RCURL))
+            {
+                return Err(ParseError::new(
+                    Some(tk),
+                    ParseErrorReason::DanglingDocComment,
+                ));
+            }
+            let mut stmt = self.parse_statement()?;
+            match &mut stmt.kind {
+                StatementKind::Function(f) => f.doc = Some(doc),
+                StatementKind::Declare(d) => d.doc = Some(doc),
+                StatementKind::Struct(s) => s.doc = Some(doc),
+                StatementKind::Enum(e) => e.doc = Some(doc),
+                _ => {
+                    return Err(ParseError::new(
+                        Some(tk),
+                        ParseErrorReason::DanglingDocComment,
+                    ));
+                }
+            }
+            return Ok(stmt);
+        }
+        let start = tk.info.span;
+        let kind = self.parse_statement_kind(tk)?;
+        Ok(Statement::new(kind, self.span_from(start)))
+    }
+
+    fn parse_statement_kind(&mut self, tk: LexedToken) -> Result<StatementKind, ParseError> {
         match &tk.token {
             Token::LCURL => {
-                dbg!("block");
                 let s = self.parse_block()?;
                 self.expect_token(Token::SEMICOLON)?;
 
-                Ok(Statement::Block(s))
+                Ok(StatementKind::Block(s))
             }
             Token::IF => {
                 self.lexer.next();
-                let guard = Box::new(self.parse_expr_internal(None, 0)?);
+                let guard = Box::new(self.parse_guard()?);
                 let t = self.parse_block()?;
-                let f = if self.accept_token(Token::ELSE) {
-                    Some(self.parse_block()?)
-                } else {
-                    None
-                };
+                let mut elifs: Vec<SElseIf> = Vec::new();
+                let mut f = None;
+                while self.accept_token(Token::ELSE) {
+                    if !self.accept_token(Token::IF) {
+                        f = Some(self.parse_block()?);
+                        break;
+                    }
+                    let guard = Box::new(self.parse_guard()?);
+                    let body = self.parse_block()?;
+                    elifs.push(SElseIf { guard, body });
+                }
                 self.expect_token(Token::SEMICOLON)?;
 
-                Ok(Statement::If(SIf { guard, t, f }))
+                Ok(StatementKind::If(SIf { guard, t, elifs, f }))
             }
             // assign
             // call
-            // TODO could this be a match statement?
+            // label
             Token::IDENT(ident) => {
                 self.lexer.next();
-                if self.accept_token(Token::EQUAL) {
-                    let value = Box::new(self.parse_expr()?);
-                    self.expect_token(Token::SEMICOLON)?;
+                if self.accept_token(Token::COLON) {
+                    return self.parse_loop(Some(ident.clone()));
+                }
+                let ident = self.parse_path(ident.clone())?;
+                let stmt = self.parse_simple(ident, tk.info.span, false)?;
+                self.expect_token(Token::SEMICOLON)?;
+                Ok(stmt)
+            }
+            Token::WHILE | Token::FOR | Token::LOOP | Token::DO => self.parse_loop(None),
+            Token::BREAK | Token::CONTINUE => {
+                self.lexer.next();
+                let label_tk = self.lexer.peek().cloned();
+                let label = self.accept_ident();
+                self.expect_token(Token::SEMICOLON)?;
 
-                    Ok(Statement::Assign(SAssign {
-                        ident: ident.clone(),
-                        value,
-                    }))
+                if self.loops.is_empty() {
+                    return Err(ParseError::new(Some(tk), ParseErrorReason::OutsideLoop));
+                }
+                if let Some(l) = &label
+                    && !self.loops.iter().any(|x| x.as_ref() == Some(l))
+                {
+                    return Err(ParseError::new(
+                        label_tk,
+                        ParseErrorReason::UndefinedLabel(l.clone()),
+                    ));
+                }
+                if tk.token == Token::BREAK {
+                    Ok(StatementKind::Break(label))
+                } else {
+                    Ok(StatementKind::Continue(label))
+                }
+            }
+            Token::IMPORT => {
+                self.lexer.next();
+                let name = self.expect_peek()?;
+                let (module, path) = match &name.token {
+                    Token::IDENT(m) => (m.clone(), format!("{m}.ro")),
+                    Token::STRINGLIT(p) => {
+                        let stem = std::path::Path::new(p).file_stem();
+                        let module = stem.map_or(p.clone(), |s| s.to_string_lossy().into());
+                        (module, p.clone())
+                    }
+                    _ => {
+                        return Err(ParseError::new(
+                            Some(name),
+                            ParseErrorReason::ExpectedModule,
+                        ));
+                    }
+                };
+                self.lexer.next();
+                self.expect_token(Token::SEMICOLON)?;
+                Ok(StatementKind::Import(SImport {
+                    module,
+                    path,
+                    span: tk.info.span.to(name.info.span),
+                }))
+            }
+            Token::RETURN => {
+                self.lexer.next();
+                let expr = Box::new(self.parse_expr()?);
+                self.expect_token(Token::SEMICOLON)?;
+                Ok(StatementKind::Return(expr))
+            }
+            // function decl, or a variable of a function type
+            Token::FN => {
+                self.lexer.next();
+                if self.expect_peek()?.token == Token::LPAREN {
+                    let typ = self.parse_fn_type()?;
+                    let typ = self.parse_array_suffix(typ)?;
+                    let stmt = self.parse_declare(typ)?;
+                    self.expect_token(Token::SEMICOLON)?;
+                    return Ok(stmt);
+                }
+                let f = self.parse_function_rest()?;
+                self.expect_token(Token::SEMICOLON)?;
+                Ok(StatementKind::Function(f))
+            }
+            Token::STRUCT => {
+                let s = self.parse_struct()?;
+                self.expect_token(Token::SEMICOLON)?;
+                Ok(StatementKind::Struct(s))
+            }
+            Token::ENUM => {
+                let e = self.parse_enum()?;
+                self.expect_token(Token::SEMICOLON)?;
+                Ok(StatementKind::Enum(e))
+            }
+            Token::MATCH => {
+                self.lexer.next();
+                let scrutinee = Box::new(self.parse_guard()?);
+                let arms = self.parse_arms(|p| p.parse_block().map(|b| (b, true)))?;
+                self.expect_token(Token::SEMICOLON)?;
+                Ok(StatementKind::Match(SMatch { scrutinee, arms }))
+            }
+            _ => {
+                if Self::lookup_type(&tk.token).is_some() {
+                    let typ = self.expect_type()?;
+                    let stmt = self.parse_declare(typ)?;
+                    self.expect_token(Token::SEMICOLON)?;
+                    Ok(stmt)
                 } else {
-                    self.expect_token(Token::LPAREN)?;
-                    let mut args: Vec<Expr> = Vec::new();
+                    Err(ParseError::new(Some(tk), ParseErrorReason::BadStatement))
+                }
+            }
+        }
+    }
+
+    // fn ident<T, ...>(params) -> type { body }, without the ;
+    pub fn parse_function(&mut self) -> Result<SFunction, ParseError> {
+        self.expect_token(Token::FN)?;
+        self.parse_function_rest()
+    }
+
+    // a function declaration after its fn
+    fn parse_function_rest(&mut self) -> Result<SFunction, ParseError> {
+        let span = self.expect_peek()?.info.span;
+        let ident = self.expect_identifier()?;
+        let mut generics = Vec::new();
+        if self.accept_token(Token::LT) {
+            loop {
+                let tk = self.expect_peek()?;
+                let generic = self.expect_identifier()?;
+                if generics.contains(&generic) {
+                    let reason = ParseErrorReason::DuplicateTypeParameter(generic);
+                    return Err(ParseError::new(Some(tk), reason));
+                }
+                generics.push(generic);
+                if !self.accept_token(Token::COMMA) {
+                    break;
+                }
+            }
+            self.expect_token(Token::GT)?;
+        }
+        let (params, ret) = self.parse_signature()?;
+        let body = self.parse_fn_body()?;
+        Ok(SFunction {
+            doc: None,
+            ident,
+            ret,
+            generics,
+            params,
+            body,
+            span,
+        })
+    }
+
+    // fn(params) -> type { body }
+    fn parse_lambda(&mut self) -> Result<Expr, ParseError> {
+        let start = self.expect_peek()?.info.span;
+        self.expect_token(Token::FN)?;
+        let (params, ret) = self.parse_signature()?;
+        let body = self.parse_fn_body()?;
+        let kind = ExprKind::Lambda(Lambda {
+            params,
+            ret,
+            body,
+            captures: Vec::new(),
+        });
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // (Type name, ...) -> Type
+    fn parse_signature(&mut self) -> Result<(Vec<(LType, String)>, LType), ParseError> {
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LPAREN)?;
+        let mut params: Vec<(LType, String)> = Vec::new();
+        while self.expect_peek()?.token != Token::RPAREN {
+            let x = self.expect_var_sig()?;
+            params.push(x);
+            if !self.accept_token(Token::COMMA) {
+                break;
+            }
+        }
+        self.expect_closing(Token::RPAREN, &open)?;
+        self.expect_token(Token::ARROW)?;
+        let ret = self.expect_type()?;
+        Ok((params, ret))
+    }
+
+    fn parse_fn_body(&mut self) -> Result<Vec<Statement>, ParseError> {
+        // loops around a function cannot be left from inside it
+        let loops = std::mem::take(&mut self.loops);
+        let body = self.parse_block();
+        self.loops = loops;
+        body
+    }
+
+    // struct Name { Type field; ... }, without the ;
+    pub fn parse_struct(&mut self) -> Result<SStruct, ParseError> {
+        self.expect_token(Token::STRUCT)?;
+        let span = self.expect_peek()?.info.span;
+        let ident = self.expect_identifier()?;
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LCURL)?;
+
+        let mut fields: Vec<(LType, String)> = Vec::new();
+        while self.expect_peek()?.token != Token::RCURL {
+            fields.push(self.expect_var_sig()?);
+            self.expect_token(Token::SEMICOLON)?;
+        }
+        self.expect_closing(Token::RCURL, &open)?;
+
+        Ok(SStruct {
+            doc: None,
+            ident,
+            fields,
+            span,
+        })
+    }
+
+    // enum Name { A(int, float), B }, without the ;
+    pub fn parse_enum(&mut self) -> Result<SEnum, ParseError> {
+        self.expect_token(Token::ENUM)?;
+        let span = self.expect_peek()?.info.span;
+        let ident = self.expect_identifier()?;
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LCURL)?;
 
+        let mut variants: Vec<(String, Vec<LType>)> = Vec::new();
+        while self.expect_peek()?.token != Token::RCURL {
+            let variant = self.expect_identifier()?;
+            let mut payload = Vec::new();
+            if let Some(open) = self.accept_open(Token::LPAREN) {
+                while self.expect_peek()?.token != Token::RPAREN {
+                    payload.push(self.expect_type()?);
+                    if !self.accept_token(Token::COMMA) {
+                        break;
+                    }
+                }
+                self.expect_closing(Token::RPAREN, &open)?;
+            }
+            variants.push((variant, payload));
+            if !self.accept_token(Token::COMMA) {
+                break;
+            }
+        }
+        self.expect_closing(Token::RCURL, &open)?;
+
+        Ok(SEnum {
+            doc: None,
+            ident,
+            variants,
+            span,
+        })
+    }
+
+    // { pattern [if guard] => body, ... } after the scrutinee of a match,
+    // body also says whether it ended in a block, which needs no comma
+    fn parse_arms<B>(
+        &mut self,
+        body: impl FnMut(&mut Self) -> Result<(B, bool), ParseError>,
+    ) -> Result<Vec<Arm<B>>, ParseError> {
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LCURL)?;
+        let saved = std::mem::replace(&mut self.no_struct, false);
+        let arms = self.parse_arms_inner(body);
+        self.no_struct = saved;
+        let arms = arms?;
+        self.expect_closing(Token::RCURL, &open)?;
+        Ok(arms)
+    }
+
+    fn parse_arms_inner<B>(
+        &mut self,
+        mut body: impl FnMut(&mut Self) -> Result<(B, bool), ParseError>,
+    ) -> Result<Vec<Arm<B>>, ParseError> {
+        let mut arms: Vec<Arm<B>> = Vec::new();
+        while self.expect_peek()?.token != Token::RCURL {
+            let start = self.expect_peek()?.info.span;
+            let pattern = self.parse_pattern()?;
+            let span = self.span_from(start);
+            let guard = if self.accept_token(Token::IF) {
+                Some(Box::new(self.parse_expr()?))
+            } else {
+                None
+            };
+            self.expect_token(Token::FATARROW)?;
+            let (body, block) = body(self)?;
+            arms.push(Arm {
+                pattern,
+                span,
+                guard,
+                body,
+            });
+            if !self.accept_token(Token::COMMA) && !block {
+                break;
+            }
+        }
+        Ok(arms)
+    }
+
+    // _, a binding, a literal or a variant like Shape::Rect(w, _)
+    pub fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let tk = self.expect_peek()?;
+        match tk.token {
+            Token::IDENT(ident) => {
+                self.lexer.next();
+                if ident == "_" {
+                    return Ok(Pattern::Wildcard);
+                }
+                let path = self.parse_path(ident)?;
+                let Some((ident, variant)) = path.rsplit_once("::") else {
+                    return Ok(Pattern::Binding(path));
+                };
+                let mut fields = Vec::new();
+                if let Some(open) = self.accept_open(Token::LPAREN) {
                     while self.expect_peek()?.token != Token::RPAREN {
-                        args.push(self.parse_expr()?);
+                        fields.push(self.parse_pattern()?);
                         if !self.accept_token(Token::COMMA) {
                             break;
                         }
                     }
-                    self.expect_token(Token::RPAREN)?;
-                    self.expect_token(Token::SEMICOLON)?;
+                    self.expect_closing(Token::RPAREN, &open)?;
+                }
+                Ok(Pattern::Variant(VariantPattern {
+                    ident: ident.to_string(),
+                    variant: variant.to_string(),
+                    fields,
+                }))
+            }
+            Token::MINUS
+            | Token::NUMBER(..)
+            | Token::FLOATNUMBER(..)
+            | Token::STRINGLIT(_)
+            | Token::CHARLIT(_)
+            | Token::TRUE
+            | Token::FALSE => {
+                let lit = self.expect_atomic()?;
+                match &lit.kind {
+                    ExprKind::Unary(u)
+                        if !matches!(
+                            (&u.op, &u.x.kind),
+                            (UOp::Neg, ExprKind::FloatIntermediate(..))
+                        ) =>
+                    {
+                        Err(ParseError::new(Some(tk), ParseErrorReason::ExpectedPattern))
+                    }
+                    _ => Ok(Pattern::Literal(lit)),
+                }
+            }
+            _ => Err(ParseError::new(Some(tk), ParseErrorReason::ExpectedPattern)),
+        }
+    }
+
+    // ident [= value] after the type of a declaration, without the ;
+    fn parse_declare(&mut self, typ: LType) -> Result<StatementKind, ParseError> {
+        let ident = self.expect_identifier()?;
+        let assign = if self.accept_token(Token::EQUAL) {
+            Some(Box::new(self.parse_expr()?))
+        } else {
+            None
+        };
+
+        Ok(StatementKind::Declare(SDeclare {
+            doc: None,
+            typ,
+            ident,
+            assign,
+        }))
+    }
+
+    // assignment, step, call or struct declaration after its identifier,
+    // without the ;
+    // value is set inside a block expression, where any expression may end
+    // the block instead of only a call
+    fn parse_simple(
+        &mut self,
+        ident: String,
+        start: Span,
+        value: bool,
+    ) -> Result<StatementKind, ParseError> {
+        let target = self.parse_place(ident, start)?;
+        if let Some(typ) = self.place_as_type(&target)? {
+            return self.parse_declare(typ);
+        }
+        self.parse_simple_at(target, value)
+    }
 
-                    Ok(Statement::Call(SCall {
-                        ident: ident.clone(),
-                        params: args,
-                    }))
+    // Point p or Point[4] ps, a place followed by an identifier was a type
+    fn place_as_type(&mut self, place: &Place) -> Result<Option<LType>, ParseError> {
+        let tk = self.expect_peek()?;
+        if !matches!(tk.token, Token::IDENT(_)) {
+            return Ok(None);
+        }
+        let mut typ = LType::Named(place.ident.clone());
+        for access in &place.path {
+            match access {
+                Access::Index(Expr {
+                    kind: ExprKind::Intermediate(len, None),
+                    ..
+                }) if *len >= 0 => {
+                    typ = LType::Array(Box::new(typ), *len as usize);
+                }
+                _ => {
+                    return Err(ParseError::new(
+                        Some(tk),
+                        ParseErrorReason::ExpectedToken(Token::EQUAL),
+                    ));
                 }
             }
-            // while
+        }
+        Ok(Some(typ))
+    }
+
+    fn parse_simple_at(&mut self, target: Place, value: bool) -> Result<StatementKind, ParseError> {
+        let tk = self.expect_peek()?;
+        if self.accept_token(Token::EQUAL) {
+            let value = Box::new(self.parse_expr()?);
+            Ok(StatementKind::Assign(SAssign {
+                target,
+                op: None,
+                value,
+                step: false,
+            }))
+        } else if let Some(op) = Self::parse_compound(&tk.token) {
+            self.lexer.next();
+            let value = Box::new(self.parse_expr()?);
+            Ok(StatementKind::Assign(SAssign {
+                target,
+                op: Some(op),
+                value,
+                step: false,
+            }))
+        } else if let Some(op) = Self::parse_step(&tk.token) {
+            // x++ is lowered to x += 1
+            self.lexer.next();
+            Ok(StatementKind::Assign(SAssign {
+                target,
+                op: Some(op),
+                value: Box::new(Expr::new(ExprKind::Intermediate(1, None), tk.info.span)),
+                step: true,
+            }))
+        } else if !value && !target.path.is_empty() && tk.token != Token::LPAREN {
+            // p.x on its own does nothing
+            Err(ParseError::new(
+                Some(tk),
+                ParseErrorReason::ExpectedToken(Token::EQUAL),
+            ))
+        } else {
+            // f(x), or p.on_click(e) through a field
+            let callee = if target.path.is_empty() && value {
+                self.parse_ident_expr(target.ident, target.span)?
+            } else if target.path.is_empty() {
+                self.parse_call(target.ident, target.span)?
+            } else {
+                Self::place_expr(target)
+            };
+            let call = self.parse_postfix(callee)?;
+            let expr = self.parse_expr_from(Some(call))?;
+            Ok(StatementKind::Expr(Box::new(expr)))
+        }
+    }
+
+    // the fields and indices after a variable that is assigned to, like p.x[i]
+    fn parse_place(&mut self, ident: String, start: Span) -> Result<Place, ParseError> {
+        let mut path = Vec::new();
+        loop {
+            if self.accept_token(Token::DOT) {
+                path.push(Access::Field(self.expect_identifier()?));
+            } else if let Some(open) = self.accept_open(Token::LBRACKET) {
+                let index = self.parse_nested_expr()?;
+                self.expect_closing(Token::RBRACKET, &open)?;
+                path.push(Access::Index(index));
+            } else {
+                let span = self.span_from(start);
+                return Ok(Place { ident, path, span });
+            }
+        }
+    }
+
+    // the same place read as an expression, its parts all span the place
+    fn place_expr(place: Place) -> Expr {
+        let span = place.span;
+        let ident = Expr::new(ExprKind::Ident(place.ident), span);
+        place.path.into_iter().fold(ident, |base, access| {
+            let kind = match access {
+                Access::Field(field) => ExprKind::Field(FieldAccess {
+                    base: Box::new(base),
+                    field,
+                }),
+                Access::Index(index) => ExprKind::Index(IndexExpr {
+                    base: Box::new(base),
+                    index: Box::new(index),
+                }),
+            };
+            Expr::new(kind, span)
+        })
+    }
+
+    // the body is parsed with label in scope for break and continue
+    fn parse_loop(&mut self, label: Option<String>) -> Result<StatementKind, ParseError> {
+        self.loops.push(label.clone());
+        let stmt = self.parse_loop_inner(label);
+        self.loops.pop();
+        stmt
+    }
+
+    fn parse_loop_inner(&mut self, label: Option<String>) -> Result<StatementKind, ParseError> {
+        let tk = self.expect_peek()?;
+        match tk.token {
             Token::WHILE => {
                 self.lexer.next();
                 self.expect_token(Token::LPAREN)?;
@@ -116,93 +899,447 @@ impl<'a> Parser<'a> {
                 self.expect_token(Token::RPAREN)?;
 
                 let body = self.parse_block()?;
-                Ok(Statement::While(SWhile { cond, body }))
+                Ok(StatementKind::While(SWhile { label, cond, body }))
             }
-            Token::RETURN => {
+            Token::FOR => {
                 self.lexer.next();
-                let expr = Box::new(self.parse_expr()?);
-                self.expect_token(Token::SEMICOLON)?;
-                Ok(Statement::Return(expr))
+                let open = self.expect_peek()?;
+                self.expect_token(Token::LPAREN)?;
+
+                let first = self.expect_peek()?;
+                let init = if self.accept_token(Token::SEMICOLON) {
+                    None
+                } else if Self::lookup_type(&first.token).is_some() {
+                    Some(Box::new(self.parse_statement()?))
+                } else {
+                    let ident = self.expect_identifier()?;
+                    let stmt = self.parse_simple(ident, first.info.span, false)?;
+                    let stmt = Statement::new(stmt, self.span_from(first.info.span));
+                    self.expect_token(Token::SEMICOLON)?;
+                    Some(Box::new(stmt))
+                };
+                let cond = if self.accept_token(Token::SEMICOLON) {
+                    None
+                } else {
+                    let cond = self.parse_expr()?;
+                    self.expect_token(Token::SEMICOLON)?;
+                    Some(Box::new(cond))
+                };
+                let first = self.expect_peek()?;
+                let step = if first.token == Token::RPAREN {
+                    None
+                } else {
+                    let ident = self.expect_identifier()?;
+                    let stmt = self.parse_simple(ident, first.info.span, false)?;
+                    Some(Box::new(Statement::new(
+                        stmt,
+                        self.span_from(first.info.span),
+                    )))
+                };
+                self.expect_closing(Token::RPAREN, &open)?;
+
+                let body = self.parse_block()?;
+                Ok(StatementKind::For(SFor {
+                    label,
+                    init,
+                    cond,
+                    step,
+                    body,
+                }))
             }
-            // function decl
-            Token::FN => {
+            Token::LOOP => {
+                self.lexer.next();
+                let body = self.parse_block()?;
+                Ok(StatementKind::Loop(SLoop { label, body }))
+            }
+            Token::DO => {
                 self.lexer.next();
-                let ident = self.expect_identifier()?;
+                let body = self.parse_block()?;
+                self.expect_token(Token::WHILE)?;
                 self.expect_token(Token::LPAREN)?;
+                let cond = Box::new(self.parse_expr()?);
+                self.expect_token(Token::RPAREN)?;
+                self.expect_token(Token::SEMICOLON)?;
+                Ok(StatementKind::DoWhile(SDoWhile { label, body, cond }))
+            }
+            _ => Err(ParseError::new(Some(tk), ParseErrorReason::ExpectedLoop)),
+        }
+    }
 
-                let mut params: Vec<(LType, String)> = Vec::new();
-                while self.expect_peek()?.token != Token::RPAREN {
-                    let x = self.expect_var_sig()?;
-                    params.push(x);
-                    if !self.accept_token(Token::COMMA) {
-                        break;
-                    }
+    pub fn lookup_type(t: &Token) -> Option<LType> {
+        Some(match t {
+            Token::BOOL => LType::Bool,
+            Token::INT | Token::I64 => INT,
+            Token::I8 => LType::Int(IntType::I8),
+            Token::I16 => LType::Int(IntType::I16),
+            Token::I32 => LType::Int(IntType::I32),
+            Token::U8 => LType::Int(IntType::U8),
+            Token::U16 => LType::Int(IntType::U16),
+            Token::U32 => LType::Int(IntType::U32),
+            Token::U64 | Token::NATURAL => LType::Int(IntType::U64),
+            Token::FLOAT | Token::F64 | Token::REAL => FLOAT,
+            Token::F32 => LType::Float(FloatType::F32),
+            Token::CHAR => LType::Char,
+            Token::STRING => LType::String,
+            _ => return None,
+        })
+    }
+
+    pub fn parse_block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LCURL)?;
+        let mut statements: Vec<Statement> = Vec::new();
+
+        while let Some(tk) = self.lexer.peek()
+            && !matches!(tk.token, Token::RCURL | Token::EOF)
+        {
+            if let Some(s) = self.parse_recover() {
+                statements.push(s);
+            }
+        }
+        self.expect_closing(Token::RCURL, &open)?;
+        Ok(statements)
+    }
+
+    // parses every statement, statements with errors are left out
+    pub fn parse_top(&mut self) -> (Vec<Statement>, Vec<ParseError>) {
+        let mut stmts: Vec<Statement> = Vec::new();
+        while self.lexer.peek().is_some() && !self.accept_token(Token::EOF) {
+            if let Some(s) = self.parse_recover() {
+                stmts.push(s);
+            }
+        }
+        (stmts, std::mem::take(&mut self.errors))
+    }
+
+    // like parse_top but all or nothing
+    pub fn parse_program(&mut self) -> Result<Vec<Statement>, Vec<ParseError>> {
+        let (stmts, errors) = self.parse_top();
+        if errors.is_empty() {
+            Ok(stmts)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // records the error of a failed statement and skips past it
+    fn parse_recover(&mut self) -> Option<Statement> {
+        let start = self.lexer.peek().map(|t| t.info.span);
+        match self.parse_statement() {
+            Ok(s) => Some(s),
+            Err(e) => {
+                self.report(e);
+                self.synchronize(start);
+                None
+            }
+        }
+    }
+
+    // an error at a token already reported is a cascade of that one
+    fn report(&mut self, e: ParseError) {
+        let span = e.get_span();
+        if span.is_none() || !self.errors.iter().any(|p| p.get_span() == span) {
+            self.errors.push(e);
+        }
+    }
+
+    // panic mode, skips to just after a ; or to the start of the next statement
+    fn synchronize(&mut self, start: Option<Span>) {
+        // the statement failed on its first token, which must go
+        if self.lexer.peek().map(|t| t.info.span) == start
+            && let Some(tk) = self.lexer.next()
+        {
+            self.report_illegal(tk);
+        }
+
+        while let Some(tk) = self.lexer.peek() {
+            match tk.token {
+                Token::SEMICOLON => {
+                    self.lexer.next();
+                    return;
                 }
-                self.expect_token(Token::RPAREN)?;
-                self.expect_token(Token::ARROW)?;
+                Token::RCURL
+                | Token::EOF
+                | Token::LCURL
+                | Token::DOC(_)
+                | Token::IF
+                | Token::IMPORT
+                | Token::WHILE
+                | Token::FOR
+                | Token::LOOP
+                | Token::DO
+                | Token::BREAK
+                | Token::CONTINUE
+                | Token::RETURN
+                | Token::FN
+                | Token::STRUCT
+                | Token::ENUM
+                | Token::MATCH
+                | Token::CHAR
+                | Token::STRING => return,
+                ref t if Self::lookup_type(t).is_some() => return,
+                _ => {
+                    let tk = self.lexer.next().unwrap();
+                    self.report_illegal(tk);
+                }
+            }
+        }
+    }
+
+    // lexical errors skipped over still need reporting
+    fn report_illegal(&mut self, tk: LexedToken) {
+        if let Token::ILLEGAL(reason) = &tk.token {
+            let reason = ParseErrorReason::Lex(reason.clone());
+            self.report(ParseError::new(Some(tk), reason));
+        }
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        self.parse_expr_from(None)
+    }
 
-                let ret = self.expect_type()?;
-                let body = self.parse_block()?;
+    // binary expression starting at l, then c ? a : b binding loosest
+    pub fn parse_expr_from(&mut self, l: Option<Expr>) -> Result<Expr, ParseError> {
+        let guard = self.parse_expr_internal(l, 0)?;
+        if !self.accept_token(Token::QUESTION) {
+            return Ok(guard);
+        }
+        let t = self.parse_expr()?;
+        self.expect_token(Token::COLON)?;
+        let f = self.parse_expr()?;
+        let span = guard.span.to(f.span);
+        let kind = ExprKind::If(IfExpr {
+            guard: Box::new(guard),
+            t: Box::new(t),
+            f: Box::new(f),
+        });
+        Ok(Expr::new(kind, span))
+    }
 
-                self.expect_token(Token::SEMICOLON)?;
+    // an if guard, struct literals there need parentheses
+    fn parse_guard(&mut self) -> Result<Expr, ParseError> {
+        let saved = std::mem::replace(&mut self.no_struct, true);
+        let guard = self.parse_expr();
+        self.no_struct = saved;
+        guard
+    }
 
-                Ok(Statement::Function(SFunction {
-                    ident,
-                    ret,
-                    params,
-                    body,
-                }))
-            }
-            _ => {
-                if let Some(typ) = self.lookup_type(&tk.token) {
-                    self.lexer.next();
-                    let ident = self.expect_identifier()?;
+    // an expression inside delimiters, where struct literals are allowed again
+    fn parse_nested_expr(&mut self) -> Result<Expr, ParseError> {
+        let saved = std::mem::replace(&mut self.no_struct, false);
+        let expr = self.parse_expr();
+        self.no_struct = saved;
+        expr
+    }
 
-                    let assign = if self.accept_token(Token::EQUAL) {
-                        Some(Box::new(self.parse_expr_internal(None, 0)?))
-                    } else {
-                        None
-                    };
-                    self.expect_token(Token::SEMICOLON)?;
+    // { stmts; value }, the value is the trailing expression without a ;
+    pub fn parse_block_expr(&mut self) -> Result<BlockExpr, ParseError> {
+        let saved = std::mem::replace(&mut self.no_struct, false);
+        let block = self.parse_block_expr_inner();
+        self.no_struct = saved;
+        block
+    }
 
-                    Ok(Statement::Declare(SDeclare { typ, ident, assign }))
-                } else {
-                    Err(ParseError::new(Some(tk), ParseErrorReason::BadStatement))
+    fn parse_block_expr_inner(&mut self) -> Result<BlockExpr, ParseError> {
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LCURL)?;
+        let mut stmts: Vec<Statement> = Vec::new();
+        loop {
+            let tk = self.expect_peek()?;
+            let start = tk.info.span;
+            let expr = match &tk.token {
+                Token::RCURL => break,
+                Token::IDENT(ident) => {
+                    self.lexer.next();
+                    if self.accept_token(Token::COLON) {
+                        let stmt = self.parse_loop(Some(ident.clone()))?;
+                        stmts.push(Statement::new(stmt, self.span_from(start)));
+                        continue;
+                    }
+                    let ident = self.parse_path(ident.clone())?;
+                    match self.parse_simple(ident, start, true)? {
+                        StatementKind::Expr(expr) => *expr,
+                        stmt => {
+                            self.expect_token(Token::SEMICOLON)?;
+                            stmts.push(Statement::new(stmt, self.span_from(start)));
+                            continue;
+                        }
+                    }
                 }
+                Token::LCURL | Token::IF | Token::MATCH => match self.parse_branching()? {
+                    (StatementKind::Expr(expr), true) => *expr,
+                    (stmt, _) => {
+                        stmts.push(Statement::new(stmt, self.span_from(start)));
+                        continue;
+                    }
+                },
+                t if Self::starts_value(t) => self.parse_expr()?,
+                _ => {
+                    stmts.push(self.parse_statement()?);
+                    continue;
+                }
+            };
+            if self.accept_token(Token::SEMICOLON) {
+                let span = self.span_from(start);
+                stmts.push(Statement::new(StatementKind::Expr(Box::new(expr)), span));
+                continue;
             }
+            self.expect_closing(Token::RCURL, &open)?;
+            return Ok(BlockExpr {
+                stmts,
+                value: Some(Box::new(expr)),
+            });
         }
+        self.expect_closing(Token::RCURL, &open)?;
+        Ok(BlockExpr { stmts, value: None })
     }
 
-    pub fn lookup_type(&self, t: &Token) -> Option<LType> {
-        Some(match t {
-            Token::INT => LType::Int,
-            Token::FLOAT => LType::Float,
-            _ => return None,
-        })
+    // a block, if or match in a block expression is a statement when a ;
+    // follows, otherwise it is the value of the block; true with the value
+    fn parse_branching(&mut self) -> Result<(StatementKind, bool), ParseError> {
+        let tk = self.expect_peek()?;
+        if tk.token == Token::IF {
+            return self.parse_if_item();
+        }
+        let expr = self.expect_primary(tk)?;
+        if !self.accept_token(Token::SEMICOLON) {
+            return Ok((StatementKind::Expr(Box::new(expr)), true));
+        }
+        let span = expr.span;
+        let stmt = match expr.kind {
+            ExprKind::Block(BlockExpr { stmts, value: None }) => StatementKind::Block(stmts),
+            ExprKind::Match(m) if m.arms.iter().all(|a| Self::is_statement_block(&a.body)) => {
+                let arms = m.arms.into_iter().map(|a| Arm {
+                    pattern: a.pattern,
+                    span: a.span,
+                    guard: a.guard,
+                    body: Self::block_statements(a.body),
+                });
+                StatementKind::Match(SMatch {
+                    scrutinee: m.scrutinee,
+                    arms: arms.collect(),
+                })
+            }
+            // a value thrown away, like f(x);
+            kind => StatementKind::Expr(Box::new(Expr::new(kind, span))),
+        };
+        Ok((stmt, false))
     }
 
-    pub fn parse_block(&mut self) -> Result<Vec<Statement>, ParseError> {
-        self.expect_token(Token::LCURL)?;
-        let mut statements: Vec<Statement> = Vec::new();
-
-        //TODO: wtf
-        while !(matches!(self.expect_peek()?.token, Token::RCURL)) {
-            statements.push(self.parse_statement()?);
+    // if c { a } else if d { b } else { e }, the else may only be left out
+    // of a statement
+    fn parse_if_item(&mut self) -> Result<(StatementKind, bool), ParseError> {
+        let start = self.expect_peek()?.info.span;
+        self.expect_token(Token::IF)?;
+        let guard = Box::new(self.parse_guard()?);
+        let t = self.parse_block_value()?;
+        let mut elifs: Vec<(Box<Expr>, Expr)> = Vec::new();
+        let mut f = None;
+        while self.accept_token(Token::ELSE) {
+            if !self.accept_token(Token::IF) {
+                f = Some(self.parse_block_value()?);
+                break;
+            }
+            let guard = Box::new(self.parse_guard()?);
+            elifs.push((guard, self.parse_block_value()?));
         }
-        self.expect_token(Token::RCURL)?;
-        Ok(statements)
+        let end = self.expect_peek()?;
+        let statement = self.accept_token(Token::SEMICOLON);
+        let blocks = std::iter::once(&t).chain(elifs.iter().map(|(_, b)| b));
+        if statement && blocks.chain(&f).all(Self::is_statement_block) {
+            let elifs = elifs.into_iter().map(|(guard, body)| SElseIf {
+                guard,
+                body: Self::block_statements(body),
+            });
+            let stmt = StatementKind::If(SIf {
+                guard,
+                t: Self::block_statements(t),
+                elifs: elifs.collect(),
+                f: f.map(Self::block_statements),
+            });
+            return Ok((stmt, false));
+        }
+        let Some(f) = f else {
+            let expected = if statement {
+                Token::ELSE
+            } else {
+                Token::SEMICOLON
+            };
+            return Err(ParseError::new(
+                Some(end),
+                ParseErrorReason::ExpectedToken(expected),
+            ));
+        };
+        let f = elifs.into_iter().rev().fold(f, |f, (guard, t)| {
+            let span = guard.span.to(f.span);
+            let kind = ExprKind::If(IfExpr {
+                guard,
+                t: Box::new(t),
+                f: Box::new(f),
+            });
+            Expr::new(kind, span)
+        });
+        let kind = ExprKind::If(IfExpr {
+            guard,
+            t: Box::new(t),
+            f: Box::new(f),
+        });
+        let expr = Expr::new(kind, self.span_from(start));
+        Ok((StatementKind::Expr(Box::new(expr)), !statement))
     }
 
-    pub fn parse_top(&mut self) -> Result<Vec<Statement>, ParseError> {
-        let mut stmts: Vec<Statement> = Vec::new();
-        while !self.accept_token(Token::EOF) {
-            stmts.push(self.parse_statement()?);
+    // a block without a value, which can run as statements
+    fn is_statement_block(expr: &Expr) -> bool {
+        matches!(&expr.kind, ExprKind::Block(b) if b.value.is_none())
+    }
+
+    fn block_statements(expr: Expr) -> Vec<Statement> {
+        match expr.kind {
+            ExprKind::Block(b) => b.stmts,
+            _ => unreachable!("parsed as a block"),
         }
-        Ok(stmts)
     }
 
-    pub fn parse_expr(&mut self) -> Result<Expr, ParseError> {
-        self.parse_expr_internal(None, 0)
+    // tokens that start an expression but never a statement
+    fn starts_value(t: &Token) -> bool {
+        matches!(
+            t,
+            Token::NUMBER(..)
+                | Token::FLOATNUMBER(..)
+                | Token::STRINGLIT(_)
+                | Token::CHARLIT(_)
+                | Token::TRUE
+                | Token::FALSE
+                | Token::LPAREN
+                | Token::LBRACKET
+        ) || Self::parse_unary(t).is_some()
+    }
+
+    // if c { a } else { b }, the else is required for a value
+    fn parse_if_expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.expect_peek()?.info.span;
+        self.expect_token(Token::IF)?;
+        let guard = self.parse_guard()?;
+        let t = self.parse_block_value()?;
+        self.expect_token(Token::ELSE)?;
+        let f = if self.expect_peek()?.token == Token::IF {
+            self.parse_if_expr()?
+        } else {
+            self.parse_block_value()?
+        };
+        let kind = ExprKind::If(IfExpr {
+            guard: Box::new(guard),
+            t: Box::new(t),
+            f: Box::new(f),
+        });
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // a block expression as an Expr
+    fn parse_block_value(&mut self) -> Result<Expr, ParseError> {
+        let start = self.expect_peek()?.info.span;
+        let block = self.parse_block_expr()?;
+        Ok(Expr::new(ExprKind::Block(block), self.span_from(start)))
     }
 
     pub fn parse_expr_internal(
@@ -210,8 +1347,9 @@ impl<'a> Parser<'a> {
         l: Option<Expr>,
         min_prec: u32,
     ) -> Result<Expr, ParseError> {
+        // an operand parsed by the caller may still be cast
         let mut lhs = if let Some(x) = l {
-            x
+            self.parse_casts(x)?
         } else {
             self.expect_atomic()?
         };
@@ -242,30 +1380,180 @@ impl<'a> Parser<'a> {
                 Token::GT => Op::Gt,
                 Token::LT => Op::Lt,
                 Token::EQUALEQUAL => Op::Eq,
+                Token::PERCENT => Op::Mod,
+                Token::LTEQUAL => Op::Le,
+                Token::GTEQUAL => Op::Ge,
+                Token::BANGEQUAL => Op::Ne,
+                Token::AMPAMP => Op::And,
+                Token::PIPEPIPE => Op::Or,
+                Token::AMP => Op::BitAnd,
+                Token::PIPE => Op::BitOr,
+                Token::CARET => Op::BitXor,
+                Token::LTLT => Op::Shl,
+                Token::GTGT => Op::Shr,
                 _ => {
                     return Err(ParseError::new(Some(op), ParseErrorReason::BadBinOp));
                 }
             };
 
-            lhs = Expr::Binop(Binop {
+            let span = lhs.span.to(rhs.span);
+            let kind = ExprKind::Binop(Binop {
                 a: Box::new(lhs),
                 op: binop,
                 b: Box::new(rhs),
-            })
+            });
+            lhs = Expr::new(kind, span);
         }
         Ok(lhs)
     }
 
+    // joins a qualified name like math::sqrt
+    pub fn parse_path(&mut self, mut ident: String) -> Result<String, ParseError> {
+        while self.accept_token(Token::COLONCOLON) {
+            ident.push_str("::");
+            ident.push_str(&self.expect_identifier()?);
+        }
+        Ok(ident)
+    }
+
+    // the argument list of a call, after its identifier which starts at start
+    pub fn parse_call(&mut self, ident: String, start: Span) -> Result<Expr, ParseError> {
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LPAREN)?;
+        let params = self.parse_args(&open)?;
+        let kind = ExprKind::Call(SCall { ident, params });
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // arguments up to the ) closing open
+    fn parse_args(&mut self, open: &LexedToken) -> Result<Vec<Expr>, ParseError> {
+        let mut params: Vec<Expr> = Vec::new();
+        while self.expect_peek()?.token != Token::RPAREN {
+            params.push(self.parse_nested_expr()?);
+            if !self.accept_token(Token::COMMA) {
+                break;
+            }
+        }
+        self.expect_closing(Token::RPAREN, open)?;
+        Ok(params)
+    }
+
+    // the fields of a struct literal, after its type name
+    fn parse_struct_literal(&mut self, ident: String, start: Span) -> Result<Expr, ParseError> {
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LCURL)?;
+        let mut fields: Vec<FieldInit> = Vec::new();
+        while self.expect_peek()?.token != Token::RCURL {
+            let field = self.expect_identifier()?;
+            self.expect_token(Token::COLON)?;
+            let value = self.parse_nested_expr()?;
+            fields.push(FieldInit {
+                ident: field,
+                value,
+            });
+            if !self.accept_token(Token::COMMA) {
+                break;
+            }
+        }
+        self.expect_closing(Token::RCURL, &open)?;
+        let kind = ExprKind::StructLiteral(StructLiteral { ident, fields });
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // a call, struct literal or variable, with any fields read from it
+    fn parse_ident_expr(&mut self, ident: String, start: Span) -> Result<Expr, ParseError> {
+        let next = self.lexer.peek().map(|t| t.token.clone());
+        let expr = match next {
+            Some(Token::LPAREN) => self.parse_call(ident, start)?,
+            Some(Token::LCURL) if !self.no_struct => self.parse_struct_literal(ident, start)?,
+            _ => Expr::new(ExprKind::Ident(ident), self.span_from(start)),
+        };
+        self.parse_postfix(expr)
+    }
+
+    // field accesses, indices and calls after an atom, like f().x[0](1)
+    fn parse_postfix(&mut self, mut expr: Expr) -> Result<Expr, ParseError> {
+        loop {
+            let start = expr.span;
+            let kind = if let Some(open) = self.accept_open(Token::LPAREN) {
+                let params = self.parse_args(&open)?;
+                ExprKind::Apply(Apply {
+                    f: Box::new(expr),
+                    params,
+                })
+            } else if self.accept_token(Token::DOT) {
+                let field = self.expect_identifier()?;
+                ExprKind::Field(FieldAccess {
+                    base: Box::new(expr),
+                    field,
+                })
+            } else if let Some(open) = self.accept_open(Token::LBRACKET) {
+                let index = self.parse_nested_expr()?;
+                self.expect_closing(Token::RBRACKET, &open)?;
+                ExprKind::Index(IndexExpr {
+                    base: Box::new(expr),
+                    index: Box::new(index),
+                })
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::new(kind, self.span_from(start));
+        }
+    }
+
+    // [a, b, c]
+    fn parse_array_literal(&mut self) -> Result<Expr, ParseError> {
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LBRACKET)?;
+        let mut items: Vec<Expr> = Vec::new();
+        while self.expect_peek()?.token != Token::RBRACKET {
+            items.push(self.parse_nested_expr()?);
+            if !self.accept_token(Token::COMMA) {
+                break;
+            }
+        }
+        self.expect_closing(Token::RBRACKET, &open)?;
+        let kind = ExprKind::ArrayLiteral(items);
+        Ok(Expr::new(kind, self.span_from(open.info.span)))
+    }
+
     pub fn parse_unary(t: &Token) -> Option<UOp> {
         match t {
             Token::PLUS => Some(UOp::Pos),
             Token::MINUS => Some(UOp::Neg),
+            Token::BANG => Some(UOp::Not),
+            Token::TILDE => Some(UOp::BitNot),
+            _ => None,
+        }
+    }
+
+    pub fn parse_compound(t: &Token) -> Option<Op> {
+        match t {
+            Token::PLUSEQUAL => Some(Op::Add),
+            Token::MINUSEQUAL => Some(Op::Sub),
+            Token::ASTEREQUAL => Some(Op::Mul),
+            Token::SLASHEQUAL => Some(Op::Div),
+            Token::PERCENTEQUAL => Some(Op::Mod),
+            _ => None,
+        }
+    }
+
+    pub fn parse_step(t: &Token) -> Option<Op> {
+        match t {
+            Token::PLUSPLUS => Some(Op::Add),
+            Token::MINUSMINUS => Some(Op::Sub),
             _ => None,
         }
     }
 
     pub fn expect_peek(&mut self) -> Result<LexedToken, ParseError> {
         if let Some(tk) = self.lexer.peek() {
+            if let Token::ILLEGAL(reason) = &tk.token {
+                return Err(ParseError::new(
+                    Some(tk.clone()),
+                    ParseErrorReason::Lex(reason.clone()),
+                ));
+            }
             Ok(tk.clone())
         } else {
             Err(ParseError::new(None, ParseErrorReason::StreamEnded))
@@ -286,14 +1574,63 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // like expect_token but points back at the opening delimiter
+    fn expect_closing(&mut self, token: Token, open: &LexedToken) -> Result<(), ParseError> {
+        self.expect_token(token)
+            .map_err(|e| e.with_related(open.info.span, "unclosed delimiter"))
+    }
+
     pub fn expect_type(&mut self) -> Result<LType, ParseError> {
         let t = self.expect_peek()?;
-        if let Some(typ) = self.lookup_type(&t.token) {
+        let typ = if let Some(typ) = Self::lookup_type(&t.token) {
+            self.lexer.next();
+            typ
+        } else if let Token::IDENT(ident) = t.token {
             self.lexer.next();
-            Ok(typ)
+            LType::Named(self.parse_path(ident)?)
+        } else if let Token::FN = t.token {
+            self.lexer.next();
+            self.parse_fn_type()?
         } else {
-            Err(ParseError::new(Some(t), ParseErrorReason::ExpectedType))
+            return Err(ParseError::new(Some(t), ParseErrorReason::ExpectedType));
+        };
+        self.parse_array_suffix(typ)
+    }
+
+    // (Type, ...) -> Type after fn, the return type takes any [n] after it
+    fn parse_fn_type(&mut self) -> Result<LType, ParseError> {
+        let open = self.expect_peek()?;
+        self.expect_token(Token::LPAREN)?;
+        let mut params = Vec::new();
+        while self.expect_peek()?.token != Token::RPAREN {
+            params.push(self.expect_type()?);
+            if !self.accept_token(Token::COMMA) {
+                break;
+            }
+        }
+        self.expect_closing(Token::RPAREN, &open)?;
+        self.expect_token(Token::ARROW)?;
+        let ret = self.expect_type()?;
+        Ok(LType::Fn(params, Box::new(ret)))
+    }
+
+    // int[8][2] is two int[8]
+    fn parse_array_suffix(&mut self, mut typ: LType) -> Result<LType, ParseError> {
+        while let Some(open) = self.accept_open(Token::LBRACKET) {
+            let len = self.expect_peek()?;
+            let Token::NUMBER(n, None) = len.token else {
+                return Err(ParseError::new(
+                    Some(len),
+                    ParseErrorReason::ExpectedArrayLength,
+                ));
+            };
+            self.lexer.next();
+            let n = usize::try_from(n)
+                .map_err(|_| ParseError::new(Some(len), ParseErrorReason::LiteralOutOfRange))?;
+            self.expect_closing(Token::RBRACKET, &open)?;
+            typ = LType::Array(Box::new(typ), n);
         }
+        Ok(typ)
     }
 
     pub fn expect_var_sig(&mut self) -> Result<(LType, String), ParseError> {
@@ -327,6 +1664,34 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // joins consecutive doc comment lines
+    pub fn accept_doc(&mut self) -> Option<String> {
+        let mut doc: Option<String> = None;
+        while let Some(tk) = self.lexer.peek()
+            && let Token::DOC(line) = &tk.token
+        {
+            let line = line.clone();
+            self.lexer.next();
+            match &mut doc {
+                Some(d) => {
+                    d.push('\n');
+                    d.push_str(&line);
+                }
+                None => doc = Some(line),
+            }
+        }
+        doc
+    }
+
+    // like accept_token but returns the opening delimiter for expect_closing
+    fn accept_open(&mut self, token: Token) -> Option<LexedToken> {
+        let tk = self.lexer.peek().filter(|tk| tk.token == token).cloned();
+        if tk.is_some() {
+            self.lexer.next();
+        }
+        tk
+    }
+
     pub fn accept_token(&mut self, token: Token) -> bool {
         if let Some(tk) = self.lexer.peek()
             && tk.token == token
@@ -338,27 +1703,129 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // the type a literal suffix names
+    fn suffix_type(suffix: NumSuffix) -> LType {
+        match suffix {
+            NumSuffix::I8 => LType::Int(IntType::I8),
+            NumSuffix::I16 => LType::Int(IntType::I16),
+            NumSuffix::I32 => LType::Int(IntType::I32),
+            NumSuffix::I64 => INT,
+            NumSuffix::U8 => LType::Int(IntType::U8),
+            NumSuffix::U16 => LType::Int(IntType::U16),
+            NumSuffix::U32 => LType::Int(IntType::U32),
+            NumSuffix::U64 => LType::Int(IntType::U64),
+            NumSuffix::F32 => LType::Float(FloatType::F32),
+            NumSuffix::F64 => FLOAT,
+        }
+    }
+
+    // the range is checked once the literal's type is known
+    fn int_literal(n: u64, suffix: Option<NumSuffix>, negate: bool, span: Span) -> Expr {
+        let typ = match suffix.map(Self::suffix_type) {
+            Some(LType::Int(t)) => Some(t),
+            _ => None,
+        };
+        let n = i128::from(n);
+        Expr::new(
+            ExprKind::Intermediate(if negate { -n } else { n }, typ),
+            span,
+        )
+    }
+
+    // a unary expression followed by any casts, -x as u8 casts -x
     pub fn expect_atomic(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.expect_unary()?;
+        self.parse_casts(expr)
+    }
+
+    fn parse_casts(&mut self, mut expr: Expr) -> Result<Expr, ParseError> {
+        while self.accept_token(Token::AS) {
+            let typ = self.expect_type()?;
+            let start = expr.span;
+            let kind = ExprKind::Cast(Cast {
+                x: Box::new(expr),
+                typ,
+            });
+            expr = Expr::new(kind, self.span_from(start));
+        }
+        Ok(expr)
+    }
+
+    fn expect_unary(&mut self) -> Result<Expr, ParseError> {
         let tk = self.expect_peek()?;
 
-        if let Token::LPAREN = tk.token {
+        if let Some(uop) = Self::parse_unary(&tk.token) {
+            self.lexer.next();
+            // -128i8 is one literal, 128i8 alone does not fit
+            if let UOp::Neg = uop
+                && let Some(lit) = self.lexer.peek().cloned()
+                && let Token::NUMBER(n, suffix) = lit.token
+            {
+                self.lexer.next();
+                let span = tk.info.span.to(lit.info.span);
+                return Ok(Self::int_literal(n, suffix, true, span));
+            }
+            let u = Unary {
+                op: uop,
+                x: Box::new(self.expect_unary()?),
+            };
+            return Ok(Expr::new(ExprKind::Unary(u), self.span_from(tk.info.span)));
+        }
+        if let Token::IDENT(s) = tk.token {
+            self.lexer.next();
+            let s = self.parse_path(s)?;
+            return self.parse_ident_expr(s, tk.info.span);
+        }
+        let primary = self.expect_primary(tk)?;
+        self.parse_postfix(primary)
+    }
+
+    // a literal or a delimited expression
+    fn expect_primary(&mut self, tk: LexedToken) -> Result<Expr, ParseError> {
+        let span = tk.info.span;
+        if let Token::LCURL = tk.token {
+            self.parse_block_value()
+        } else if let Token::IF = tk.token {
+            self.parse_if_expr()
+        } else if let Token::FN = tk.token {
+            self.parse_lambda()
+        } else if let Token::MATCH = tk.token {
             self.lexer.next();
-            let expr = self.parse_expr_internal(None, 0)?;
-            self.expect_token(Token::RPAREN)?;
+            let scrutinee = Box::new(self.parse_guard()?);
+            let arms = self.parse_arms(|p| {
+                let body = p.parse_expr()?;
+                let block = matches!(body.kind, ExprKind::Block(_));
+                Ok((body, block))
+            })?;
+            let kind = ExprKind::Match(MatchExpr { scrutinee, arms });
+            Ok(Expr::new(kind, self.span_from(span)))
+        } else if let Token::LPAREN = tk.token {
+            self.lexer.next();
+            let expr = self.parse_nested_expr()?;
+            self.expect_closing(Token::RPAREN, &tk)?;
             Ok(expr)
-        } else if let Token::NUMBER(n) = tk.token {
+        } else if let Token::LBRACKET = tk.token {
+            self.parse_array_literal()
+        } else if let Token::NUMBER(n, suffix) = tk.token {
             self.lexer.next();
-            Ok(Expr::Intermediate(n.into()))
-        } else if let Some(uop) = Self::parse_unary(&tk.token) {
+            Ok(Self::int_literal(n, suffix, false, span))
+        } else if let Token::FLOATNUMBER(x, suffix) = tk.token {
             self.lexer.next();
-            let u = Unary {
-                op: uop,
-                x: Box::new(self.expect_atomic()?),
+            let typ = match suffix.map(Self::suffix_type) {
+                Some(LType::Float(t)) => Some(t),
+                _ => None,
             };
-            Ok(Expr::Unary(u))
-        } else if let Token::IDENT(s) = tk.token {
+            Ok(Expr::new(ExprKind::FloatIntermediate(x, typ), span))
+        } else if let Token::STRINGLIT(s) = tk.token {
+            self.lexer.next();
+            Ok(Expr::new(ExprKind::StringLiteral(s), span))
+        } else if let Token::CHARLIT(c) = tk.token {
             self.lexer.next();
-            Ok(Expr::Ident(s.clone()))
+            Ok(Expr::new(ExprKind::CharLiteral(c), span))
+        } else if let Token::TRUE | Token::FALSE = tk.token {
+            self.lexer.next();
+            let kind = ExprKind::BoolLiteral(tk.token == Token::TRUE);
+            Ok(Expr::new(kind, span))
         } else {
             Err(ParseError::new(
                 Some(tk),
@@ -370,14 +1837,20 @@ impl<'a> Parser<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::Parser;
+    use super::{Parse, ParseErrorReason, Parser};
+    use crate::ast::{
+        Expr, ExprKind, FLOAT, FloatType, INT, IntType, LType, Pattern, SEnum, SFunction,
+        Statement, StatementKind,
+    };
+    use crate::lexer::{LexedToken, Lexer};
+    use crate::tokens::LexErrorReason;
 
     #[test]
     fn parse_basic() {
         let source = include_str!("ro/basic.ro");
         let mut parser = Parser::new(source.chars());
 
-        let _stmts = parser.parse_top().expect("basic.ro should parse");
+        let _stmts = parser.parse_program().expect("basic.ro should parse");
     }
 
     #[test]
@@ -385,9 +1858,568 @@ mod tests {
         let source = include_str!("ro/fib.ro");
         let mut parser = Parser::new(source.chars());
 
-        let _stmts = parser.parse_top().unwrap_or_else(|e| {
-            eprintln!("parse_top failed: {e:?}");
+        let _stmts = parser.parse_program().unwrap_or_else(|e| {
+            eprintln!("parse_program failed: {e:?}");
             panic!("fib.ro should parse");
         });
     }
+
+    #[test]
+    fn parse_precedence() {
+        let source = "int a = 1 | 2 ^ 3 & 4 == 5 < 6 << 7 + 8 * 9 || !a && ~a != 0;";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+
+        let StatementKind::Declare(d) = &stmts[0].kind else {
+            panic!("expected a declaration");
+        };
+        let sexpr = |e: &Expr| -> String {
+            fn go(e: &Expr) -> String {
+                match &e.kind {
+                    ExprKind::Binop(b) => format!("({} {} {})", go(&b.a), b.op, go(&b.b)),
+                    ExprKind::Unary(u) => format!("{}{}", u.op, go(&u.x)),
+                    _ => e.to_string(),
+                }
+            }
+            go(e)
+        };
+        assert_eq!(
+            sexpr(d.assign.as_ref().unwrap()),
+            "((1 | (2 ^ (3 & (4 == (5 < (6 << (7 + (8 * 9)))))))) || (!a && (~a != 0)))"
+        );
+    }
+
+    #[test]
+    fn parse_compound_assign() {
+        let source = "a += 1; a -= 2; a *= 3; a /= 4; a %= 5; a++; a--;";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+
+        let labels: Vec<String> = stmts.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "assign<a +=>",
+                "assign<a -=>",
+                "assign<a *=>",
+                "assign<a /=>",
+                "assign<a %=>",
+                "assign<a +=>",
+                "assign<a -=>",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_doc_comments() {
+        let source = "/// adds\n/// two ints\nfn add(int a, int b) -> int { return a + b; };";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+
+        let StatementKind::Function(f) = &stmts[0].kind else {
+            panic!("expected a function");
+        };
+        assert_eq!(f.doc.as_deref(), Some("adds\ntwo ints"));
+
+        let err = Parser::new("/// dangling\na = 1;".chars())
+            .parse_program()
+            .unwrap_err();
+        assert!(matches!(
+            err[0].reason,
+            ParseErrorReason::DanglingDocComment
+        ));
+
+        // at the end of the file or a block
+        for source in ["int a;\n/// trailing", "fn f() -> int { /// last\n};"] {
+            let err = Parser::new(source.chars()).parse_program().unwrap_err();
+            assert!(matches!(
+                err[0].reason,
+                ParseErrorReason::DanglingDocComment
+            ));
+        }
+
+        let err = Parser::new("int a; /* open".chars())
+            .parse_program()
+            .unwrap_err();
+        assert!(matches!(
+            err[0].reason,
+            ParseErrorReason::Lex(LexErrorReason::UnterminatedComment)
+        ));
+    }
+
+    #[test]
+    fn parse_int_range() {
+        let source = "int a = -9223372036854775808; int b = 9223372036854775807i64;";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let StatementKind::Declare(d) = &stmts[0].kind else {
+            panic!("expected a declaration");
+        };
+        assert!(matches!(
+            d.assign.as_deref().map(|e| &e.kind),
+            Some(ExprKind::Intermediate(-9223372036854775808, None))
+        ));
+        let StatementKind::Declare(d) = &stmts[1].kind else {
+            panic!("expected a declaration");
+        };
+        assert!(matches!(
+            d.assign.as_deref().map(|e| &e.kind),
+            Some(ExprKind::Intermediate(
+                9223372036854775807,
+                Some(IntType::I64)
+            ))
+        ));
+
+        // the checker decides whether it fits once it has a type
+        assert!(
+            Parser::new("natural a = 9223372036854775808;".chars())
+                .parse_program()
+                .is_ok()
+        );
+        let err = Parser::new("int[99999999999999999999] a;".chars())
+            .parse_program()
+            .unwrap_err();
+        assert!(matches!(err[0].reason, ParseErrorReason::Lex(_)));
+    }
+
+    #[test]
+    fn parse_recovery() {
+        let source = "int a = ;\nfn f(int x) -> int {\n    x = 1 2;\n    return x;\n};\nint b = 0x;\nb = 3;\n}\nint c = (1;";
+        let (stmts, errors) = Parser::new(source.chars()).parse_top();
+
+        let labels: Vec<String> = stmts.iter().map(|s| s.to_string()).collect();
+        assert_eq!(labels, vec!["fn <f(int x) -> int>", "assign<b>"]);
+
+        let reasons: Vec<String> = errors.iter().map(|e| format!("{:?}", e.reason)).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "NonAtomicExpression",
+                "ExpectedToken(SEMICOLON)",
+                "Lex(MissingDigits { radix: 16 })",
+                "BadStatement",
+                "ExpectedToken(RPAREN)",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_diagnostic() {
+        let source = "int a = 1 b;\nint c = (2;";
+        let (_, errors) = Parser::new(source.chars()).parse_top();
+        let diags: Vec<_> = errors.iter().map(|e| e.diagnostic()).collect();
+
+        assert_eq!(diags[0].message, "expected `;`, found identifier `b`");
+        assert_eq!(diags[1].message, "expected `)`, found `;`");
+        let labels: Vec<&str> = diags[1].labels.iter().map(|l| l.message.as_str()).collect();
+        assert_eq!(labels, vec!["expected `)`", "unclosed delimiter"]);
+    }
+
+    #[test]
+    fn parse_call_expr() {
+        let source = "int x = f(g(1, 2) * 3, h()) + 4; f(x);";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+
+        let StatementKind::Declare(d) = &stmts[0].kind else {
+            panic!("expected a declaration");
+        };
+        let Some(ExprKind::Binop(sum)) = d.assign.as_deref().map(|e| &e.kind) else {
+            panic!("expected a sum");
+        };
+        let ExprKind::Call(f) = &sum.a.kind else {
+            panic!("expected a call");
+        };
+        assert_eq!(f.ident, "f");
+        assert_eq!(f.params.len(), 2);
+        assert!(matches!(&f.params[1].kind, ExprKind::Call(h) if h.params.is_empty()));
+        assert!(
+            matches!(&stmts[1].kind, StatementKind::Expr(e) if matches!(&e.kind, ExprKind::Call(_)))
+        );
+    }
+
+    #[test]
+    fn parse_else_if() {
+        let source = "if a { } else if b { x = 1; } else if c { } else { };";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+
+        let StatementKind::If(sif) = &stmts[0].kind else {
+            panic!("expected an if");
+        };
+        assert_eq!(sif.elifs.len(), 2);
+        assert_eq!(sif.elifs[0].body.len(), 1);
+        assert!(sif.f.is_some());
+    }
+
+    #[test]
+    fn parse_loops() {
+        let source = "
+            outer: for (int i = 0; i < 3; i++) {
+                loop { break outer; }
+                do { continue; } while (1);
+            }
+            for (;;) { break; }
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let StatementKind::For(f) = &stmts[0].kind else {
+            panic!("expected a for loop");
+        };
+        assert_eq!(f.label.as_deref(), Some("outer"));
+        assert!(f.init.is_some() && f.cond.is_some() && f.step.is_some());
+        assert!(matches!(&stmts[1].kind, StatementKind::For(f) if f.cond.is_none()));
+
+        let source = "break; while (1) { continue nope; } fn f() -> int { loop { g(); } break; };";
+        let (_, errors) = Parser::new(source.chars()).parse_top();
+        let reasons: Vec<String> = errors.iter().map(|e| format!("{:?}", e.reason)).collect();
+        assert_eq!(
+            reasons,
+            vec!["OutsideLoop", "UndefinedLabel(\"nope\")", "OutsideLoop"]
+        );
+    }
+
+    #[test]
+    fn parse_fragments() {
+        let expr: Expr = "a + b * 8".parse().unwrap();
+        assert!(
+            matches!(&expr.kind, ExprKind::Binop(b) if matches!(&b.b.kind, ExprKind::Binop(_)))
+        );
+
+        let f: SFunction = "/// doubles\nfn double(int x) -> int { return x * 2; }"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            (f.ident.as_str(), f.doc.as_deref()),
+            ("double", Some("doubles"))
+        );
+        assert_eq!("string".parse::<LType>().unwrap(), LType::String);
+        assert!(matches!(
+            "x++;".parse::<Statement>().map(|s| s.kind),
+            Ok(StatementKind::Assign(_))
+        ));
+        assert_eq!(
+            Vec::<Statement>::parse_str("{ int a; a = 1; }")
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let tokens: Vec<LexedToken> = Lexer::new("f(1)".chars().peekable()).collect();
+        assert!(matches!(
+            Expr::parse(&tokens[..4]).map(|e| e.kind),
+            Ok(ExprKind::Call(_))
+        ));
+
+        let err = "a + b c".parse::<Expr>().unwrap_err();
+        assert!(matches!(err[0].reason, ParseErrorReason::TrailingInput));
+        let err = "{ int a = ; }".parse::<Statement>().unwrap_err();
+        assert!(matches!(
+            err[0].reason,
+            ParseErrorReason::NonAtomicExpression
+        ));
+    }
+
+    #[test]
+    fn parse_value_exprs() {
+        let block: Expr = "{ int t = 1; f(t); t + 1 }".parse().unwrap();
+        let ExprKind::Block(b) = &block.kind else {
+            panic!("expected a block");
+        };
+        assert_eq!(b.stmts.len(), 2);
+        assert!(matches!(
+            b.value.as_deref().map(|e| &e.kind),
+            Some(ExprKind::Binop(_))
+        ));
+
+        let ternary: Expr = "a || b ? 1 : c ? 2 : 3".parse().unwrap();
+        let ExprKind::If(i) = &ternary.kind else {
+            panic!("expected a conditional");
+        };
+        assert!(matches!(&i.guard.kind, ExprKind::Binop(_)));
+        assert!(matches!(&i.f.kind, ExprKind::If(_)));
+
+        let chain: Expr = "if a { 1 } else if b { 2 } else { 3 }".parse().unwrap();
+        assert!(matches!(&chain.kind, ExprKind::If(i) if matches!(&i.f.kind, ExprKind::If(_))));
+        assert!("if a { 1 }".parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn parse_nested_tails() {
+        // a block, if or match without a ; before } is the value of the block
+        let value = |src: &str| {
+            let block: Expr = src.parse().unwrap();
+            let ExprKind::Block(b) = block.kind else {
+                panic!("expected a block");
+            };
+            b.value.map(|v| v.kind)
+        };
+        assert!(matches!(value("{ { 1 } }"), Some(ExprKind::Block(_))));
+        assert!(matches!(
+            value("{ int t = 1; if t > 0 { t } else { 0 } }"),
+            Some(ExprKind::If(_))
+        ));
+        assert!(matches!(
+            value("{ match 1 { _ => 7 } }"),
+            Some(ExprKind::Match(_))
+        ));
+        let nested: Expr = "if true { if false { 1 } else { 2 } } else { 3 }"
+            .parse()
+            .unwrap();
+        let ExprKind::If(i) = &nested.kind else {
+            panic!("expected a conditional");
+        };
+        assert!(matches!(&i.t.kind, ExprKind::Block(b)
+            if matches!(b.value.as_deref().map(|v| &v.kind), Some(ExprKind::If(_)))));
+        assert!(
+            "if true { match 1 { _ => 7 } } else { 3 }"
+                .parse::<Expr>()
+                .is_ok()
+        );
+
+        // with a ; they are statements, and an if may leave out its else
+        let block: Expr = "{ { f(1); }; if a { f(2); }; match x { _ => { f(3); } }; 4 }"
+            .parse()
+            .unwrap();
+        let ExprKind::Block(b) = &block.kind else {
+            panic!("expected a block");
+        };
+        let kinds: Vec<_> = b.stmts.iter().map(|s| &s.kind).collect();
+        assert!(matches!(
+            kinds[..],
+            [
+                StatementKind::Block(_),
+                StatementKind::If(_),
+                StatementKind::Match(_)
+            ]
+        ));
+        assert!("{ if a { 1 } }".parse::<Expr>().is_err());
+        assert!("{ if a { 1 }; }".parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn parse_structs() {
+        let source = "
+            struct Point { int x; float y; };
+            Point p = Point { x: 1, y: 2.0 };
+            p.x = f(p).x + 1;
+            if p.x == 2 { p.y += 1.0; };
+            if p == (Point { x: 1, y: 2.0 }) { };
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let labels: Vec<String> = stmts.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "struct <Point { int x, float y }>",
+                "declare<Point p>",
+                "assign<p.x>",
+                "if",
+                "if",
+            ]
+        );
+        let StatementKind::Assign(a) = &stmts[2].kind else {
+            panic!("expected an assignment");
+        };
+        let ExprKind::Binop(sum) = &a.value.kind else {
+            panic!("expected a sum");
+        };
+        assert!(
+            matches!(&sum.a.kind, ExprKind::Field(f) if matches!(&f.base.kind, ExprKind::Call(_)))
+        );
+
+        let fields: Expr = "Point { x: 1, y: { 2.0 } }.y".parse().unwrap();
+        assert!(
+            matches!(&fields.kind, ExprKind::Field(f) if matches!(&f.base.kind, ExprKind::StructLiteral(s) if s.fields.len() == 2))
+        );
+        assert!("p.x;".parse::<Statement>().is_err());
+    }
+
+    #[test]
+    fn parse_casts() {
+        // as binds tighter than binary operators and looser than unary ones
+        let e: Expr = "-x as u8 as natural + 1".parse().unwrap();
+        let ExprKind::Binop(b) = e.kind else {
+            panic!("expected a binary expression");
+        };
+        let ExprKind::Cast(outer) = &b.a.kind else {
+            panic!("expected a cast");
+        };
+        assert_eq!(outer.typ, LType::Int(IntType::U64));
+        assert!(
+            matches!(&outer.x.kind, ExprKind::Cast(inner) if matches!(&inner.x.kind, ExprKind::Unary(_)))
+        );
+
+        let e: Expr = "2.5f32".parse().unwrap();
+        assert!(matches!(
+            e.kind,
+            ExprKind::FloatIntermediate(2.5, Some(FloatType::F32))
+        ));
+        assert_eq!("real".parse::<LType>().unwrap(), FLOAT);
+        assert!("x as".parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn parse_arrays() {
+        let source = "
+            int[8][2] grid;
+            Point[4] ps = f();
+            grid[1][i + 1] = [1, 2][0];
+            ps[0].x += 1;
+            int y = { ps[0].x };
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let labels: Vec<String> = stmts.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "declare<int[8][2] grid>",
+                "declare<Point[4] ps>",
+                "assign<grid[][]>",
+                "assign<ps[].x +=>",
+                "declare<int y>",
+            ]
+        );
+        assert!(
+            matches!(&stmts[2].kind, StatementKind::Assign(a) if matches!(&a.value.kind, ExprKind::Index(i) if matches!(&i.base.kind, ExprKind::ArrayLiteral(items) if items.len() == 2)))
+        );
+
+        assert_eq!(
+            "int[3]".parse::<LType>().unwrap(),
+            LType::Array(Box::new(INT), 3)
+        );
+        let err = "int[n] a;".parse::<Statement>().unwrap_err();
+        assert!(matches!(
+            err[0].reason,
+            ParseErrorReason::ExpectedArrayLength
+        ));
+    }
+
+    #[test]
+    fn parse_match() {
+        let source = "
+            /// a shape
+            enum Shape { Circle(float), Rect(float, float), Empty, };
+            match s {
+                Shape::Circle(r) if r > 1.0 => { print(r); }
+                Shape::Rect(w, _) => { },
+                _ => { }
+            };
+            float a = match s { Shape::Circle(r) => r, Shape::Empty => { 0.0 } _ => -1.0 };
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let labels: Vec<String> = stmts.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "enum <Shape { Circle(float), Rect(float, float), Empty }>",
+                "match",
+                "declare<float a>",
+            ]
+        );
+        assert!(
+            matches!(&stmts[0].kind, StatementKind::Enum(e) if e.doc.as_deref() == Some("a shape"))
+        );
+        let StatementKind::Match(m) = &stmts[1].kind else {
+            panic!("expected a match");
+        };
+        let arms: Vec<String> = m.arms.iter().map(|a| a.pattern.to_string()).collect();
+        assert_eq!(arms, vec!["Shape::Circle(r)", "Shape::Rect(w, _)", "_"]);
+        assert!(m.arms[0].guard.is_some() && m.arms[0].body.len() == 1);
+        assert!(
+            matches!(&stmts[2].kind, StatementKind::Declare(d) if matches!(d.assign.as_deref().map(|e| &e.kind), Some(ExprKind::Match(m)) if m.arms.len() == 3))
+        );
+
+        let e: SEnum = "enum Unit { A }".parse().unwrap();
+        assert_eq!(e.variants, vec![("A".to_string(), Vec::new())]);
+        assert!(matches!("-1".parse::<Pattern>(), Ok(Pattern::Literal(_))));
+        assert!(matches!(
+            "math::Op::Neg(x)".parse::<Pattern>(),
+            Ok(Pattern::Variant(v)) if v.ident == "math::Op"
+        ));
+        let err = "match x { 1 => 1 2 => 2 }".parse::<Expr>().unwrap_err();
+        assert!(matches!(err[0].reason, ParseErrorReason::ExpectedToken(_)));
+        let err = "match x { a + b => 1 }".parse::<Expr>().unwrap_err();
+        assert!(matches!(err[0].reason, ParseErrorReason::ExpectedToken(_)));
+        let err = "match x { (a) => 1 }".parse::<Expr>().unwrap_err();
+        assert!(matches!(err[0].reason, ParseErrorReason::ExpectedPattern));
+    }
+
+    #[test]
+    fn parse_closures() {
+        let source = "
+            fn(int, int) -> bool less = fn(int a, int b) -> bool { return a < b; };
+            fn apply(fn(int) -> int f, int x) -> int { return f(x); };
+            b.on_click(1);
+            fns[0](2);
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let labels: Vec<String> = stmts.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "declare<fn(int, int) -> bool less>",
+                "fn <apply(fn(int) -> int f, int x) -> int>",
+                "call",
+                "call",
+            ]
+        );
+        assert!(
+            matches!(&stmts[0].kind, StatementKind::Declare(d) if matches!(d.assign.as_deref().map(|e| &e.kind), Some(ExprKind::Lambda(l)) if l.params.len() == 2 && l.body.len() == 1))
+        );
+        assert!(
+            matches!(&stmts[2].kind, StatementKind::Expr(e) if matches!(&e.kind, ExprKind::Apply(a) if matches!(&a.f.kind, ExprKind::Field(_))))
+        );
+
+        // calls chain, and the return type of a function type takes the
+        // array suffix
+        let e: Expr = "make(2)(3)".parse().unwrap();
+        assert!(
+            matches!(&e.kind, ExprKind::Apply(a) if matches!(&a.f.kind, ExprKind::Call(c) if c.ident == "make"))
+        );
+        assert_eq!(
+            "fn(int) -> int[4]".parse::<LType>().unwrap(),
+            LType::Fn(vec![INT], Box::new(LType::Array(Box::new(INT), 4)))
+        );
+        assert_eq!(
+            "fn() -> fn(float) -> float"
+                .parse::<LType>()
+                .unwrap()
+                .to_string(),
+            "fn() -> fn(float) -> float"
+        );
+        let err = "fn(int x) { return x; }".parse::<Expr>().unwrap_err();
+        assert!(matches!(err[0].reason, ParseErrorReason::ExpectedToken(_)));
+    }
+
+    #[test]
+    fn parse_generics() {
+        let source = "
+            fn max<T>(T a, T b) -> T { if a > b { return a; }; return b; };
+            fn apply<T, U>(fn(T) -> U f, T x) -> U { return f(x); };
+            bool less = 1 < 2;
+        ";
+        let stmts = Parser::new(source.chars()).parse_program().unwrap();
+        let labels: Vec<String> = stmts.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "fn <max<T>(T a, T b) -> T>",
+                "fn <apply<T, U>(fn(T) -> U f, T x) -> U>",
+                "declare<bool less>",
+            ]
+        );
+        assert!(matches!(&stmts[1].kind, StatementKind::Function(f) if f.generics == ["T", "U"]));
+
+        let err = Parser::new("fn max<>(int a) -> int { return a; };".chars())
+            .parse_program()
+            .unwrap_err();
+        assert!(matches!(
+            err[0].reason,
+            ParseErrorReason::ExpectedIdentifier
+        ));
+
+        // the second T is the one to blame
+        let source = "fn f<T, T>(T a) -> T { return a; };";
+        let err = Parser::new(source.chars()).parse_program().unwrap_err();
+        assert!(matches!(
+            &err[0].reason,
+            ParseErrorReason::DuplicateTypeParameter(t) if t == "T"
+        ));
+        let span = err[0].token.as_ref().unwrap().info.span;
+        assert_eq!((span.start, span.end), (8, 9));
+    }
 }
@@ -0,0 +1,146 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FileId(pub u32);
+
+// byte range [start, end) in a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub file: FileId,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Span {
+    pub fn new(file: FileId, start: u32, end: u32) -> Self {
+        Self { file, start, end }
+    }
+
+    // smallest span covering both
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            file: self.file,
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    pub fn len(&self) -> u32 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+// 1-based line and column, columns count chars
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+impl fmt::Display for LineCol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+    // byte offset of the first char of every line
+    line_starts: Vec<u32>,
+}
+
+impl SourceFile {
+    fn new(name: String, source: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i as u32 + 1))
+            .collect();
+        Self {
+            name,
+            source,
+            line_starts,
+        }
+    }
+
+    // 0-based index of the line containing offset
+    pub fn line_index(&self, offset: u32) -> usize {
+        self.line_starts.partition_point(|&s| s <= offset) - 1
+    }
+
+    pub fn line_col(&self, offset: u32) -> LineCol {
+        let line = self.line_index(offset);
+        let start = self.line_starts[line] as usize;
+        let end = (offset as usize).min(self.source.len());
+        LineCol {
+            line: line as u32 + 1,
+            col: self.source[start..end].chars().count() as u32 + 1,
+        }
+    }
+
+    // text of a 0-based line without the line break
+    pub fn line(&self, index: usize) -> &str {
+        let start = self.line_starts[index] as usize;
+        let end = self
+            .line_starts
+            .get(index + 1)
+            .map_or(self.source.len(), |&e| e as usize);
+        self.source[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    pub fn snippet(&self, span: Span) -> &str {
+        &self.source[span.start as usize..span.end as usize]
+    }
+}
+
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        self.files.push(SourceFile::new(name.into(), source.into()));
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    pub fn get(&self, file: FileId) -> &SourceFile {
+        &self.files[file.0 as usize]
+    }
+
+    pub fn line_col(&self, file: FileId, offset: u32) -> LineCol {
+        self.get(file).line_col(offset)
+    }
+
+    // "name:line:col" of the start of span
+    pub fn location(&self, span: Span) -> String {
+        let file = self.get(span.file);
+        format!("{}:{}", file.name, file.line_col(span.start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineCol, SourceMap, Span};
+
+    #[test]
+    fn line_col() {
+        let mut map = SourceMap::new();
+        let f = map.add("a.ro", "int a;\nint ü = 1;\n");
+
+        assert_eq!(map.line_col(f, 0), LineCol { line: 1, col: 1 });
+        assert_eq!(map.line_col(f, 7), LineCol { line: 2, col: 1 });
+        // ü is two bytes wide but one column
+        assert_eq!(map.line_col(f, 13), LineCol { line: 2, col: 6 });
+        assert_eq!(map.get(f).line(1), "int ü = 1;");
+        assert_eq!(map.get(f).snippet(Span::new(f, 7, 10)), "int");
+        assert_eq!(map.location(Span::new(f, 11, 13)), "a.ro:2:5");
+    }
+}
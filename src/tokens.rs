@@ -1,74 +1,350 @@
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
+    AMP,
+    AMPAMP,
     ARROW,
+    AS,
     ASTER,
+    ASTEREQUAL,
+    BANG,
+    BANGEQUAL,
+    BOOL,
+    BREAK,
+    CARET,
+    CHAR,
+    CHARLIT(char),
+    COLON,
+    COLONCOLON,
     COMMA,
+    CONTINUE,
+    DO,
+    DOC(String),
+    DOT,
     ELSE,
+    ENUM,
     EOF,
     EQUAL,
     EQUALEQUAL,
+    FALSE,
+    F32,
+    F64,
+    FATARROW,
     FLOAT,
+    FLOATNUMBER(f64, Option<NumSuffix>),
     FN,
+    FOR,
     GT,
+    GTEQUAL,
+    GTGT,
+    I8,
+    I16,
+    I32,
+    I64,
     IDENT(String),
     IF,
-    ILLEGAL,
+    IMPORT,
+    // malformed input, lexing resumes after it
+    ILLEGAL(LexErrorReason),
     INT,
+    LBRACKET,
     LCURL,
+    LOOP,
     LPAREN,
     LT,
+    LTEQUAL,
+    LTLT,
+    MATCH,
     MINUS,
     MINUSEQUAL,
     MINUSMINUS,
-    NUMBER(u32),
+    NATURAL,
+    NUMBER(u64, Option<NumSuffix>),
+    PERCENT,
+    PERCENTEQUAL,
+    PIPE,
+    PIPEPIPE,
     PLUS,
     PLUSEQUAL,
     PLUSPLUS,
+    QUESTION,
+    RBRACKET,
     RCURL,
+    REAL,
     RETURN,
     RPAREN,
     SEMICOLON,
     SLASH,
+    SLASHEQUAL,
+    STRING,
+    STRINGLIT(String),
+    STRUCT,
+    TILDE,
+    TRUE,
+    U8,
+    U16,
+    U32,
+    U64,
     WHILE,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexErrorReason {
+    InvalidCharLiteral,
+    InvalidDigit { digit: char, radix: u32 },
+    InvalidEscape(char),
+    InvalidSuffix(String),
+    InvalidUnicodeEscape,
+    LeadingZero,
+    MissingDigits { radix: u32 },
+    NumberOverflow,
+    UnexpectedChar(char),
+    UnterminatedComment,
+    UnterminatedLiteral,
+}
+
+// type suffix of a number literal, `f` is short for f64
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl NumSuffix {
+    pub fn lookup(s: &str) -> Option<NumSuffix> {
+        Some(match s {
+            "i8" => NumSuffix::I8,
+            "i16" => NumSuffix::I16,
+            "i32" => NumSuffix::I32,
+            "i64" => NumSuffix::I64,
+            "u8" => NumSuffix::U8,
+            "u16" => NumSuffix::U16,
+            "u32" => NumSuffix::U32,
+            "u64" => NumSuffix::U64,
+            "f32" => NumSuffix::F32,
+            "f" | "f64" => NumSuffix::F64,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NumSuffix::I8 => "i8",
+            NumSuffix::I16 => "i16",
+            NumSuffix::I32 => "i32",
+            NumSuffix::I64 => "i64",
+            NumSuffix::U8 => "u8",
+            NumSuffix::U16 => "u16",
+            NumSuffix::U32 => "u32",
+            NumSuffix::U64 => "u64",
+            NumSuffix::F32 => "f32",
+            NumSuffix::F64 => "f64",
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, NumSuffix::F32 | NumSuffix::F64)
+    }
+
+    // largest literal an integer suffix accepts, signed types allow one
+    // past their max so the literal can be negated to their min
+    pub fn max_magnitude(&self) -> Option<u64> {
+        Some(match self {
+            NumSuffix::I8 => 1 << 7,
+            NumSuffix::I16 => 1 << 15,
+            NumSuffix::I32 => 1 << 31,
+            NumSuffix::I64 => 1 << 63,
+            NumSuffix::U8 => u8::MAX.into(),
+            NumSuffix::U16 => u16::MAX.into(),
+            NumSuffix::U32 => u32::MAX.into(),
+            NumSuffix::U64 => u64::MAX,
+            NumSuffix::F32 | NumSuffix::F64 => return None,
+        })
+    }
+}
+
 pub struct OpInfo {
     pub prec: u32,
     pub l_assoc: bool,
 }
 
 impl Token {
+    // precedence follows C, higher binds tighter
     pub fn get_op_info(&self) -> Option<OpInfo> {
+        let prec = match self {
+            Token::PIPEPIPE => 0,
+            Token::AMPAMP => 1,
+            Token::PIPE => 2,
+            Token::CARET => 3,
+            Token::AMP => 4,
+            Token::EQUALEQUAL | Token::BANGEQUAL => 5,
+            Token::LT | Token::GT | Token::LTEQUAL | Token::GTEQUAL => 6,
+            Token::LTLT | Token::GTGT => 7,
+            Token::PLUS | Token::MINUS => 8,
+            Token::ASTER | Token::SLASH | Token::PERCENT => 9,
+            _ => return None,
+        };
+        Some(OpInfo {
+            prec,
+            l_assoc: true,
+        })
+    }
+
+    // source spelling of tokens without a payload
+    pub fn text(&self) -> Option<&'static str> {
         Some(match self {
-            Token::PLUS => OpInfo {
-                prec: 2,
-                l_assoc: true,
-            },
-            Token::MINUS => OpInfo {
-                prec: 2,
-                l_assoc: true,
-            },
-            Token::ASTER => OpInfo {
-                prec: 3,
-                l_assoc: true,
-            },
-            Token::SLASH => OpInfo {
-                prec: 3,
-                l_assoc: true,
-            },
-            Token::LT => OpInfo {
-                prec: 1,
-                l_assoc: true,
-            },
-            Token::GT => OpInfo {
-                prec: 1,
-                l_assoc: true,
-            },
-            Token::EQUALEQUAL => OpInfo {
-                prec: 0,
-                l_assoc: false,
-            },
+            Token::AMP => "&",
+            Token::AMPAMP => "&&",
+            Token::ARROW => "->",
+            Token::AS => "as",
+            Token::ASTER => "*",
+            Token::ASTEREQUAL => "*=",
+            Token::BANG => "!",
+            Token::BANGEQUAL => "!=",
+            Token::BOOL => "bool",
+            Token::BREAK => "break",
+            Token::CARET => "^",
+            Token::CHAR => "char",
+            Token::COLON => ":",
+            Token::COLONCOLON => "::",
+            Token::COMMA => ",",
+            Token::CONTINUE => "continue",
+            Token::DO => "do",
+            Token::DOT => ".",
+            Token::ELSE => "else",
+            Token::ENUM => "enum",
+            Token::EQUAL => "=",
+            Token::EQUALEQUAL => "==",
+            Token::FALSE => "false",
+            Token::F32 => "f32",
+            Token::F64 => "f64",
+            Token::FATARROW => "=>",
+            Token::FLOAT => "float",
+            Token::FN => "fn",
+            Token::FOR => "for",
+            Token::GT => ">",
+            Token::GTEQUAL => ">=",
+            Token::GTGT => ">>",
+            Token::I8 => "i8",
+            Token::I16 => "i16",
+            Token::I32 => "i32",
+            Token::I64 => "i64",
+            Token::IF => "if",
+            Token::IMPORT => "import",
+            Token::INT => "int",
+            Token::LBRACKET => "[",
+            Token::LCURL => "{",
+            Token::LOOP => "loop",
+            Token::LPAREN => "(",
+            Token::LT => "<",
+            Token::LTEQUAL => "<=",
+            Token::LTLT => "<<",
+            Token::MATCH => "match",
+            Token::MINUS => "-",
+            Token::MINUSEQUAL => "-=",
+            Token::MINUSMINUS => "--",
+            Token::NATURAL => "natural",
+            Token::PERCENT => "%",
+            Token::PERCENTEQUAL => "%=",
+            Token::PIPE => "|",
+            Token::PIPEPIPE => "||",
+            Token::PLUS => "+",
+            Token::PLUSEQUAL => "+=",
+            Token::PLUSPLUS => "++",
+            Token::QUESTION => "?",
+            Token::RBRACKET => "]",
+            Token::RCURL => "}",
+            Token::REAL => "real",
+            Token::RETURN => "return",
+            Token::RPAREN => ")",
+            Token::SEMICOLON => ";",
+            Token::SLASH => "/",
+            Token::SLASHEQUAL => "/=",
+            Token::STRING => "string",
+            Token::STRUCT => "struct",
+            Token::TILDE => "~",
+            Token::TRUE => "true",
+            Token::U8 => "u8",
+            Token::U16 => "u16",
+            Token::U32 => "u32",
+            Token::U64 => "u64",
+            Token::WHILE => "while",
             _ => return None,
         })
     }
+
+    pub fn is_keyword(&self) -> bool {
+        self.text()
+            .is_some_and(|t| t.starts_with(char::is_alphabetic))
+    }
+}
+
+// describes the token for diagnostics, e.g. identifier `b`
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(text) = self.text() {
+            if self.is_keyword() {
+                return write!(f, "keyword `{text}`");
+            }
+            return write!(f, "`{text}`");
+        }
+        match self {
+            Token::CHARLIT(c) => write!(f, "char literal {c:?}"),
+            Token::DOC(_) => write!(f, "doc comment"),
+            Token::EOF => write!(f, "end of file"),
+            Token::FLOATNUMBER(x, suffix) => {
+                write!(
+                    f,
+                    "float literal `{x:?}{}`",
+                    suffix.map_or("", |s| s.name())
+                )
+            }
+            Token::IDENT(s) => write!(f, "identifier `{s}`"),
+            Token::ILLEGAL(_) => write!(f, "invalid token"),
+            Token::NUMBER(n, suffix) => {
+                write!(
+                    f,
+                    "integer literal `{n}{}`",
+                    suffix.map_or("", |s| s.name())
+                )
+            }
+            Token::STRINGLIT(s) => write!(f, "string literal {s:?}"),
+            _ => write!(f, "{self:?}"),
+        }
+    }
+}
+
+impl fmt::Display for LexErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexErrorReason::InvalidCharLiteral => {
+                write!(f, "char literal must hold exactly one char")
+            }
+            LexErrorReason::InvalidDigit { digit, radix } => {
+                write!(f, "invalid digit {digit:?} in a base {radix} literal")
+            }
+            LexErrorReason::InvalidEscape(c) => write!(f, "unknown escape `\\{c}`"),
+            LexErrorReason::InvalidSuffix(s) => write!(f, "invalid literal suffix `{s}`"),
+            LexErrorReason::InvalidUnicodeEscape => write!(f, "invalid unicode escape"),
+            LexErrorReason::LeadingZero => {
+                write!(f, "decimal literals cannot have leading zeros")
+            }
+            LexErrorReason::MissingDigits { radix } => {
+                write!(f, "expected base {radix} digits")
+            }
+            LexErrorReason::NumberOverflow => write!(f, "integer literal is too large"),
+            LexErrorReason::UnexpectedChar(c) => write!(f, "unexpected character {c:?}"),
+            LexErrorReason::UnterminatedComment => write!(f, "unterminated block comment"),
+            LexErrorReason::UnterminatedLiteral => write!(f, "unterminated literal"),
+        }
+    }
 }